@@ -41,6 +41,10 @@ impl<K: Eq + PartialEq + Hash, V: Default, C: NetClock> AgedUnorderedMap<K, V, C
         self.inner.get(k)
     }
 
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        self.inner.retain(f)
+    }
+
     pub fn touch(&self, _k: &K) {
         // TODO: Update the timestamp on the entry
     }
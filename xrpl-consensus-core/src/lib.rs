@@ -1,10 +1,17 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
 pub mod aged_unordered_map;
+pub mod node_id;
 
-use std::fmt::{Debug, Display};
-use std::hash::Hash;
+use core::fmt::{Debug, Display};
+use core::hash::Hash;
+#[cfg(feature = "std")]
 use std::time::SystemTime;
 use serde::Serialize;
 
+pub use node_id::NodeId;
+
 pub type LedgerIndex = u32;
 
 pub trait Ledger: Clone + Debug + Display {
@@ -21,15 +28,132 @@ pub trait Ledger: Clone + Debug + Display {
     fn mismatch(&self, other: &Self) -> LedgerIndex;
 }
 
+/// The subset of [`Ledger`] needed to track the ancestry of a ledger.
+///
+/// Ledger ancestry tries only need to know a ledger's ID, sequence number and the IDs of its
+/// ancestors, so lightweight ledger headers can implement this directly instead of [`Ledger`].
+/// Every [`Ledger`] is a `SpannableLedger`.
+pub trait SpannableLedger: Clone + Debug {
+    type IdType: LedgerId;
+
+    fn id(&self) -> Self::IdType;
+
+    fn seq(&self) -> LedgerIndex;
+
+    /// Return the ID of this ledger's ancestor with the given sequence number, which must be
+    /// less than or equal to this ledger's sequence number.
+    ///
+    /// For a sequence number this ledger does not know the ancestor of, see
+    /// [`SpannableLedger::known_ancestor_seq`], any ID may be returned.
+    fn get_ancestor(&self, seq: LedgerIndex) -> Self::IdType;
+
+    fn make_genesis() -> Self;
+
+    /// Return the largest sequence number, no larger than `seq`, whose ancestor ID this ledger
+    /// knows.
+    ///
+    /// Ledgers that only know their ancestry at sparse checkpoints, such as those of light
+    /// clients, override this. The genesis ledger and the ledger itself must always be known.
+    /// The default implementation knows every ancestor.
+    fn known_ancestor_seq(&self, seq: LedgerIndex) -> LedgerIndex {
+        seq
+    }
+
+    /// Return the sequence number of the first ancestor that differs between this ledger and
+    /// `other`.
+    ///
+    /// The default implementation binary searches the ancestry of both ledgers, relying on
+    /// the unique history of ledgers. Only ancestors both ledgers know are compared, so
+    /// sequence numbers either ledger doesn't know are treated as matching until the first
+    /// known ancestor that differs.
+    fn mismatch(&self, other: &Self) -> LedgerIndex {
+        // The ancestor both ledgers know with the largest sequence number no larger than `seq`
+        let known = |seq: LedgerIndex| {
+            let mut known = seq;
+            loop {
+                let (a, b) = (self.known_ancestor_seq(known), other.known_ancestor_seq(known));
+                if a == b {
+                    return a;
+                }
+                known = core::cmp::min(a, b);
+            }
+        };
+
+        first_mismatch(core::cmp::min(self.seq(), other.seq()), |seq| {
+            let known = known(seq);
+            self.get_ancestor(known) == other.get_ancestor(known)
+        })
+    }
+
+    /// Return the ID of this ledger's parent, or `None` for the genesis ledger.
+    fn parent_id(&self) -> Option<Self::IdType> {
+        if self.seq() == 0 {
+            return None;
+        }
+
+        Some(self.get_ancestor(self.seq() - 1))
+    }
+}
+
+impl<T: Ledger> SpannableLedger for T {
+    type IdType = <T as Ledger>::IdType;
+
+    fn id(&self) -> Self::IdType {
+        Ledger::id(self)
+    }
+
+    fn seq(&self) -> LedgerIndex {
+        Ledger::seq(self)
+    }
+
+    fn get_ancestor(&self, seq: LedgerIndex) -> Self::IdType {
+        Ledger::get_ancestor(self, seq)
+    }
+
+    fn make_genesis() -> Self {
+        <T as Ledger>::make_genesis()
+    }
+
+    fn mismatch(&self, other: &Self) -> LedgerIndex {
+        Ledger::mismatch(self, other)
+    }
+}
+
+/// Return the first sequence number no larger than `last` for which `matches` is false, or
+/// `last + 1` if there is none, by binary search.
+///
+/// `matches` must be true for every sequence number before the first mismatch and false for
+/// every one after, as when comparing the ancestry of two ledgers with a unique history.
+pub fn first_mismatch(last: LedgerIndex, matches: impl Fn(LedgerIndex) -> bool) -> LedgerIndex {
+    let mut start = 0;
+    let mut count = last + 1;
+    while count > 0 {
+        let step = count / 2;
+        let curr = start + step;
+        if matches(curr) {
+            start = curr + 1;
+            count -= step + 1;
+        } else {
+            count = step;
+        }
+    }
+
+    start
+}
+
 pub trait LedgerId: Eq + PartialEq + Ord + PartialOrd + Copy + Clone + Hash + Serialize + Debug + Display {
 
 }
 
+#[cfg(feature = "std")]
 pub trait Validation: Copy + Clone {
     type LedgerIdType: LedgerId;
+    type NodeIdType: Eq + Hash + Copy;
 
     fn seq(&self) -> LedgerIndex;
     fn ledger_id(&self) -> Self::LedgerIdType;
+    /// Return the identity of the node that signed this validation.
+    fn node_id(&self) -> Self::NodeIdType;
     fn sign_time(&self) -> SystemTime;
     fn seen_time(&self) -> SystemTime;
     fn cookie(&self) -> u64;
@@ -38,12 +162,15 @@ pub trait Validation: Copy + Clone {
     fn load_fee(&self) -> Option<u32>;
 }
 
+#[cfg(feature = "std")]
 pub trait NetClock {
     fn now(&self) -> SystemTime;
 }
 
+#[cfg(feature = "std")]
 pub struct WallNetClock;
 
+#[cfg(feature = "std")]
 impl NetClock for WallNetClock {
     fn now(&self) -> SystemTime {
         SystemTime::now()
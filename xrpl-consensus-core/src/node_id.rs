@@ -0,0 +1,73 @@
+use core::fmt::{Debug, Display, Formatter};
+
+/// The identity of a validator, which is the 160 bit hash of its master public key.
+///
+/// Displays as upper case hex, like rippled's `NodeID`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct NodeId([u8; NodeId::LEN]);
+
+impl NodeId {
+    /// The number of bytes in a `NodeId`.
+    pub const LEN: usize = 20;
+
+    pub const fn from_bytes(bytes: [u8; NodeId::LEN]) -> Self {
+        NodeId(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; NodeId::LEN] {
+        &self.0
+    }
+}
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "NodeId({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::node_id::NodeId;
+
+    fn node_id(last: u8) -> NodeId {
+        let mut bytes = [0; NodeId::LEN];
+        bytes[0] = 0xab;
+        bytes[NodeId::LEN - 1] = last;
+        NodeId::from_bytes(bytes)
+    }
+
+    #[test]
+    fn test_eq() {
+        assert_eq!(node_id(1), node_id(1));
+        assert_ne!(node_id(1), node_id(2));
+        assert!(node_id(1) < node_id(2));
+        assert_eq!(node_id(1).as_bytes()[NodeId::LEN - 1], 1);
+        assert_eq!(NodeId::from_bytes(*node_id(3).as_bytes()), node_id(3));
+    }
+
+    #[test]
+    fn test_hash() {
+        let ids = HashSet::from([node_id(1), node_id(2), node_id(1)]);
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&node_id(2)));
+        assert!(!ids.contains(&node_id(3)));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(node_id(0x0f).to_string(), "AB0000000000000000000000000000000000000F");
+        assert_eq!(format!("{:?}", node_id(0x0f)), "NodeId(AB0000000000000000000000000000000000000F)");
+        assert_eq!(NodeId::default().to_string(), "0".repeat(2 * NodeId::LEN));
+    }
+}
@@ -0,0 +1,355 @@
+//! Benchmarks for [`ArenaLedgerTrie`] on simulated ledger histories.
+//!
+//! Run with `cargo bench -p xrpl-consensus-validations --features test-utils`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use xrpl_consensus_core::Ledger;
+use xrpl_consensus_validations::arena_ledger_trie::ArenaLedgerTrie;
+use xrpl_consensus_validations::ledger_trie::LedgerTrie;
+use xrpl_consensus_validations::test_utils::Span;
+use xrpl_consensus_validations::test_utils::ledgers::{LedgerOracle, SimulatedLedger, Tx};
+
+/// Counts every allocation made by the benchmark process.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// A criterion measurement of the number of allocations a benchmark makes, instead of the
+/// time it takes.
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocationsFormatter
+    }
+}
+
+struct AllocationsFormatter;
+
+impl ValueFormatter for AllocationsFormatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(&self, _typical_value: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        let per = match *throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) | Throughput::Elements(n) => n as f64,
+        };
+        for value in values {
+            *value /= per;
+        }
+        "allocs/elem"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+/// The shape of a simulated ledger history: `branches` chains of `branch_len` ledgers, each
+/// forking from the end of a shared chain of `trunk` ledgers after genesis.
+#[derive(Copy, Clone)]
+struct Shape {
+    name: &'static str,
+    trunk: u32,
+    branches: u32,
+    branch_len: u32,
+}
+
+const DEEP_CHAIN: Shape = Shape { name: "deep_chain", trunk: 1000, branches: 1, branch_len: 0 };
+const WIDE_FORK: Shape = Shape { name: "wide_fork", trunk: 16, branches: 256, branch_len: 4 };
+
+/// Every ledger of a simulated history, in the order they were created.
+struct History {
+    oracle: LedgerOracle,
+    next_tx: u32,
+    ledgers: Vec<SimulatedLedger>,
+    tips: Vec<SimulatedLedger>,
+}
+
+impl History {
+    fn new(shape: Shape) -> Self {
+        let mut history = History {
+            oracle: LedgerOracle::new(),
+            next_tx: 0,
+            ledgers: vec![],
+            tips: vec![],
+        };
+
+        let trunk = history.extend(&SimulatedLedger::genesis(), shape.trunk);
+        for _ in 0..shape.branches {
+            let tip = history.extend(&trunk, shape.branch_len);
+            history.tips.push(tip);
+        }
+        history
+    }
+
+    /// Add a chain of `len` ledgers after `parent` and return its last ledger.
+    fn extend(&mut self, parent: &SimulatedLedger, len: u32) -> SimulatedLedger {
+        let mut curr = parent.clone();
+        for _ in 0..len {
+            self.next_tx += 1;
+            curr = (*self.oracle.accept("", &curr, Tx::new(self.next_tx))).clone();
+            self.ledgers.push(curr.clone());
+        }
+        curr
+    }
+
+    /// A trie with one validation for each tip.
+    fn trie(&self) -> ArenaLedgerTrie<SimulatedLedger> {
+        let mut trie = ArenaLedgerTrie::new();
+        for tip in &self.tips {
+            trie.insert(tip, None);
+        }
+        trie
+    }
+
+    /// The largest sequence number in the history.
+    fn largest_issued(&self) -> u32 {
+        self.ledgers.iter().map(|ledger| ledger.seq()).max().unwrap_or(0)
+    }
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+
+    // Each ledger of a long chain in turn, as validations arrive along it
+    let chain = History::new(DEEP_CHAIN);
+    group.throughput(Throughput::Elements(chain.ledgers.len() as u64));
+    group.bench_function(DEEP_CHAIN.name, |b| {
+        b.iter(|| {
+            let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+            for ledger in &chain.ledgers {
+                trie.insert(ledger, None);
+            }
+            trie
+        })
+    });
+
+    // Many validators each on their own branch
+    let fork = History::new(WIDE_FORK);
+    group.throughput(Throughput::Elements(fork.tips.len() as u64));
+    group.bench_function(WIDE_FORK.name, |b| b.iter(|| fork.trie()));
+
+    // Validators following the chain together, each moving its validation from the previous
+    // ledger to the next, where only the first to move extends the last inserted tip
+    const VALIDATORS: usize = 8;
+    let mut start: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+    start.insert(&chain.ledgers[0], Some(VALIDATORS as u32));
+    group.throughput(Throughput::Elements(((chain.ledgers.len() - 1) * VALIDATORS) as u64));
+    group.bench_function("steady_state", |b| {
+        b.iter_batched(
+            || start.clone(),
+            |mut trie| {
+                for ledgers in chain.ledgers.windows(2) {
+                    for _ in 0..VALIDATORS {
+                        trie.remove(&ledgers[0], None).unwrap();
+                        trie.insert(&ledgers[1], None);
+                    }
+                }
+                trie
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_get_preferred(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_preferred");
+    for shape in [DEEP_CHAIN, WIDE_FORK] {
+        let history = History::new(shape);
+        let trie = history.trie();
+        let largest_issued = history.largest_issued();
+        group.bench_function(shape.name, |b| {
+            b.iter(|| trie.get_preferred(black_box(largest_issued)))
+        });
+    }
+    group.finish();
+}
+
+/// Looking up the support of the last tip of a wide fork, by searching the trie for its ID,
+/// by following its ancestry from the root, and for an ancestor whose sequence number lets
+/// the search skip the deeper subtrees.
+fn bench_find(c: &mut Criterion) {
+    let history = History::new(WIDE_FORK);
+    let trie = history.trie();
+    let last = history.tips.last().unwrap();
+    let first = &history.ledgers[history.ledgers.len() - WIDE_FORK.branch_len as usize];
+    let tip = Span::from(last.clone()).tip();
+
+    let mut group = c.benchmark_group("find");
+    group.bench_function("search", |b| b.iter(|| trie.branch_support(black_box(last))));
+    group.bench_function("follow", |b| b.iter(|| trie.branch_support_of_tip(black_box(&tip))));
+    group.bench_function("pruned", |b| b.iter(|| trie.tip_support(black_box(first))));
+    group.finish();
+}
+
+fn bench_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+    for shape in [DEEP_CHAIN, WIDE_FORK] {
+        let history = History::new(shape);
+        let trie = history.trie();
+        group.throughput(Throughput::Elements(history.tips.len() as u64));
+        group.bench_function(shape.name, |b| {
+            b.iter_batched(
+                || trie.clone(),
+                |mut trie| {
+                    for tip in &history.tips {
+                        trie.remove(tip, None).unwrap();
+                    }
+                    trie
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Each validator on a wide fork moves to the next ledger of its branch, and the preferred
+/// ledger is recomputed after every round.
+fn bench_mixed_churn(c: &mut Criterion) {
+    const ROUNDS: usize = 8;
+
+    let mut history = History::new(WIDE_FORK);
+    let trie = history.trie();
+    let mut rounds = vec![history.tips.clone()];
+    for _ in 0..ROUNDS {
+        let next: Vec<SimulatedLedger> = rounds.last().unwrap().clone().iter()
+            .map(|tip| history.extend(tip, 1))
+            .collect();
+        rounds.push(next);
+    }
+    let largest_issued = history.largest_issued();
+
+    let mut group = c.benchmark_group("mixed_churn");
+    group.throughput(Throughput::Elements((ROUNDS * history.tips.len()) as u64));
+    group.bench_function(WIDE_FORK.name, |b| {
+        b.iter_batched(
+            || trie.clone(),
+            |mut trie| {
+                for round in rounds.windows(2) {
+                    for (prev, next) in round[0].iter().zip(&round[1]) {
+                        trie.remove(prev, None).unwrap();
+                        trie.insert(next, None);
+                    }
+                    black_box(trie.get_preferred(largest_issued));
+                }
+                trie
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// Support a chain's tip and then ten of its ancestors, splitting the chain's node each time.
+/// Spans share their ledger instead of copying its ancestry, so the allocations should depend
+/// on how many nodes the trie has but not on how long the chain is.
+fn bench_long_chain_allocations(c: &mut Criterion<Allocations>) {
+    let mut group = c.benchmark_group("long_chain_allocations");
+    for trunk in [100, 1000] {
+        let chain = History::new(Shape { name: "", trunk, branches: 1, branch_len: 0 });
+        let tip = chain.tips[0].clone();
+        let ancestors: Vec<SimulatedLedger> = (1..=10)
+            .map(|i| chain.ledgers[i * chain.ledgers.len() / 11].clone())
+            .collect();
+        let mut start: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+        start.insert(&tip, None);
+
+        group.bench_function(BenchmarkId::from_parameter(trunk), |b| {
+            b.iter_batched(
+                || start.clone(),
+                |mut trie| {
+                    for ancestor in &ancestors {
+                        trie.insert(ancestor, None);
+                    }
+                    trie
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Validators on a wide fork repeatedly moving to the next ledger of their branch and back,
+/// where the nodes that each move frees are reused by the next.
+fn bench_churn_allocations(c: &mut Criterion<Allocations>) {
+    let mut history = History::new(WIDE_FORK);
+    let trie = history.trie();
+    let next: Vec<SimulatedLedger> = history.tips.clone().iter()
+        .map(|tip| history.extend(tip, 1))
+        .collect();
+
+    let mut group = c.benchmark_group("churn_allocations");
+    group.throughput(Throughput::Elements(2 * history.tips.len() as u64));
+    group.bench_function(WIDE_FORK.name, |b| {
+        b.iter_batched(
+            || trie.clone(),
+            |mut trie| {
+                for (prev, next) in history.tips.iter().zip(&next) {
+                    trie.remove(prev, None).unwrap();
+                    trie.insert(next, None);
+                }
+                for (prev, next) in history.tips.iter().zip(&next) {
+                    trie.remove(next, None).unwrap();
+                    trie.insert(prev, None);
+                }
+                trie
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert, bench_get_preferred, bench_find, bench_remove, bench_mixed_churn);
+criterion_group! {
+    name = allocations;
+    config = Criterion::default().with_measurement(Allocations).without_plots();
+    targets = bench_long_chain_allocations, bench_churn_allocations
+}
+criterion_main!(benches, allocations);
@@ -5,7 +5,7 @@ use xrpl_consensus_core::{Ledger, LedgerId, NetClock, Validation};
 
 #[async_trait]
 pub trait Adaptor {
-    type ValidationType: Validation<LedgerIdType = Self::LedgerIdType>;
+    type ValidationType: Validation<LedgerIdType = Self::LedgerIdType, NodeIdType = Self::NodeIdType>;
     type LedgerType: Ledger<IdType = Self::LedgerIdType>;
     type LedgerIdType: LedgerId;
     type NodeIdType: Eq + PartialEq + Hash + Copy + Clone;
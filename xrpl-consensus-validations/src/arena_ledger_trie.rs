@@ -1,34 +1,63 @@
-use std::cmp::Ordering;
-use std::collections::BTreeMap;
-use std::collections::btree_map::Entry;
+use core::cmp::{Ordering, Reverse};
+use core::fmt::{Debug, Formatter, LowerHex};
+#[cfg(feature = "serde")]
+use core::fmt::Display;
+use core::hash::{BuildHasher, BuildHasherDefault, Hash};
+
+use alloc::collections::BTreeMap;
+use alloc::collections::btree_map::Entry;
+#[cfg(feature = "serde")]
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 
 use generational_arena::{Arena, Index};
+use rustc_hash::FxHasher;
+#[cfg(feature = "serde")]
 use serde::{Serialize, Serializer};
+#[cfg(feature = "serde")]
 use serde::ser::SerializeStruct;
 
-use xrpl_consensus_core::{Ledger, LedgerIndex};
+use xrpl_consensus_core::{LedgerIndex, NodeId, SpannableLedger};
 
-use crate::ledger_trie::LedgerTrie;
+use crate::ledger_trie::{LedgerTrie, LedgerTrieExt, Support, TrieError};
 use crate::span::{Span, SpanTip};
 
-pub struct Node<T: Ledger> {
+/// The most emptied `children` a trie keeps for reuse. Enough for the nodes that insert/remove
+/// churn creates and frees between preferred ledgers, without holding on to the memory of a
+/// trie that has shrunk from a large fork.
+const MAX_FREE_CHILDREN: usize = 64;
+
+#[derive(Clone)]
+pub struct Node<T: SpannableLedger, S: Support = u32> {
     idx: Index,
     span: Span<T>,
-    tip_support: u32,
-    branch_support: u32,
+    tip_support: S,
+    /// Support from partial validations of this node's tip, which counts towards
+    /// `branch_support` but not `tip_support`.
+    partial_support: S,
+    branch_support: S,
     children: Vec<Index>,
     parent: Option<Index>,
+    /// The most nodes on any path down from this node, not counting itself.
+    height: usize,
 }
 
-impl<T: Ledger> Node<T> {
+impl<T: SpannableLedger, S: Support> Node<T, S> {
     pub fn new(idx: Index, ledger: T) -> Self {
         Node {
             idx,
             span: Span::from(ledger),
-            tip_support: 1,
-            branch_support: 1,
+            tip_support: S::one(),
+            partial_support: S::zero(),
+            branch_support: S::one(),
             children: vec![],
             parent: None,
+            height: 0,
         }
     }
 
@@ -36,10 +65,12 @@ impl<T: Ledger> Node<T> {
         Node {
             idx,
             span: Span::default(),
-            tip_support: 0,
-            branch_support: 0,
+            tip_support: S::zero(),
+            partial_support: S::zero(),
+            branch_support: S::zero(),
             children: vec![],
             parent: None,
+            height: 0,
         }
     }
 
@@ -47,47 +78,485 @@ impl<T: Ledger> Node<T> {
         Node {
             idx,
             span,
-            tip_support: 0,
-            branch_support: 0,
+            tip_support: S::zero(),
+            partial_support: S::zero(),
+            branch_support: S::zero(),
             children: vec![],
             parent: None,
+            height: 0,
         }
     }
 
+    /// The tip support if `full`, otherwise the partial support, of this node.
+    fn support(&self, full: bool) -> S {
+        if full { self.tip_support } else { self.partial_support }
+    }
+
+    fn support_mut(&mut self, full: bool) -> &mut S {
+        if full { &mut self.tip_support } else { &mut self.partial_support }
+    }
+
     pub fn erase(&mut self, child: Index) {
         self.children.swap_remove(self.children.iter().position(|c| *c == child).unwrap());
     }
 }
 
+/// Accessors for white-box tests of a trie's nodes, including tests in other crates with the
+/// `test-utils` feature. Nothing keeps a node changed through these consistent with the rest
+/// of the trie, see [`ArenaLedgerTrie::check_invariants`].
+#[cfg(any(test, feature = "test-utils"))]
+impl<T: SpannableLedger, S: Support> Node<T, S> {
+    pub fn tip_support(&self) -> S {
+        self.tip_support
+    }
+
+    pub fn set_tip_support(&mut self, tip_support: S) {
+        self.tip_support = tip_support;
+    }
+
+    pub fn branch_support(&self) -> S {
+        self.branch_support
+    }
+
+    pub fn set_branch_support(&mut self, branch_support: S) {
+        self.branch_support = branch_support;
+    }
+
+    pub fn span(&self) -> &Span<T> {
+        &self.span
+    }
+
+    pub fn set_span(&mut self, span: Span<T>) {
+        self.span = span;
+    }
+
+    pub fn children(&self) -> &[Index] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<Index> {
+        &mut self.children
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height;
+    }
+
+    pub fn parent(&self) -> Option<Index> {
+        self.parent
+    }
+
+    pub fn set_parent(&mut self, parent: Option<Index>) {
+        self.parent = parent;
+    }
+}
+
 /// A `LedgerTrie` implemented using a generational arena.
-pub struct ArenaLedgerTrie<T: Ledger> {
+///
+/// Support is counted with `S`, which can be widened from the default `u32` for networks with
+/// more support than a `u32` can count.
+///
+/// Maps keyed on `T::IdType` hash with `H`. Ledger IDs are usually already hashes, so the
+/// default is a fast non-cryptographic hasher.
+///
+/// Nodes live in the arena at an `Index` whose slot may hold another node once the node is
+/// removed, and a node may cover a different span after an insert splits it or a remove
+/// compresses it. Callers referring to a node over time should hold a [`NodeHandle`] instead.
+#[derive(Clone)]
+pub struct ArenaLedgerTrie<
+    T: SpannableLedger,
+    S: Support = u32,
+    H: BuildHasher + Clone = BuildHasherDefault<FxHasher>,
+> {
     root: Index,
-    arena: Arena<Node<T>>,
-    seq_support: BTreeMap<LedgerIndex, u32>, // Needs to be ordered
+    arena: Arena<Node<T, S>>,
+    seq_support: BTreeMap<LedgerIndex, S>, // Needs to be ordered
+    hasher: H,
+    /// The emptied `children` of removed nodes, which are reused by new nodes rather than
+    /// allocating their own.
+    free_children: Vec<Vec<Index>>,
+    /// Incremented whenever the support in the trie changes.
+    version: u64,
+    /// The preferred ledger found by `get_preferred_cached`, with the version and largest
+    /// issued sequence number it was found for.
+    preferred_cache: Option<(u64, LedgerIndex, Option<SpanTip<T>>)>,
+    /// Whether to reject ledgers whose ancestry contradicts the trie, see
+    /// `with_strict_ancestry`.
+    strict_ancestry: bool,
+    /// The most nodes below the root on any path, if limited with `with_max_depth`.
+    max_depth: Option<usize>,
+    /// The ledger every preferred ledger must descend from, see `set_locked`.
+    locked: Option<SpanTip<T>>,
+    /// The node of the last inserted ledger that was a leaf, which the next ledger most often
+    /// extends by one sequence number, see `insert_get`.
+    last_tip: Option<NodeHandle<T::IdType>>,
+}
+
+/// A reference to a node of an [`ArenaLedgerTrie`] that stays safe to use as the trie changes,
+/// see [`ArenaLedgerTrie::handle`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct NodeHandle<Id> {
+    idx: Index,
+    /// The ID of the node's tip ledger when the handle was made.
+    id: Id,
+}
+
+/// The outcome of removing support from an [`ArenaLedgerTrie`], see
+/// [`ArenaLedgerTrie::remove_with_freed`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FreedNodes<Id> {
+    /// Whether a matching node had support to remove.
+    pub changed: bool,
+    /// The handles of the nodes that were erased or combined with their child, which no
+    /// longer resolve.
+    pub freed: Vec<NodeHandle<Id>>,
+}
+
+/// The outcome of [`ArenaLedgerTrie::get_preferred_diagnostic`].
+pub enum PreferredResult<T: SpannableLedger> {
+    /// The preferred ledger.
+    Decided(SpanTip<T>),
+    /// The search for the preferred ledger stopped at a ledger whose children are tied for
+    /// the most branch support.
+    Tied {
+        /// The tips of the tied children.
+        candidates: Vec<SpanTip<T>>
+    },
+    /// The trie has no support.
+    Empty,
+}
+
+/// The outcome of a walk for the preferred ledger, see [`ArenaLedgerTrie::_get_preferred_from`].
+struct PreferredWalk<T: SpannableLedger, S> {
+    /// The preferred ledger.
+    tip: SpanTip<T>,
+    /// The branch support of the preferred ledger.
+    support: S,
+    /// The children of the node the walk stopped at that are tied for the most branch
+    /// support, if that tie stopped the walk.
+    ties: Vec<Index>,
+    /// How far the best child of the node the walk stopped at leads the second best, counting
+    /// the tie-breaker, or zero if the walk did not stop at a fork.
+    margin: S,
+    /// Each child the walk moved to, with the support that was uncommitted when it did.
+    path: Vec<(Index, S)>,
 }
 
-impl<T: Ledger> Default for ArenaLedgerTrie<T> {
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone + Default> Default for ArenaLedgerTrie<T, S, H> {
     fn default() -> Self {
-        ArenaLedgerTrie::new()
+        ArenaLedgerTrie::with_hasher(H::default())
     }
 }
 
-impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
-    fn insert(&mut self, ledger: &T, count: Option<u32>) {
-        // Find the ID of the node with the longest common ancestry with `ledger`
-        // and the sequence of the first ledger difference
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone + Default> LedgerTrie<T> for ArenaLedgerTrie<T, S, H> {
+    type Support = S;
+
+    fn insert(&mut self, ledger: &T, count: Option<S>) {
+        if let Err(err) = self.insert_get(ledger, count) {
+            panic!("{:?}", err);
+        }
+    }
+
+    fn remove(&mut self, ledger: &T, count: Option<S>) -> Result<bool, TrieError<S>> {
+        self.remove_with_freed(ledger, count).map(|freed| freed.changed)
+    }
+
+    fn get_preferred(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self._get_preferred(largest_issued).map(|walk| walk.tip)
+    }
+
+    fn get_preferred_with_support(&self, largest_issued: LedgerIndex) -> Option<(SpanTip<T>, S)> {
+        self._get_preferred(largest_issued).map(|walk| (walk.tip, walk.support))
+    }
+
+    fn empty(&self) -> bool {
+        self.arena.get(self.root).unwrap().branch_support == S::zero()
+    }
+
+    fn tip_support(&self, ledger: &T) -> S {
+        match self._find_by_ledger_id(ledger.id(), Some(ledger.seq()), None) {
+            None => S::zero(),
+            Some(loc) => {
+                self.arena.get(loc).unwrap().tip_support
+            }
+        }
+    }
+
+    fn branch_support(&self, ledger: &T) -> S {
+        let loc = self._find_by_ledger_id(ledger.id(), Some(ledger.seq()), None);
+
+        loc.map_or_else(
+            || {
+                let (l, diff_seq) = self._find(ledger);
+                let loc_node = self.arena.get(l).unwrap();
+                if diff_seq > ledger.seq() && ledger.seq() < loc_node.span.end() {
+                    Some(loc_node)
+                } else {
+                    None
+                }
+            },
+            |l| Some(self.arena.get(l).unwrap()),
+        ).map_or_else(
+            || S::zero(),
+            |loc_node| loc_node.branch_support,
+        )
+    }
+}
+
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone + Default> LedgerTrieExt<T> for ArenaLedgerTrie<T, S, H> {
+    fn preferred_fork_margin(&self, largest_issued: LedgerIndex) -> Option<S> {
+        self._get_preferred(largest_issued).map(|walk| walk.margin)
+    }
+
+    fn abandoned_branches(&self, largest_issued: LedgerIndex) -> Vec<SpanTip<T>> {
+        let walk = match self._get_preferred(largest_issued) {
+            Some(walk) => walk,
+            None => return vec![],
+        };
+
+        // Check the siblings of each child the walk moved to
+        let mut abandoned = vec![];
+        for (leader_idx, uncommitted) in walk.path {
+            let leader = self.arena.get(leader_idx).unwrap();
+            let parent = self.arena.get(leader.parent.unwrap()).unwrap();
+            for sibling in &parent.children {
+                let sibling = self.arena.get(*sibling).unwrap();
+                if sibling.idx != leader_idx && sibling.branch_support + uncommitted < leader.branch_support {
+                    let first = sibling.span.before(sibling.span.start() + 1).unwrap();
+                    abandoned.push(first.tip());
+                }
+            }
+        }
+        abandoned
+    }
+
+    fn total_support(&self) -> S {
+        self.arena.get(self.root).unwrap().branch_support
+    }
+
+    fn tip_support_of(&self, tip: &SpanTip<T>) -> S {
+        match self._find_by_ledger_id(tip.id(), Some(tip.seq()), None) {
+            None => S::zero(),
+            Some(loc) => self.arena.get(loc).unwrap().tip_support,
+        }
+    }
+
+    fn branch_support_of(&self, tip: &SpanTip<T>) -> S {
+        self.branch_support_of_tip(tip)
+    }
+}
+
+impl<T: SpannableLedger> ArenaLedgerTrie<T> {
+    /// Create an empty trie that counts support with `u32`. Use `Default` to count support
+    /// with another [`Support`] type.
+    pub fn new() -> Self {
+        ArenaLedgerTrie::default()
+    }
+
+    /// Return the number of leading sequences two ledgers have in common, whether or not
+    /// either is in a trie.
+    ///
+    /// Every ledger descends from the same genesis ledger, so this is at least 1, and is one
+    /// more than the sequence number of the ledgers if they are the same ledger.
+    pub fn common_prefix_len(a: &T, b: &T) -> LedgerIndex {
+        a.mismatch(b)
+    }
+
+    /// Create a trie holding the latest validated ledger of each node.
+    ///
+    /// # Params
+    /// **vals** - Pairs of node identifier and the ledger it validated. When a node appears
+    /// more than once, only its last ledger contributes support.
+    pub fn from_validations<I: IntoIterator<Item = (NodeId, T)>>(vals: I) -> Self {
+        let latest: HashMap<NodeId, T, BuildHasherDefault<FxHasher>> = vals.into_iter().collect();
+
+        let mut trie = ArenaLedgerTrie::new();
+        latest.values().for_each(|ledger| trie.insert(ledger, None));
+        trie
+    }
+}
+
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone> ArenaLedgerTrie<T, S, H> {
+    /// Create an empty trie whose maps keyed on ledger IDs use the given hasher.
+    pub fn with_hasher(hasher: H) -> Self {
+        let mut arena = Arena::new();
+        let root = arena.insert_with(|idx| Node::with_index(idx));
+        ArenaLedgerTrie {
+            root,
+            arena,
+            seq_support: Default::default(),
+            free_children: vec![],
+            version: 0,
+            preferred_cache: None,
+            strict_ancestry: false,
+            max_depth: None,
+            locked: None,
+            last_tip: None,
+            hasher,
+        }
+    }
+
+    /// Set whether to reject ledgers whose ancestry contradicts the ancestry they share with
+    /// the trie, rather than inserting them as a fork.
+    ///
+    /// A ledger that agrees with the trie at some sequence number but not at an earlier one
+    /// breaks the unique history of ledgers, see [`LedgerTrie`], and most likely comes from a
+    /// malformed validation. Checking takes time in proportion to the number of ledgers the
+    /// ledger shares with the trie below its root.
+    /// [`ArenaLedgerTrie::checked_insert`] and the other inserts report a rejected ledger with
+    /// [`TrieError::ConflictingAncestry`] and leave the trie unchanged, and
+    /// [`LedgerTrie::insert`] panics.
+    pub fn with_strict_ancestry(mut self, strict: bool) -> Self {
+        self.strict_ancestry = strict;
+        self
+    }
+
+    /// Limit how many nodes below the root any path through the trie may have, so that a
+    /// stream of crafted validations forking at every ledger can't make the trie arbitrarily
+    /// deep. There is no limit by default.
+    ///
+    /// [`ArenaLedgerTrie::checked_insert`] and the other inserts report a ledger that would
+    /// exceed the limit with [`TrieError::TooDeep`] and leave the trie unchanged, and
+    /// [`LedgerTrie::insert`] panics. Checking takes time in proportion to the depth of the
+    /// node the ledger is inserted at.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Return the version of the trie, which changes whenever the support in the trie changes
+    /// and stays the same otherwise, so callers can tell whether results they computed from
+    /// the trie are still current.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Return the preferred ledger like [`LedgerTrie::get_preferred`], reusing the result of
+    /// the last call if neither the trie's [`ArenaLedgerTrie::version`] nor `largest_issued`
+    /// changed since.
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    pub fn get_preferred_cached(&mut self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        match &self.preferred_cache {
+            Some((version, issued, tip)) if *version == self.version && *issued == largest_issued => tip.clone(),
+            _ => {
+                let tip = self._get_preferred(largest_issued).map(|walk| walk.tip);
+                self.preferred_cache = Some((self.version, largest_issued, tip.clone()));
+                tip
+            }
+        }
+    }
+
+    /// Lock the preferred ledger to `ledger` and its descendants, for example once that ledger
+    /// is final and must never be reorganized away.
+    ///
+    /// Afterwards, if the preferred ledger would not descend from the locked ledger, the walk
+    /// for it starts over from the locked ledger, ignoring every branch that diverges before
+    /// it. If no support descends from the locked ledger, it is preferred itself. Locking again
+    /// replaces the previous lock.
+    ///
+    /// The lock applies to every method that finds a preferred ledger, including the variants
+    /// that adjust the support first, such as [`ArenaLedgerTrie::get_preferred_excluding`]. For
+    /// [`ArenaLedgerTrie::get_preferred_from`], the lock takes precedence over the anchor.
+    ///
+    /// # Returns
+    /// Whether the ledger was found in the trie, either at the tip of a node or within its
+    /// span. The lock is unchanged otherwise.
+    pub fn set_locked(&mut self, ledger: &T) -> bool {
         let (loc_idx, diff_seq) = self._find(ledger);
+        if diff_seq <= ledger.seq() {
+            return false;
+        }
 
-        let mut inc_node_idx = Some(loc_idx);
+        // The ledger is in the span of the node at loc_idx
+        let span = &self.arena.get(loc_idx).unwrap().span;
+        self.locked = Some(span.before(ledger.seq() + 1).unwrap().tip());
+        self.preferred_cache = None;
+        true
+    }
+
+    /// Remove all support from the trie, keeping its hasher. The locked ledger is no longer in
+    /// the trie, so the lock is removed as well.
+    pub fn clear(&mut self) {
+        let version = self.version + 1;
+        let max_depth = self.max_depth;
+        *self = ArenaLedgerTrie::with_hasher(self.hasher.clone())
+            .with_strict_ancestry(self.strict_ancestry);
+        self.max_depth = max_depth;
+        self.version = version;
+    }
+
+    /// Create an empty trie with room for `nodes` nodes besides the root before its arena has
+    /// to grow, for example before replaying a large number of validations.
+    ///
+    /// The capacity is only a hint, and the trie grows past it as needed. Each inserted ledger
+    /// adds at most two nodes. The support by sequence number is kept in a `BTreeMap`, which
+    /// can't be sized ahead of time.
+    pub fn with_capacity(nodes: usize) -> Self where H: Default {
+        let mut trie = ArenaLedgerTrie::default();
+        trie.arena.reserve(nodes);
+        trie
+    }
+
+    /// Create an empty trie rooted at a known genesis ledger instead of the ledger with
+    /// sequence 0.
+    ///
+    /// The root's tip is `genesis`, and the preferred ledger is never one of its ancestors.
+    /// Every ledger inserted into the trie must descend from, or be, `genesis`. Inserting any
+    /// other ledger reports [`TrieError::DivergesFromGenesis`] without changing the trie, and
+    /// [`LedgerTrie::insert`] panics.
+    pub fn with_genesis(genesis: T) -> Self where H: Default {
+        let mut trie = ArenaLedgerTrie::default();
+        trie.arena.get_mut(trie.root).unwrap().span = Span::from(genesis);
+        trie
+    }
+
+    /// Check that the ledger found at `diff_seq` of the node at `loc_idx` doesn't diverge
+    /// within the root's span, meaning it is or descends from the root's tip.
+    fn _check_descends_from_root(&self, loc_idx: Index, diff_seq: LedgerIndex) -> Result<(), TrieError<S>> {
+        if loc_idx == self.root && diff_seq < self.arena.get(self.root).unwrap().span.end() {
+            return Err(TrieError::DivergesFromGenesis { seq: diff_seq });
+        }
+        Ok(())
+    }
+
+    /// Insert and/or increment the support for a ledger given where it diverges from the trie.
+    ///
+    /// # Params
+    /// **loc_idx** - The `Index` of the node with the longest common ancestry with the ledger.
+    ///
+    /// **diff_seq** - The `LedgerIndex` of the first difference between that node and the ledger.
+    ///
+    /// **new_suffix** - The part of the ledger's span after `diff_seq`, if any.
+    ///
+    /// **seq** - The sequence number of the ledger.
+    ///
+    /// **count** - The count of support for the ledger.
+    ///
+    /// **full** - Whether the support is from full validations, which count towards the
+    /// ledger's tip support, or partial validations, which do not.
+    fn _insert_at(
+        &mut self,
+        loc_idx: Index,
+        diff_seq: LedgerIndex,
+        new_suffix: Option<Span<T>>,
+        seq: LedgerIndex,
+        count: Option<S>,
+        full: bool,
+    ) -> Option<Index> {
+        // A zero count would otherwise leave behind a new node without any support
+        let count = count.unwrap_or(S::one());
+        if count == S::zero() {
+            return None;
+        }
 
-        // Insert a new, basically empty, Node and also get a mutable reference to both the loc node
-        // and new node we inserted.
-        // We have to do it this way because we need a mutable reference to both, but
-        // cannot cannot call self.arena.get_mut twice without having two simultaneous
-        // mutable borrows of self.arena, which would break Rust's ownership rules.
-        let (loc, new_node) = self._add_empty_and_get(loc_idx);
+        let mut inc_node_idx = loc_idx;
 
-        let loc_idx = loc.idx;
         // loc->span has the longest common prefix with Span{ledger} of all
         // existing nodes in the trie. The optional<Span>'s below represent
         // the possible common suffixes between loc->span and Span{ledger}.
@@ -99,9 +568,7 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
         // Span{ledger}
         //  a b c  | g h i
         //  prefix | newSuffix
-        let prefix = loc.span.before(diff_seq);
-        let old_suffix = loc.span.after(diff_seq);
-        let new_suffix = Span::from(ledger.clone()).after(diff_seq);
+        let (prefix, old_suffix) = self.arena.get(loc_idx).unwrap().span.split_at(diff_seq);
 
         if let Some(old_suffix) = old_suffix {
             // Have
@@ -111,35 +578,40 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
             // Becomes
             //   abc -> def -> ...
 
+            // Insert a new, basically empty, Node and also get a mutable reference to both the
+            // loc node and new node we inserted.
+            // We have to do it this way because we need a mutable reference to both, but
+            // cannot call self.arena.get_mut twice without having two simultaneous
+            // mutable borrows of self.arena, which would break Rust's ownership rules.
+            let (loc, new_node) = self._add_empty_and_get(loc_idx);
+
             // Set new_node's span to old_suffix and take tip_support and branch_support
             // from loc so that new_node takes over loc. new_node will be loc's child.
             new_node.span = old_suffix;
             new_node.tip_support = loc.tip_support;
+            new_node.partial_support = loc.partial_support;
             new_node.branch_support = loc.branch_support;
             new_node.parent = Some(loc.idx);
+            new_node.height = loc.height;
 
-            // Replace loc's children Vec with an empty vector because we will move
-            // loc's children into new_node's children. However, we need to clone
-            // the children Vec into new_node.children because we later need to
-            // iterate through the children, get a mutable reference to the Node
-            // the child Index points to and update each child Node's parent idx to
-            // point to new_node. If we simply moved loc.children into new_node.children,
-            // we'd need to keep the mutable reference to new_node alive which would
-            // prevent us from getting mutable references to each child Node.
-            let loc_children = std::mem::replace(&mut loc.children, vec![]);
-            new_node.children = loc_children.clone();
+            // Swap the children Vecs so that new_node takes over loc's children, and loc
+            // reuses new_node's empty Vec, which may have been kept from a removed node.
+            core::mem::swap(&mut loc.children, &mut new_node.children);
 
             // loc truncates to prefix and new_node is its child
             loc.span = prefix.unwrap();
             loc.children.push(new_node.idx);
-            loc.tip_support = 0;
+            loc.tip_support = S::zero();
+            loc.partial_support = S::zero();
 
+            // Update each child node's parent field to point to new_node. We index into
+            // new_node's children rather than iterate over them because each child Node
+            // needs a mutable borrow of the arena.
             let new_node_idx = new_node.idx;
-            // Update each child node's parent field to point to new_node.
-            loc_children.iter()
-                .for_each(|child_idx| {
-                    self.arena.get_mut(*child_idx).unwrap().parent = Some(new_node_idx)
-                })
+            for i in 0..self.arena.get(new_node_idx).unwrap().children.len() {
+                let child_idx = self.arena.get(new_node_idx).unwrap().children[i];
+                self.arena.get_mut(child_idx).unwrap().parent = Some(new_node_idx);
+            }
         }
 
         if let Some(new_suffix) = new_suffix {
@@ -151,25 +623,62 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
             //  abc -> ...
             //     \-> def
 
-            // Insert a new, basically empty, Node and save its Index.
-            let new_node_idx = self.arena.insert_with(|idx| {
-                let new_node = Node::with_index(idx);
-                new_node
-            });
-
-            // Unfortunately we need to get loc and create a new node again here because the mutable
-            // borrow of self.arena created on the initial call to get2_mut can't outlive
-            // the mutable borrow of arena when we update the children nodes.
+            // The borrows of the split above have ended, so loc can be borrowed again
+            // alongside another new node.
             let (loc, new_node) = self._add_empty_and_get(loc_idx);
             new_node.span = new_suffix;
             new_node.parent = Some(loc_idx);
-            inc_node_idx = Some(new_node.idx);
+            inc_node_idx = new_node.idx;
+            // new_node is a leaf, so loc can have its Vec if loc has none allocated yet
+            if loc.children.capacity() == 0 {
+                core::mem::swap(&mut loc.children, &mut new_node.children);
+            }
             loc.children.push(new_node.idx);
         }
 
+        self._update_heights(loc_idx);
+        self._increment(inc_node_idx, seq, count, full);
+        Some(inc_node_idx)
+    }
+
+    /// Recompute the height of the node at `idx` from its children after they changed, and of
+    /// its ancestors for as long as their height changes too.
+    fn _update_heights(&mut self, idx: Index) {
+        let mut curr = Some(idx);
+        while let Some(idx) = curr {
+            let node = self.arena.get(idx).unwrap();
+            let height = node.children.iter()
+                .map(|child| self.arena.get(*child).unwrap().height + 1)
+                .max()
+                .unwrap_or(0);
+            if height == node.height {
+                break;
+            }
+
+            let node = self.arena.get_mut(idx).unwrap();
+            node.height = height;
+            curr = node.parent;
+        }
+    }
+
+    /// Return an error if adding `count` support would overflow the trie's total support.
+    ///
+    /// The root's branch support is the total support in the trie, so it is the largest
+    /// support of any node or sequence number and overflows first.
+    fn _check_overflow(&self, count: S) -> Result<(), TrieError<S>> {
+        let total = self.arena.get(self.root).unwrap().branch_support;
+        match total.checked_add(count) {
+            Some(_) => Ok(()),
+            None => Err(TrieError::SupportOverflow { total, requested: count }),
+        }
+    }
+
+    /// Increase the support of a node and the branch support of its ancestors.
+    fn _increment(&mut self, loc_idx: Index, seq: LedgerIndex, count: S, full: bool) {
+        self.version += 1;
         // Update branch support all the way up the trie
-        let count = count.unwrap_or(1);
-        self.arena.get_mut(inc_node_idx.unwrap()).unwrap().tip_support += count;
+        let mut inc_node_idx = Some(loc_idx);
+        *self.arena.get_mut(loc_idx).unwrap().support_mut(full) += count;
         while inc_node_idx.is_some() {
             let inc_node = self.arena.get_mut(inc_node_idx.unwrap()).unwrap();
             inc_node.branch_support += count;
@@ -177,7 +686,7 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
         }
 
         // Update seq support by adding count, or insert a new entry
-        match self.seq_support.entry(ledger.seq()) {
+        match self.seq_support.entry(seq) {
             Entry::Occupied(mut entry) => {
                 *entry.get_mut() += count;
             }
@@ -187,44 +696,74 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
         }
     }
 
-    fn remove(&mut self, ledger: &T, count: Option<u32>) -> bool {
-        let loc_idx = self._find_by_ledger_id(ledger.id(), None);
-        let loc_node = loc_idx
-            .map(|i| self.arena.get_mut(i).unwrap());
-
-        // Must be exact match with tip support
-        if let Some(l) = &loc_node {
-            if l.tip_support == 0 {
-                return false;
-            }
-        } else {
-            return false;
-        }
-
-        let loc_node = loc_node.unwrap();
+    /// Remove support for a ledger, see [`LedgerTrie::remove`].
+    ///
+    /// # Params
+    /// **full** - Whether to remove support from full validations or partial validations.
+    ///
+    /// # Returns
+    /// Whether a matching node had support to remove, and the handles of the nodes freed by
+    /// compressing the trie.
+    fn _remove(
+        &mut self,
+        ledger: &T,
+        count: Option<S>,
+        full: bool,
+    ) -> Result<FreedNodes<T::IdType>, TrieError<S>> {
+        let loc_idx = self._find_by_ledger_id(ledger.id(), Some(ledger.seq()), None);
+
+        // Must be exact match with tip support, or partial support when removing a partial
+        // validation
+        let loc_idx = match loc_idx {
+            Some(l) if self.arena.get(l).unwrap().support(full) != S::zero() => l,
+            _ => return Ok(FreedNodes { changed: false, freed: vec![] }),
+        };
 
-        // Have to save this for later when we try to merge/erase nodes, otherwise we get a double
-        // mutable borrow
-        // let parent_idx = loc_node.parent;
+        self._decrement(loc_idx, ledger.seq(), count.unwrap_or(S::one()), full)?;
+        Ok(FreedNodes { changed: true, freed: self._compress(loc_idx) })
+    }
 
-        let count = std::cmp::min(count.unwrap_or(1), loc_node.tip_support);
-        loc_node.tip_support -= count;
+    /// Decrease the support of a node and the branch support of its ancestors, without
+    /// removing or combining any nodes.
+    ///
+    /// # Returns
+    /// [`TrieError::InsufficientSupport`] without changing any support if `count` exceeds the
+    /// node's support.
+    fn _decrement(&mut self, loc_idx: Index, seq: LedgerIndex, count: S, full: bool) -> Result<(), TrieError<S>> {
+        let support = self.arena.get_mut(loc_idx).unwrap().support_mut(full);
+        if count > *support {
+            return Err(TrieError::InsufficientSupport {
+                available: *support,
+                requested: count,
+            });
+        }
+        *support -= count;
+        self.version += 1;
 
-        let support = self.seq_support.get_mut(&ledger.seq()).unwrap();
+        let support = self.seq_support.get_mut(&seq).unwrap();
         assert!(*support >= count);
         *support -= count;
-        if *support == 0 {
-            self.seq_support.remove(&ledger.seq()).unwrap();
+        if *support == S::zero() {
+            self.seq_support.remove(&seq).unwrap();
         }
 
-        let mut dec_node_idx = loc_idx;
+        let mut dec_node_idx = Some(loc_idx);
         while dec_node_idx.is_some() {
             let dec_node = self.arena.get_mut(dec_node_idx.unwrap()).unwrap();
             dec_node.branch_support -= count;
             dec_node_idx = dec_node.parent;
         }
+        Ok(())
+    }
 
-        let mut loc_idx = loc_idx.unwrap();
+    /// Erase a node without support and no children, or combine it with its only child,
+    /// repeating up the trie for as long as this leaves its parent in the same state.
+    ///
+    /// # Returns
+    /// The handles of the erased nodes and of the nodes combined into their child.
+    fn _compress(&mut self, loc_idx: Index) -> Vec<NodeHandle<T::IdType>> {
+        let mut loc_idx = loc_idx;
+        let mut freed = vec![];
 
         while loc_idx != self.root {
             let parent_idx = self.arena.get(loc_idx).unwrap().parent.unwrap();
@@ -232,20 +771,26 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
             let loc_node = loc_node.unwrap();
 
             let loc_span = loc_node.span.clone();
-            if loc_node.tip_support != 0 {
+            if loc_node.tip_support != S::zero() || loc_node.partial_support != S::zero() {
                 break;
             }
 
             let parent_node = parent.unwrap();
+            let handle = NodeHandle { idx: loc_idx, id: loc_span.tip().id() };
             if loc_node.children.is_empty() {
                 // this node can be erased.
+                freed.push(handle);
                 parent_node.erase(loc_idx);
+                let loc_node = self.arena.remove(loc_idx).unwrap();
+                self._recycle_children(loc_node.children);
             } else if loc_node.children.len() == 1 {
                 // This node can be combined with its child
                 let child_idx = *loc_node.children.last().unwrap();
+                freed.push(handle);
                 parent_node.children.push(child_idx);
                 parent_node.erase(loc_idx);
-                self.arena.remove(loc_idx);
+                let loc_node = self.arena.remove(loc_idx).unwrap();
+                self._recycle_children(loc_node.children);
 
                 let child_node = self.arena.get_mut(child_idx).unwrap();
                 child_node.span = Span::merge(&loc_span, &child_node.span);
@@ -256,887 +801,4456 @@ impl<T: Ledger> LedgerTrie<T> for ArenaLedgerTrie<T> {
 
             loc_idx = parent_idx;
         }
-        true
+
+        // Every node removed above was on the path down to where the loop stopped
+        self._update_heights(loc_idx);
+        freed
     }
 
-    fn get_preferred(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
-        if self.empty() {
-            return None;
-        }
+    /// Apply a signed change to the tip support of a ledger already in the trie, as when a
+    /// validator's weight changes without it switching ledgers.
+    ///
+    /// # Params
+    /// **id** - The ID of the ledger.
+    ///
+    /// **delta** - The amount to add to, or if negative subtract from, the ledger's tip support.
+    ///
+    /// # Returns
+    /// Whether the tip support was adjusted. The trie is unchanged if no node with tip support
+    /// has the ledger as its tip, if `delta` would take its tip support below zero, or if the
+    /// adjusted support cannot be counted by `S`.
+    pub fn adjust_tip(&mut self, id: T::IdType, delta: i64) -> bool {
+        let loc_idx = match self._find_by_ledger_id(id, None, None) {
+            Some(l) if self.arena.get(l).unwrap().tip_support != S::zero() => l,
+            _ => return false,
+        };
+        let count = match S::try_from(delta.unsigned_abs()) {
+            Ok(count) => count,
+            Err(_) => return false,
+        };
+        let loc_node = self.arena.get(loc_idx).unwrap();
+        let seq = loc_node.span.end() - 1;
 
-        let mut curr = self.arena.get(self.root);
-        let mut done = false;
+        if delta >= 0 {
+            if self._check_overflow(count).is_err() {
+                return false;
+            }
+            self._increment(loc_idx, seq, count, true);
+        } else {
+            if self._decrement(loc_idx, seq, count, true).is_err() {
+                return false;
+            }
+            self._compress(loc_idx);
+        }
+        true
+    }
 
-        let mut uncommitted: u32 = 0;
+    /// Prune the subtrees of nodes that do not satisfy a predicate.
+    ///
+    /// # Params
+    /// **pred** - Called with the tip, tip support and branch support of each node other than
+    /// the root, as they were before any pruning. Returning `false` removes the node and all
+    /// of its descendants, along with their support.
+    ///
+    /// # Returns
+    /// The handles of the removed nodes and of any nodes combined with their child afterwards,
+    /// for example to purge an external cache keyed by [`NodeHandle`].
+    pub fn retain(&mut self, pred: impl Fn(&SpanTip<T>, S, S) -> bool) -> Vec<NodeHandle<T::IdType>> {
+        let mut to_prune = vec![];
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            for child_idx in &self.arena.get(idx).unwrap().children {
+                let child = self.arena.get(*child_idx).unwrap();
+                if pred(&child.span.tip(), child.tip_support, child.branch_support) {
+                    nodes.push(*child_idx);
+                } else {
+                    to_prune.push(*child_idx);
+                }
+            }
+        }
 
-        let mut uncommitted_it = self.seq_support.iter();
-        let mut next = uncommitted_it.next();
+        let mut freed = vec![];
+        let mut parents = Vec::with_capacity(to_prune.len());
+        for idx in to_prune {
+            parents.push(self._prune(idx, &mut freed));
+        }
 
-        while curr.is_some() && !done {
-            // Within a single span, the preferred by branch strategy is simply
-            // to continue along the span as long as the branch support of
-            // the next ledger exceeds the uncommitted support for that ledger.
+        for parent_idx in parents {
+            // Compressing an earlier parent may have already combined this one with its child
+            if self.arena.contains(parent_idx) {
+                freed.extend(self._compress(parent_idx));
+            }
+        }
+        freed
+    }
 
-            {
-                // Add any initial uncommitted support prior for ledgers
-                // earlier than nextSeq or earlier than largestIssued
-                let mut next_seq = curr.unwrap().span.start() + 1;
-                while let Some((seq, support)) = next {
-                    if *seq < std::cmp::max(next_seq, largest_issued) {
-                        uncommitted += support;
-                        next = uncommitted_it.next();
-                    } else {
-                        break;
-                    }
+    /// Remove a non-root node, its descendants and all of their support, without compressing
+    /// its parent. The handles of the removed nodes are added to `freed`.
+    ///
+    /// # Returns
+    /// The `Index` of the removed node's parent.
+    fn _prune(&mut self, idx: Index, freed: &mut Vec<NodeHandle<T::IdType>>) -> Index {
+        let node = self.arena.get(idx).unwrap();
+        let parent_idx = node.parent.unwrap();
+        let branch_support = node.branch_support;
+
+        self.version += 1;
+        let mut subtree = vec![idx];
+        while let Some(curr_idx) = subtree.pop() {
+            let curr = self.arena.remove(curr_idx).unwrap();
+            freed.push(NodeHandle { idx: curr_idx, id: curr.span.tip().id() });
+            let support = curr.tip_support + curr.partial_support;
+            if support != S::zero() {
+                let seq = curr.span.end() - 1;
+                let seq_support = self.seq_support.get_mut(&seq).unwrap();
+                *seq_support -= support;
+                if *seq_support == S::zero() {
+                    self.seq_support.remove(&seq);
                 }
+            }
+            subtree.extend_from_slice(&curr.children);
+            self._recycle_children(curr.children);
+        }
 
-                // Advance next_seq along the span
-                while next_seq < curr.unwrap().span.end() &&
-                    curr.unwrap().branch_support > uncommitted {
-                    // Jump to the next seq_support change.
-                    if let Some((seq, support)) = next {
-                        if *seq < curr.unwrap().span.end() {
-                            next_seq = seq + 1;
-                            uncommitted += support;
-                            next = uncommitted_it.next();
-                        } else {
-                            // Otherwise we jump to the end of the span
-                            next_seq = curr.unwrap().span.end();
-                        }
-                    } else {
-                        // Otherwise we jump to the end of the span
-                        next_seq = curr.unwrap().span.end();
-                    }
-                }
+        self.arena.get_mut(parent_idx).unwrap().erase(idx);
+        let mut dec_node_idx = Some(parent_idx);
+        while let Some(dec_idx) = dec_node_idx {
+            let dec_node = self.arena.get_mut(dec_idx).unwrap();
+            dec_node.branch_support -= branch_support;
+            dec_node_idx = dec_node.parent;
+        }
+        self._update_heights(parent_idx);
 
-                // We did not consume the entire span, so we have found the
-                // preferred ledger
-                if next_seq < curr.unwrap().span.end() {
-                    return Some(curr.unwrap().span.before(next_seq)?.tip());
-                }
-            }
-
-            // We have reached the end of the current span, so we need to
-            // find the best child
-            let mut margin = 0u32;
-            let mut best: Option<&Node<T>> = None;
-            if curr.unwrap().children.len() == 1 {
-                best = Some(self.arena.get(*curr.unwrap().children.get(0).unwrap()).unwrap());
-                margin = best?.branch_support;
-            } else if !curr.unwrap().children.is_empty() { // Children length > 1
-                // Sort placing children with largest branch support in the front,
-                // breaking ties with the span's starting ID
+        parent_idx
+    }
 
-                // NOTE: In C++, they sort the actual node's children vector.
-                //  In rust, we can't get a mutable reference to curr because then
-                //  we'd have a mutable reference to self.arena at the same time as having
-                //  a shared reference to self.arena. Therefore, this code sorts a temporary
-                //  clone of curr.children but does not update curr.children
-                let mut children_to_sort = curr.unwrap().children.clone();
-                children_to_sort
-                    .sort_by(|&index1, &index2| {
-                        let node1 = self.arena.get(index1).unwrap();
-                        let node2 = self.arena.get(index2).unwrap();
-                        let cmp = node2.branch_support.cmp(&node1.branch_support);
-                        match cmp {
-                            Ordering::Equal => {
-                                node2.span.start_id().cmp(&node1.span.start_id())
-                            }
-                            _ => cmp
-                        }
-                    });
+    /// Decrease the support for a batch of ledgers, as if by calling [`LedgerTrie::remove`]
+    /// for each in order.
+    ///
+    /// Each node is located with a single pass over the trie rather than one search per ledger,
+    /// and nodes are only erased or combined once all of the support has been removed.
+    ///
+    /// # Params
+    /// **ledgers** - Pairs of a ledger and the count of support to remove from it. Ledgers that
+    /// are not in the trie or have no tip support are skipped.
+    ///
+    /// # Returns
+    /// [`TrieError::InsufficientSupport`] for the first pair whose count exceeds the ledger's
+    /// remaining tip support, in which case the pairs before it are still removed.
+    pub fn remove_all(&mut self, ledgers: &[(T, S)]) -> Result<(), TrieError<S>> {
+        let mut by_id: HashMap<T::IdType, Index, H> =
+            HashMap::with_capacity_and_hasher(self.arena.len(), self.hasher.clone());
+        for (idx, node) in self.arena.iter() {
+            by_id.entry(node.span.tip().id()).or_insert(idx);
+        }
 
-                let first_child = self.arena.get(*children_to_sort.get(0).unwrap()).unwrap();
-                let second_child = self.arena.get(*children_to_sort.get(1).unwrap()).unwrap();
-                best = Some(first_child);
-                margin = first_child.branch_support - second_child.branch_support;
+        let mut touched: Vec<Index> = Vec::with_capacity(ledgers.len());
+        let mut result = Ok(());
+        for (ledger, count) in ledgers {
+            let loc_idx = match by_id.get(&ledger.id()) {
+                Some(l) if self.arena.get(*l).unwrap().tip_support != S::zero() => *l,
+                _ => continue,
+            };
 
-                // If best holds the tie-breaker, gets one larger margin
-                // since the second best needs additional branchSupport
-                // to overcome the tie
-                if best.unwrap().span.start_id() > second_child.span.start_id() {
-                    margin += 1;
-                }
+            result = self._decrement(loc_idx, ledger.seq(), *count, true);
+            if result.is_err() {
+                break;
             }
+            touched.push(loc_idx);
+        }
 
-            // If the best child has margin exceeding the uncommitted support,
-            // continue from that child, otherwise we are done
-            if best.is_some() && ((margin > uncommitted) || (uncommitted == 0)) {
-                curr = best;
-            } else {
-                done = true;
+        for loc_idx in touched {
+            // Compressing an earlier node may have already erased or combined this one
+            if self.arena.contains(loc_idx) {
+                self._compress(loc_idx);
             }
         }
-
-        return Some(curr.unwrap().span.tip());
+        result
     }
 
-    fn tip_support(&self, ledger: &T) -> u32 {
-        match self._find_by_ledger_id(ledger.id(), None) {
-            None => 0,
-            Some(loc) => {
-                self.arena.get(loc).unwrap().tip_support
-            }
-        }
+
+    /// Insert and/or increment the support for a ledger from partial validations.
+    ///
+    /// A partial validation is issued by a node that is tracking the network but not fully
+    /// validating. Its support counts towards the branch support of the ledger and its
+    /// ancestors, so it still steers [`LedgerTrie::get_preferred`], but not towards the
+    /// ledger's tip support.
+    ///
+    /// # Params
+    /// **ledger** - The ledger to insert.
+    ///
+    /// **count** - The count of partial support for this ledger.
+    ///
+    /// # Returns
+    /// Any error of [`ArenaLedgerTrie::checked_insert`].
+    pub fn insert_partial(&mut self, ledger: &T, count: Option<S>) -> Result<(), TrieError<S>> {
+        let found = self._find(ledger);
+        let new_suffix = Span::from(ledger.clone()).after(found.1);
+        self._insert_found(found, ledger.seq(), |s| ledger.get_ancestor(s), new_suffix, count, false)?;
+        Ok(())
     }
 
-    fn branch_support(&self, ledger: &T) -> u32 {
-        let loc = self._find_by_ledger_id(ledger.id(), None);
+    /// Decrease the partial support for a ledger, removing and compressing nodes if possible.
+    ///
+    /// # Params
+    /// **ledger** - The ledger to remove partial support for.
+    ///
+    /// **count** - The count of partial support to remove.
+    ///
+    /// # Returns
+    /// Whether a matching node with partial support was decremented, or
+    /// [`TrieError::InsufficientSupport`] if `count` exceeds its partial support.
+    pub fn remove_partial(&mut self, ledger: &T, count: Option<S>) -> Result<bool, TrieError<S>> {
+        self._remove(ledger, count, false).map(|freed| freed.changed)
+    }
 
-        loc.map_or_else(
-            || {
-                let (l, diff_seq) = self._find(ledger);
-                let loc_node = self.arena.get(l).unwrap();
-                if diff_seq > ledger.seq() && ledger.seq() < loc_node.span.end() {
-                    Some(loc_node)
-                } else {
-                    None
-                }
-            },
-            |l| Some(self.arena.get(l).unwrap()),
-        ).map_or_else(
-            || 0,
-            |loc_node| loc_node.branch_support,
-        )
+    /// Decrease the support for a ledger like [`LedgerTrie::remove`], and report the nodes that
+    /// were erased or combined with their child as a result, for example to purge an external
+    /// cache keyed by [`NodeHandle`].
+    ///
+    /// # Params
+    /// **ledger** - The ledger to remove support for.
+    ///
+    /// **count** - The count of support to remove.
+    ///
+    /// # Returns
+    /// Whether a matching node with tip support was found, and the handles of the freed nodes,
+    /// or [`TrieError::InsufficientSupport`] if `count` exceeds its tip support.
+    pub fn remove_with_freed(
+        &mut self,
+        ledger: &T,
+        count: Option<S>,
+    ) -> Result<FreedNodes<T::IdType>, TrieError<S>> {
+        self._remove(ledger, count, true)
     }
-}
 
-impl<T: Ledger> ArenaLedgerTrie<T> {
-    pub fn new() -> Self {
-        let mut arena = Arena::new();
-        let root = arena.insert_with(|idx| Node::with_index(idx));
-        ArenaLedgerTrie {
-            root,
-            arena,
-            seq_support: Default::default(),
-        }
+    /// Insert and/or increment the support for a ledger known by its ID, sequence number and
+    /// ancestry, only materializing a `T` if the ledger extends past the trie's history.
+    ///
+    /// The spans stored in the trie must be backed by a `T`, so `ledger` is only called when
+    /// the ledger is not an ancestor of, or equal to, a ledger already in the trie.
+    ///
+    /// # Params
+    /// **id** - The ID of the ledger.
+    ///
+    /// **seq** - The sequence number of the ledger.
+    ///
+    /// **ancestor** - Returns the ID of the ledger's ancestor with the given sequence number.
+    /// It is only called with sequence numbers less than `seq`, and only for the few it needs
+    /// to locate the ledger in the trie, and to check it if the trie has strict ancestry. It
+    /// must honor the unique history invariant described on [`LedgerTrie`].
+    ///
+    /// **ledger** - Returns the ledger itself.
+    ///
+    /// **count** - The count of support for this ledger.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the ledger like [`ArenaLedgerTrie::insert_get`], or any error of
+    /// [`ArenaLedgerTrie::checked_insert`].
+    pub fn insert_by_id<F, L>(
+        &mut self,
+        id: T::IdType,
+        seq: LedgerIndex,
+        ancestor: F,
+        ledger: L,
+        count: Option<S>,
+    ) -> Result<Option<SpanTip<T>>, TrieError<S>>
+    where
+        F: Fn(LedgerIndex) -> T::IdType,
+        L: FnOnce() -> T,
+    {
+        let ancestry = |s: LedgerIndex| if s == seq { id } else { ancestor(s) };
+        let found = self._find_by(|span| span.diff_by(seq, ancestry));
+
+        // Any part of the ledger past the trie's history needs a span backed by the ledger
+        let new_suffix = match found.1 <= seq {
+            true => Span::from(ledger()).after(found.1),
+            false => None,
+        };
+        self._insert_found(found, seq, ancestry, new_suffix, count, true)
     }
 
-    fn _add_empty_and_get(&mut self, loc_idx: Index) -> (&mut Node<T>, &mut Node<T>) {
-        let new_node_idx = self.arena.insert_with(|idx| {
-            let new_node = Node::with_index(idx);
-            new_node
-        });
+    /// Insert and/or increment the support for the tip of a span that was already computed,
+    /// without deriving it again from a ledger.
+    ///
+    /// The ledgers of the span that are not yet in the trie are taken from the span itself, so
+    /// it must reach back to where its tip diverges from the trie. A span made with
+    /// `Span::from` a ledger always does.
+    ///
+    /// # Params
+    /// **span** - The span whose tip is supported.
+    ///
+    /// **count** - The count of support for the span's tip.
+    ///
+    /// # Returns
+    /// Whether the support was inserted. The trie is unchanged if the span starts after its tip
+    /// diverges from the trie or `count` is zero, or on any error of
+    /// [`ArenaLedgerTrie::checked_insert`].
+    pub fn insert_span(&mut self, span: Span<T>, count: Option<S>) -> Result<bool, TrieError<S>> {
+        let tip = span.tip();
+        let found = self._find_by(|s| s.diff_by(tip.seq(), |seq| tip.ancestor(seq)));
+        if found.1 < span.start() {
+            return Ok(false);
+        }
 
-        let (loc, new_node) = self.arena.get2_mut(loc_idx, new_node_idx);
+        let inserted = self._insert_found(found, tip.seq(), |seq| tip.ancestor(seq), span.after(found.1), count, true)?;
+        Ok(inserted.is_some())
+    }
 
-        (loc.unwrap(), new_node.unwrap())
+    /// Insert and/or increment the support for `ledger` like [`LedgerTrie::insert`], returning
+    /// the tip of the node whose tip support was incremented, which is `ledger` itself.
+    ///
+    /// # Returns
+    /// The `SpanTip` of `ledger`, or `None` if `count` is zero and the trie is unchanged, or
+    /// any error of [`ArenaLedgerTrie::checked_insert`].
+    pub fn insert_get(&mut self, ledger: &T, count: Option<S>) -> Result<Option<SpanTip<T>>, TrieError<S>> {
+        // Find the ID of the node with the longest common ancestry with `ledger`
+        // and the sequence of the first ledger difference, which is just past the
+        // last inserted tip if `ledger` extends it
+        self._insert_span(ledger, Span::from(ledger.clone()), count)
     }
 
-    fn _find_by_ledger_id(&self, ledger_id: T::IdType, parent: Option<&Index>) -> Option<Index> {
-        let parent = match parent {
-            None => self.root,
-            Some(p) => *p
+    /// Insert `ledger` given the span of its whole ancestry, see
+    /// [`ArenaLedgerTrie::insert_get`].
+    fn _insert_span(&mut self, ledger: &T, span: Span<T>, count: Option<S>) -> Result<Option<SpanTip<T>>, TrieError<S>> {
+        let found = match self._extends_last_tip(ledger) {
+            Some(loc_idx) => (loc_idx, ledger.seq()),
+            None => self._find(ledger),
         };
+        self._insert_found(found, ledger.seq(), |s| ledger.get_ancestor(s), span.after(found.1), count, true)
+    }
 
-        let parent_node = self.arena.get(parent).unwrap();
-        if ledger_id == parent_node.span.tip().id() {
-            return Some(parent);
-        }
+    /// Return the index of the node of the last inserted tip if it is still a leaf and `ledger`
+    /// is its child, so that nothing else in the trie has a longer common ancestry with it.
+    ///
+    /// Only the span of the tip's node is compared with `ledger`. The ancestors above it are
+    /// taken on the unique history invariant of [`LedgerTrie`], which ledgers chained by hash
+    /// uphold: a ledger whose parent is the tip shares all of the tip's history. A ledger that
+    /// contradicts the tip's span is left to
+    /// [`ArenaLedgerTrie::_find`], like any other ledger, and a trie with strict ancestry still
+    /// checks the rest of the path in [`ArenaLedgerTrie::_insert_found`].
+    fn _extends_last_tip(&self, ledger: &T) -> Option<Index> {
+        let handle = self.last_tip?;
+        let node = self.arena.get(handle.idx)?;
+        let tip = node.span.tip();
+        let extends = tip.id() == handle.id
+            && node.children.is_empty()
+            && ledger.seq() == tip.seq() + 1
+            && ledger.get_ancestor(tip.seq()) == tip.id()
+            && node.span.first_mismatch(ledger) == node.span.end();
+        extends.then_some(handle.idx)
+    }
 
-        for child in &parent_node.children {
-            let cl = self._find_by_ledger_id(ledger_id, Some(&child));
-            if cl.is_some() {
-                return cl;
+    /// Insert and/or increment the support for a ledger like [`ArenaLedgerTrie::insert_get`],
+    /// once it has been found in the trie. Every insert goes through here, so that all of them
+    /// are checked for overflow, conflicting ancestry and depth.
+    ///
+    /// # Params
+    /// **found** - The `Index` of the node with the longest common ancestry with the ledger,
+    /// and the `LedgerIndex` of the first difference, see [`ArenaLedgerTrie::_find`].
+    ///
+    /// **seq** - The sequence number of the ledger.
+    ///
+    /// **ancestry** - Returns the ID of the ledger's ancestor with the given sequence number.
+    ///
+    /// **new_suffix** - The part of the ledger's span after the first difference, if any.
+    ///
+    /// **count** - The count of support for the ledger.
+    ///
+    /// **full** - Whether the support is from full or partial validations, see
+    /// [`ArenaLedgerTrie::_insert_at`].
+    fn _insert_found<F: Fn(LedgerIndex) -> T::IdType>(
+        &mut self,
+        found: (Index, LedgerIndex),
+        seq: LedgerIndex,
+        ancestry: F,
+        new_suffix: Option<Span<T>>,
+        count: Option<S>,
+        full: bool,
+    ) -> Result<Option<SpanTip<T>>, TrieError<S>> {
+        let (loc_idx, diff_seq) = found;
+        self._check_overflow(count.unwrap_or(S::one()))?;
+        self._check_descends_from_root(loc_idx, diff_seq)?;
+        if self.strict_ancestry {
+            if let Some(seq) = self._conflicting_seq(seq, ancestry, loc_idx, diff_seq) {
+                return Err(TrieError::ConflictingAncestry { seq });
+            }
+        }
+        if let Some(max_depth) = self.max_depth {
+            if self._depth_after_insert(loc_idx, diff_seq, seq) > max_depth {
+                return Err(TrieError::TooDeep { max_depth });
             }
         }
 
-        None
+        let inc_idx = match self._insert_at(loc_idx, diff_seq, new_suffix, seq, count, full) {
+            Some(inc_idx) => inc_idx,
+            None => return Ok(None),
+        };
+
+        let inc_node = self.arena.get(inc_idx).unwrap();
+        let tip = inc_node.span.tip();
+        if inc_node.children.is_empty() {
+            self.last_tip = Some(NodeHandle { idx: inc_idx, id: tip.id() });
+        }
+        Ok(Some(tip))
     }
-    /// Find the node in the trie that represents the longest common ancestry
-    /// with the given ledger.
+
+    /// Insert and/or increment the support for `ledger` like [`ArenaLedgerTrie::checked_insert`],
+    /// searching for where it belongs only below a node known to be its ancestor, such as the
+    /// node of a ledger that was just inserted.
     ///
-    /// # Return
-    /// A tuple of the found node's `Index` and the `LedgerIndex` of the first
-    /// ledger difference.
-    fn _find(&self, ledger: &T) -> (Index, LedgerIndex) {
-        // Root is always defined and is in common with all ledgers
-        let mut curr = self.arena.get(self.root).unwrap();
+    /// # Params
+    /// **parent** - A handle to a node whose tip is `ledger` or one of its ancestors.
+    ///
+    /// **ledger** - The ledger to insert.
+    ///
+    /// **count** - The count of support for this ledger.
+    ///
+    /// # Returns
+    /// `Err(TrieError::InvalidParent)` without changing the trie if `parent` no longer resolves
+    /// or its tip is not an ancestor of `ledger`, or any error of
+    /// [`ArenaLedgerTrie::checked_insert`].
+    pub fn insert_under(
+        &mut self,
+        parent: NodeHandle<T::IdType>,
+        ledger: &T,
+        count: Option<S>,
+    ) -> Result<(), TrieError<S>> {
+        let tip = self.resolve(parent).ok_or(TrieError::InvalidParent)?;
+        if ledger.seq() < tip.seq() || ledger.get_ancestor(tip.seq()) != tip.id() {
+            return Err(TrieError::InvalidParent);
+        }
 
-        let mut pos = curr.span.diff(ledger);
+        let found = self._find_below(parent.idx, |span| span.diff(ledger));
+        let new_suffix = Span::from(ledger.clone()).after(found.1);
+        self._insert_found(found, ledger.seq(), |s| ledger.get_ancestor(s), new_suffix, count, true)?;
+        Ok(())
+    }
 
-        let mut done = false;
+    /// Return the most nodes below the root on the paths through the node at `loc_idx` once a
+    /// ledger with sequence number `seq` that differs from it at `diff_seq` is inserted there.
+    ///
+    /// Takes time in proportion to the depth of the node, using the height kept by each node
+    /// for the paths below it.
+    fn _depth_after_insert(&self, loc_idx: Index, diff_seq: LedgerIndex, seq: LedgerIndex) -> usize {
+        let mut depth = 0;
+        let mut curr = self.arena.get(loc_idx).unwrap();
+        while let Some(parent) = curr.parent {
+            depth += 1;
+            curr = self.arena.get(parent).unwrap();
+        }
 
-        // Continue searching for a better span as long as the current position
-        // matches the entire span
-        while !done && pos == curr.span.end() {
-            done = true;
+        // Splitting the node moves its descendants one level down, and any part of the ledger
+        // past diff_seq becomes a new child
+        let split = diff_seq < self.arena.get(loc_idx).unwrap().span.end();
+        let below = match (split, diff_seq <= seq) {
+            (true, _) => 1 + self.arena.get(loc_idx).unwrap().height,
+            (false, true) => 1,
+            (false, false) => 0,
+        };
+        depth + below
+    }
 
-            for child_idx in &curr.children {
-                let child = self.arena.get(*child_idx).unwrap();
-                let child_pos = child.span.diff(ledger);
+    /// Return the first sequence number at which a ledger with sequence number `seq` and the
+    /// given ancestry contradicts the ancestry of the node at `loc_idx`, which it is expected
+    /// to match before `diff_seq` and differ from after.
+    ///
+    /// Only the spans of the nodes below the root on the path down to `loc_idx` are checked.
+    /// Every ledger in the trie descends from the root's tip, so the trie has nothing to say
+    /// about the ledgers of the root's span.
+    fn _conflicting_seq<F: Fn(LedgerIndex) -> T::IdType>(
+        &self,
+        seq: LedgerIndex,
+        ancestry: F,
+        loc_idx: Index,
+        diff_seq: LedgerIndex,
+    ) -> Option<LedgerIndex> {
+        let first = self.arena.get(self.root).unwrap().span.end();
+        let tip = self.arena.get(loc_idx).unwrap().span.tip();
+        let last = core::cmp::min(seq, tip.seq());
+        (first..=last).find(|s| (ancestry(*s) == tip.ancestor(*s)) != (*s < diff_seq))
+    }
 
-                if child_pos > pos {
-                    done = false;
-                    pos = child_pos;
-                    curr = child;
-                    break;
+    /// Insert each ledger with a precomputed count of support, for example when warm starting
+    /// from persisted counts. The result is the same as inserting each ledger with its count.
+    ///
+    /// Counts for the same ledger are summed first, so each distinct ledger is inserted once,
+    /// with its own search of the trie. Ledgers are inserted in sequence order, so the trie
+    /// does not depend on the order of `counts`.
+    ///
+    /// # Returns
+    /// `Err(TrieError::SupportOverflow)` without changing the trie if the counts of a ledger
+    /// sum to more than `S` can count, or the first error of
+    /// [`ArenaLedgerTrie::checked_insert`], in which case the ledgers before it in sequence
+    /// order stay inserted.
+    pub fn bootstrap(&mut self, counts: impl IntoIterator<Item = (T, S)>) -> Result<(), TrieError<S>> {
+        let mut by_id: HashMap<T::IdType, (T, S), H> = HashMap::with_hasher(self.hasher.clone());
+        for (ledger, count) in counts {
+            match by_id.get_mut(&ledger.id()) {
+                Some((_, total)) => {
+                    *total = match total.checked_add(count) {
+                        Some(sum) => sum,
+                        None => return Err(TrieError::SupportOverflow { total: *total, requested: count }),
+                    };
+                }
+                None => {
+                    by_id.insert(ledger.id(), (ledger, count));
                 }
             }
         }
 
-        (curr.idx, pos)
+        let mut ledgers: Vec<(T, S)> = by_id.into_values().collect();
+        ledgers.sort_by_key(|(ledger, _)| (ledger.seq(), ledger.id()));
+        for (ledger, count) in ledgers {
+            self.checked_insert(&ledger, Some(count))?;
+        }
+        Ok(())
     }
 
+    /// Insert and/or increment the support for `ledger` like [`LedgerTrie::insert`], but return
+    /// an error instead of panicking if the total support in the trie would overflow `S`.
+    ///
+    /// # Returns
+    /// `Err(TrieError::SupportOverflow)` without changing the trie on overflow, or
+    /// `Err(TrieError::ConflictingAncestry)` without changing the trie if it was created
+    /// [`ArenaLedgerTrie::with_strict_ancestry`] and the ledger's ancestry conflicts with it, or
+    /// `Err(TrieError::TooDeep)` if it was created [`ArenaLedgerTrie::with_max_depth`] and the
+    /// ledger would exceed it.
+    pub fn checked_insert(&mut self, ledger: &T, count: Option<S>) -> Result<(), TrieError<S>> {
+        self.insert_get(ledger, count)?;
+        Ok(())
+    }
 
-    pub fn empty(&self) -> bool {
-        return self.arena.get(self.root).unwrap().branch_support == 0;
+    /// Insert and/or increment the support for `ledger` like
+    /// [`ArenaLedgerTrie::checked_insert`], first checking that a [`Span`] of its ancestry makes
+    /// sense, see [`Span::try_from_ledger`].
+    ///
+    /// # Returns
+    /// `Err(TrieError::InvalidSpan)` without changing the trie if the ledger can't be spanned,
+    /// or any error of [`ArenaLedgerTrie::checked_insert`].
+    pub fn try_insert(&mut self, ledger: &T, count: Option<S>) -> Result<(), TrieError<S>> {
+        let span = Span::try_from_ledger(ledger.clone()).map_err(TrieError::InvalidSpan)?;
+        self._insert_span(ledger, span, count)?;
+        Ok(())
     }
 
-    pub fn check_invariants(&self) -> bool {
-        let mut expected_seq_support: BTreeMap<LedgerIndex, u32> = BTreeMap::new();
+    /// Return a handle to the node whose tip is the given ledger, or `None` if no node's tip is
+    /// the ledger.
+    pub fn handle(&self, id: T::IdType) -> Option<NodeHandle<T::IdType>> {
+        self._find_by_ledger_id(id, None, None).map(|idx| NodeHandle { idx, id })
+    }
 
-        let mut nodes: Vec<&Node<T>> = vec![];
-        nodes.push(self.arena.get(self.root).unwrap());
-        while !nodes.is_empty() {
-            let curr = nodes.pop();
-            if let Some(curr) = curr {
+    /// Return the tip of the node referred to by `handle`, or `None` if the node has since been
+    /// removed or no longer ends with the same ledger. A split leaves the node ending at the
+    /// inserted ancestor, so its handles resolve to `None` even though the ledger is still a
+    /// tip; make a new handle with [`ArenaLedgerTrie::handle`]. The same goes for handles made
+    /// before [`ArenaLedgerTrie::compact`], which resolve to `None` unless their node moved
+    /// back to the same index.
+    pub fn resolve(&self, handle: NodeHandle<T::IdType>) -> Option<SpanTip<T>> {
+        let tip = self.arena.get(handle.idx)?.span.tip();
+        if tip.id() != handle.id {
+            return None;
+        }
+        Some(tip)
+    }
 
-                // Node with 0 tip support must have multiple children
-                // unless it is the root node
-                if curr.idx != self.root && curr.tip_support == 0 && curr.children.len() < 2 {
-                    return false;
-                }
+    /// Return the tips of the nodes on the path from the root down to the node whose tip is
+    /// the given ledger.
+    ///
+    /// # Params
+    /// **id** - The ID of the ledger.
+    ///
+    /// # Returns
+    /// The `SpanTip`s of the root, each intermediate node and the matching node, in that order,
+    /// or `None` if no node's tip is the ledger.
+    pub fn path_to(&self, id: T::IdType) -> Option<Vec<SpanTip<T>>> {
+        let path = self._path_to(id);
+        if path.is_empty() {
+            return None;
+        }
 
-                // branchSupport = tipSupport + sum(child->branchSupport)
-                let mut support = curr.tip_support;
-                if curr.tip_support != 0 {
-                    let curr_support_value = expected_seq_support.get(&(curr.span.end() - 1)).unwrap_or_else(|| &0);
-                    expected_seq_support.insert(
-                        curr.span.end() - 1,
-                        *curr_support_value + curr.tip_support
-                    );
-                }
+        Some(path.iter().map(|node| node.span.tip()).collect())
+    }
 
-                for child in &curr.children {
-                    let child_node = self.arena.get(*child).unwrap();
-                    if child_node.parent != Some(curr.idx) {
-                        return false;
-                    }
+    /// Return the support profile along the path from the root down to the node whose tip is
+    /// the given ledger.
+    ///
+    /// # Params
+    /// **id** - The ID of the ledger.
+    ///
+    /// # Returns
+    /// The tip sequence number and branch support of the root, each intermediate node and the
+    /// matching node, in that order, or an empty `Vec` if no node's tip is the ledger. Branch
+    /// support never increases along the path.
+    pub fn ancestor_support(&self, id: T::IdType) -> Vec<(LedgerIndex, S)> {
+        self._path_to(id).iter()
+            .map(|node| (node.span.end() - 1, node.branch_support))
+            .collect()
+    }
 
-                    support += child_node.branch_support;
-                    nodes.push(child_node);
-                }
+    /// Return the chain of ledgers the walk for the preferred ledger followed, see
+    /// [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The tip of the root and of each node the walk passed through, followed by the preferred
+    /// ledger, or an empty `Vec` if no preferred ledger exists.
+    pub fn preferred_chain(&self, largest_issued: LedgerIndex) -> Vec<SpanTip<T>> {
+        let preferred = match self._get_preferred(largest_issued) {
+            Some(walk) => walk.tip,
+            None => return vec![],
+        };
 
-                if support != curr.branch_support {
-                    return false;
-                }
-            }
+        let mut chain = vec![];
+        let mut curr = self.arena.get(self.root).unwrap();
+        while preferred.seq() >= curr.span.end() {
+            chain.push(curr.span.tip());
+            // Every child starts just after this node's tip
+            let start_id = preferred.ancestor(curr.span.end());
+            curr = curr.children.iter()
+                .map(|child| self.arena.get(*child).unwrap())
+                .find(|child| child.span.start_id() == start_id)
+                .unwrap();
         }
-
-        expected_seq_support == self.seq_support
+        chain.push(preferred);
+        chain
     }
-}
 
-struct NodeAndArena<'a, T: Ledger> {
-    node: &'a Node<T>,
-    arena: &'a Arena<Node<T>>
-}
+    /// Return the nodes from the root down to the node whose tip is the given ledger, or an
+    /// empty `Vec` if there is no such node.
+    fn _path_to(&self, id: T::IdType) -> Vec<&Node<T, S>> {
+        let mut curr = self._find_by_ledger_id(id, None, None);
 
-impl<'a, T: Ledger> Serialize for NodeAndArena<'a, T> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-        let mut state = serializer.serialize_struct("Node", 6)?;
-        state.serialize_field("span", &self.node.span.to_string())?;
-        state.serialize_field("start_id", &self.node.span.start_id())?;
-        state.serialize_field("seq", &self.node.span.tip().seq())?;
-        state.serialize_field("tip_support", &self.node.tip_support)?;
-        state.serialize_field("branch_support", &self.node.branch_support)?;
-        if !self.node.children.is_empty() {
-            let child_nodes: Vec<NodeAndArena<T>> = self.node.children.iter()
-                .map(|c| self.arena.get(*c).unwrap())
-                .map(|n| NodeAndArena {
-                    node: n,
-                    arena: &self.arena,
-                })
-                .collect();
-            state.serialize_field("children", &child_nodes)?;
+        let mut path = vec![];
+        while let Some(idx) = curr {
+            let node = self.arena.get(idx).unwrap();
+            path.push(node);
+            curr = node.parent;
         }
-        state.end()
+
+        path.reverse();
+        path
     }
-}
 
-impl<T: Ledger> Serialize for ArenaLedgerTrie<T> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-        let mut state = serializer.serialize_struct("trie", 2)?;
+    /// Return the branch support of the tip ledger of `tip`, such as one returned by
+    /// [`LedgerTrie::get_preferred`].
+    ///
+    /// Unlike [`LedgerTrie::branch_support`], which searches the trie for the ledger, this
+    /// follows the tip's ancestry down from the root, only looking at the first ledger of the
+    /// children along the way.
+    pub fn branch_support_of_tip(&self, tip: &SpanTip<T>) -> S {
+        let mut curr = self.arena.get(self.root).unwrap();
+        while tip.seq() >= curr.span.end() {
+            // Every child starts just after this node's tip
+            let start_id = tip.ancestor(curr.span.end());
+            let next = curr.children.iter()
+                .map(|child| self.arena.get(*child).unwrap())
+                .find(|child| child.span.start_id() == start_id);
+            match next {
+                Some(child) => curr = child,
+                None => return S::zero(),
+            }
+        }
 
-        let root = self.arena.get(self.root)
-            .map(|root| NodeAndArena {
-                node: root,
-                arena: &self.arena,
-            }).unwrap();
-        state.serialize_field("trie", &root)?;
-        state.serialize_field("seq_support", &self.seq_support)?;
-        state.end()
+        match curr.span.before(tip.seq() + 1) {
+            Some(span) if span.tip().id() == tip.id() => curr.branch_support,
+            _ => S::zero(),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use rand::distributions::{Distribution, Uniform};
-    use rand::SeedableRng;
-    use xrpl_consensus_core::Ledger;
+    /// Return the preferred ledger only if its branch support has reached a quorum.
+    ///
+    /// This distinguishes a preferred ledger with enough support to act on from one that is
+    /// merely the best of the currently insufficient support, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// **quorum**: The minimum branch support of the preferred ledger.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the preferred ledger or `None` if no preferred ledger exists or its
+    /// branch support is less than `quorum`.
+    pub fn get_preferred_confident(&self, largest_issued: LedgerIndex, quorum: S) -> Option<SpanTip<T>> {
+        self._get_preferred(largest_issued)
+            .filter(|walk| walk.support >= quorum)
+            .map(|walk| walk.tip)
+    }
 
-    use crate::arena_ledger_trie::ArenaLedgerTrie;
-    use crate::ledger_trie::LedgerTrie;
-    use crate::test_utils::ledgers::{LedgerHistoryHelper, SimulatedLedger};
+    /// Find the preferred ledger among the descendants of an anchor ledger.
+    ///
+    /// Branches that do not include the anchor are never preferred, but their support is not
+    /// ignored. As in [`LedgerTrie::get_preferred`], the support for every ledger with a
+    /// sequence number before the one the walk is at counts as uncommitted, wherever in the
+    /// trie that ledger is, since those validators may yet validate a descendant of the anchor.
+    ///
+    /// # Params
+    /// **anchor**: The ID of a ledger at the tip of a node, such as an already validated
+    /// checkpoint.
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the preferred ledger, which is the anchor or one of its descendants,
+    /// or `None` if no node's tip is the anchor or the trie is empty. If the anchor diverges
+    /// from the locked ledger, see [`ArenaLedgerTrie::set_locked`], the preferred ledger is
+    /// found within the lock instead.
+    pub fn get_preferred_from(&self, anchor: T::IdType, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        let start = self._find_by_ledger_id(anchor, None, None)?;
+        self._get_preferred_from(start, largest_issued, None, 0, &BTreeMap::new(), None).map(|walk| walk.tip)
+    }
 
-    #[test]
-    fn test_json() {
-        let (mut trie, mut h) = setup();
-        let ledger = h.get_or_create("abc");
-        insert(&mut trie, &ledger, None);
-        println!("{}", serde_json::to_string_pretty(&trie).unwrap());
+    /// Return whether the preferred ledger is no longer the one at the tip of `previous`, for
+    /// example a tip returned by an earlier call to [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **previous**: The previously preferred ledger.
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// `true` if the preferred ledger has a different ID or sequence number than `previous`, or
+    /// there is no longer a preferred ledger.
+    pub fn preferred_changed_since(&self, previous: &SpanTip<T>, largest_issued: LedgerIndex) -> bool {
+        !matches!(
+            self._get_preferred(largest_issued),
+            Some(walk) if walk.tip.id() == previous.id() && walk.tip.seq() == previous.seq()
+        )
     }
 
-    #[test]
-    fn test_insert_single_entry() {
+    /// Find the preferred ledger given a previously preferred ledger, which in steady state is
+    /// usually still preferred or an ancestor of the preferred ledger.
+    ///
+    /// This is the walk of [`LedgerTrie::get_preferred`], except that at each fork on the path
+    /// to `prev`, one pass over the children checks whether `prev`'s ancestor still leads,
+    /// rather than sorting them.
+    ///
+    /// # Params
+    /// **prev**: The previously preferred ledger, for example from an earlier call to
+    /// [`LedgerTrie::get_preferred`].
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The same ledger as [`LedgerTrie::get_preferred`], whether or not `prev` is still on the
+    /// path to it.
+    pub fn get_preferred_incremental(&self, prev: &SpanTip<T>, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self._get_preferred_from(self.root, largest_issued, None, 0, &BTreeMap::new(), Some(prev))
+            .map(|walk| walk.tip)
+    }
+
+    /// Return the preferred ledger as if one validation for the ledger `exclude` were removed,
+    /// so that the local node's own validation does not bias the result towards its branch.
+    ///
+    /// # Params
+    /// **exclude**: The ID of the ledger whose tip support is reduced by one. If no ledger with
+    /// this ID has tip support, this is the same as [`LedgerTrie::get_preferred`].
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    pub fn get_preferred_excluding(&self, exclude: T::IdType, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        let excluded = self._find_by_ledger_id(exclude, None, None)
+            .filter(|idx| self.arena.get(*idx).unwrap().tip_support != S::zero());
+        self._get_preferred_from(self.root, largest_issued, excluded, 0, &BTreeMap::new(), None).map(|walk| walk.tip)
+    }
+
+    /// Return the preferred ledger, treating ledgers before `min_seq` as already validated.
+    ///
+    /// Support for ledgers before `min_seq` is committed to them rather than uncommitted, so it
+    /// can't hold the preferred ledger back on earlier history, see
+    /// [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **min_seq**: The minimum sequence number of the preferred ledger.
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the preferred ledger, or `None` if no preferred ledger exists or its
+    /// sequence number is less than `min_seq`.
+    pub fn get_preferred_min_seq(&self, min_seq: LedgerIndex, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self._get_preferred_from(self.root, largest_issued, None, min_seq, &BTreeMap::new(), None)
+            .map(|walk| walk.tip)
+            .filter(|tip| tip.seq() >= min_seq)
+    }
+
+    /// Return the preferred ledger as if the support at some sequence numbers were different,
+    /// for example to model votes a caller expects to arrive.
+    ///
+    /// The cutoffs only change the support counted as uncommitted when choosing between
+    /// children, see [`LedgerTrie::get_preferred`]. The branch support of each ledger, and the
+    /// support in the trie, are unchanged.
+    ///
+    /// # Params
+    /// **cutoffs**: The support to count at each sequence number in place of the support of the
+    /// ledgers in the trie with that sequence number. Sequence numbers not in `cutoffs` keep
+    /// their support.
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    pub fn get_preferred_with_cutoffs(
+        &self,
+        cutoffs: &BTreeMap<LedgerIndex, S>,
+        largest_issued: LedgerIndex,
+    ) -> Option<SpanTip<T>> {
+        self._get_preferred_from(self.root, largest_issued, None, 0, cutoffs, None)
+            .map(|walk| walk.tip)
+    }
+
+    /// Return the preferred ledger as if more validators had yet to validate past a sequence
+    /// number, for example when a caller knows of validations that have not reached the trie.
+    ///
+    /// This is [`ArenaLedgerTrie::get_preferred_with_cutoffs`] with a single cutoff of the
+    /// trie's support at the sequence number plus the extra support, and also leaves the trie
+    /// unchanged.
+    ///
+    /// # Params
+    /// **extra_uncommitted**: A sequence number and the support to add to the support of the
+    /// ledgers in the trie with that sequence number. The extra support is uncommitted when
+    /// choosing between children that start after that sequence number.
+    ///
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The preferred ledger, if any, or `Err(TrieError::SupportOverflow)` if the support at the
+    /// sequence number plus the extra support is more than `S` can count.
+    pub fn get_preferred_with_extra(
+        &self,
+        extra_uncommitted: (LedgerIndex, S),
+        largest_issued: LedgerIndex,
+    ) -> Result<Option<SpanTip<T>>, TrieError<S>> {
+        let (seq, extra) = extra_uncommitted;
+        let support = self.seq_support.get(&seq).copied().unwrap_or(S::zero());
+        let uncommitted = support.checked_add(extra)
+            .ok_or(TrieError::SupportOverflow { total: support, requested: extra })?;
+        let overrides = BTreeMap::from([(seq, uncommitted)]);
+        Ok(self._get_preferred_from(self.root, largest_issued, None, 0, &overrides, None)
+            .map(|walk| walk.tip))
+    }
+
+    /// Find the preferred ledger, reporting whether the search stopped short because of a tie.
+    ///
+    /// [`LedgerTrie::get_preferred`] stops at a ledger whose children are tied in branch support
+    /// when the tie-breaking margin does not exceed the uncommitted support, so the preferred
+    /// ledger cannot advance until the tie is broken. This reports those children instead.
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// [`PreferredResult::Tied`] with the tips of the tied children if the search stopped at a
+    /// tie, otherwise the preferred ledger or [`PreferredResult::Empty`] if there is none.
+    pub fn get_preferred_diagnostic(&self, largest_issued: LedgerIndex) -> PreferredResult<T> {
+        match self._get_preferred(largest_issued) {
+            None => PreferredResult::Empty,
+            Some(walk) if walk.ties.is_empty() => PreferredResult::Decided(walk.tip),
+            Some(walk) => PreferredResult::Tied {
+                candidates: walk.ties.iter()
+                    .map(|idx| self.arena.get(*idx).unwrap().span.tip())
+                    .collect()
+            },
+        }
+    }
+
+    /// Find the preferred ledger, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Returns
+    /// The walk that found the preferred ledger, or `None` if no preferred ledger exists.
+    fn _get_preferred(&self, largest_issued: LedgerIndex) -> Option<PreferredWalk<T, S>> {
+        self._get_preferred_from(self.root, largest_issued, None, 0, &BTreeMap::new(), None)
+    }
+
+    /// Find the preferred ledger among the node at `start` and its descendants like
+    /// [`ArenaLedgerTrie::_walk`], keeping within the locked ledger, see
+    /// [`ArenaLedgerTrie::_walk_within_lock`].
+    ///
+    /// The preferred ledger is always one whose ID is known, see
+    /// [`SpannableLedger::known_ancestor_seq`].
+    fn _get_preferred_from(
+        &self,
+        start: Index,
+        largest_issued: LedgerIndex,
+        excluded: Option<Index>,
+        min_seq: LedgerIndex,
+        overrides: &BTreeMap<LedgerIndex, S>,
+        hint: Option<&SpanTip<T>>,
+    ) -> Option<PreferredWalk<T, S>> {
+        let mut walk = self._walk_within_lock(start, largest_issued, excluded, min_seq, overrides, hint)?;
+
+        // The walk can stop anywhere within a span, so for a ledger that only knows sparse
+        // ancestry, step back to the closest ledger whose ID it knows
+        let known = walk.tip.known();
+        if known.seq() != walk.tip.seq() {
+            walk.support = self.branch_support_of_tip(&known);
+            walk.tip = known;
+        }
+        Some(walk)
+    }
+
+    /// Find the preferred ledger like [`ArenaLedgerTrie::_walk`], keeping within the locked
+    /// ledger, see [`ArenaLedgerTrie::set_locked`].
+    ///
+    /// If the walk from `start` reaches a ledger that does not descend from the locked ledger,
+    /// it starts over from the node holding the locked ledger with the same parameters.
+    fn _walk_within_lock(
+        &self,
+        start: Index,
+        largest_issued: LedgerIndex,
+        excluded: Option<Index>,
+        min_seq: LedgerIndex,
+        overrides: &BTreeMap<LedgerIndex, S>,
+        hint: Option<&SpanTip<T>>,
+    ) -> Option<PreferredWalk<T, S>> {
+        let start_seq = self.arena.get(start).unwrap().span.end();
+        let preferred = self._walk((start, start_seq), largest_issued, excluded, min_seq, overrides, hint);
+        let (locked, walk) = match (&self.locked, &preferred) {
+            (Some(locked), Some(walk)) => (locked, walk),
+            _ => return preferred,
+        };
+        if walk.tip.ancestor_id(locked.seq()) == Some(locked.id()) {
+            return preferred;
+        }
+
+        let (loc_idx, diff_seq) = self._find_by(|s| s.diff_by(locked.seq(), |seq| locked.ancestor(seq)));
+        if diff_seq <= locked.seq() {
+            // Nothing descends from the locked ledger anymore
+            return Some(PreferredWalk {
+                tip: locked.clone(),
+                support: S::zero(),
+                ties: vec![],
+                margin: S::zero(),
+                path: vec![],
+            });
+        }
+
+        // The locked ledger is in the span of the node at loc_idx, so everything the walk can
+        // reach from there past the locked ledger descends from it. The rest of the span after
+        // the locked ledger still has to outweigh the uncommitted support.
+        match self._walk((loc_idx, locked.seq() + 1), largest_issued, excluded, min_seq, overrides, hint) {
+            Some(walk) if walk.tip.seq() >= locked.seq() => Some(walk),
+            _ => Some(PreferredWalk {
+                tip: locked.clone(),
+                support: self.arena.get(loc_idx).unwrap().branch_support,
+                ties: vec![],
+                margin: S::zero(),
+                path: vec![],
+            }),
+        }
+    }
+
+    /// Find the preferred ledger among the node at `start` and its descendants, ignoring any
+    /// locked ledger, see [`ArenaLedgerTrie::_get_preferred`].
+    ///
+    /// The ledgers of the start node's span before `start_seq` are always committed, and the
+    /// walk only moves past the ledgers from `start_seq` on while they outweigh the uncommitted
+    /// support. Starting at the end of the span commits to all of it.
+    ///
+    /// If `excluded` is the index of a node with tip support, the search runs as if that node
+    /// had one less tip support. Support for ledgers before `min_seq` is never uncommitted.
+    /// Uncommitted support is counted from the trie's support at each sequence number, except
+    /// at the sequence numbers in `overrides`, which is usually empty.
+    ///
+    /// `hint` is a ledger the walk is expected to pass, such as the previously preferred
+    /// ledger. At a fork on the path to it, the walk first checks whether the hint's ancestor
+    /// leads the other children in one pass, and only sorts the children if it does not.
+    fn _walk(
+        &self,
+        (start, start_seq): (Index, LedgerIndex),
+        largest_issued: LedgerIndex,
+        excluded: Option<Index>,
+        min_seq: LedgerIndex,
+        overrides: &BTreeMap<LedgerIndex, S>,
+        hint: Option<&SpanTip<T>>,
+    ) -> Option<PreferredWalk<T, S>> {
+        // The excluded support counts towards the branch support of every node from the
+        // excluded node up to the root, and towards the seq support of its tip
+        let mut excluded_path = vec![];
+        let mut excluded_seq = None;
+        if let Some(idx) = excluded {
+            excluded_seq = Some(self.arena.get(idx).unwrap().span.end() - 1);
+            let mut curr = Some(idx);
+            while let Some(idx) = curr {
+                excluded_path.push(idx);
+                curr = self.arena.get(idx).unwrap().parent;
+            }
+        }
+        let branch_support = |node: &Node<T, S>| if excluded_path.contains(&node.idx) {
+            node.branch_support - S::one()
+        } else {
+            node.branch_support
+        };
+
+        if branch_support(self.arena.get(self.root).unwrap()) == S::zero() {
+            return None;
+        }
+
+        let mut curr = self.arena.get(start);
+        let mut done = false;
+
+        let mut uncommitted = S::zero();
+
+        let mut uncommitted_it = self._seq_support_with(overrides)
+            .map(|(seq, support)| match excluded_seq {
+                Some(excluded_seq) if excluded_seq == seq => (seq, support - S::one()),
+                _ => (seq, support),
+            })
+            .filter(|(seq, support)| *seq >= min_seq && *support != S::zero());
+        let mut next = uncommitted_it.next();
+
+        let mut ties = vec![];
+        let mut fork_margin = S::zero();
+        let mut path = vec![];
+
+        while curr.is_some() && !done {
+            // Within a single span, the preferred by branch strategy is simply
+            // to continue along the span as long as the branch support of
+            // the next ledger exceeds the uncommitted support for that ledger.
+
+            {
+                // Add any initial uncommitted support prior for ledgers
+                // earlier than nextSeq or earlier than largestIssued. Every candidate
+                // descends from the start node, so its span is committed up to start_seq.
+                let mut next_seq = if curr.unwrap().idx == start {
+                    start_seq
+                } else {
+                    curr.unwrap().span.start() + 1
+                };
+                while let Some((seq, support)) = next {
+                    if seq < core::cmp::max(next_seq, largest_issued) {
+                        uncommitted += support;
+                        next = uncommitted_it.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                // Advance next_seq along the span
+                while next_seq < curr.unwrap().span.end() &&
+                    branch_support(curr.unwrap()) > uncommitted {
+                    // Jump to the next seq_support change.
+                    if let Some((seq, support)) = next {
+                        if seq < curr.unwrap().span.end() {
+                            next_seq = seq + 1;
+                            uncommitted += support;
+                            next = uncommitted_it.next();
+                        } else {
+                            // Otherwise we jump to the end of the span
+                            next_seq = curr.unwrap().span.end();
+                        }
+                    } else {
+                        // Otherwise we jump to the end of the span
+                        next_seq = curr.unwrap().span.end();
+                    }
+                }
+
+                // We did not consume the entire span, so we have found the
+                // preferred ledger
+                if next_seq < curr.unwrap().span.end() {
+                    return Some(PreferredWalk {
+                        tip: curr.unwrap().span.before(next_seq)?.tip(),
+                        support: branch_support(curr.unwrap()),
+                        ties: vec![],
+                        margin: S::zero(),
+                        path,
+                    });
+                }
+            }
+
+            // We have reached the end of the current span, so we need to
+            // find the best child
+            let mut margin = S::zero();
+            let mut best: Option<&Node<T, S>> = None;
+            let mut tied: Vec<Index> = vec![];
+            if curr.unwrap().children.len() == 1 {
+                let child = self.arena.get(*curr.unwrap().children.first().unwrap()).unwrap();
+                // An only child left without support by the exclusion is not a candidate
+                if branch_support(child) != S::zero() {
+                    best = Some(child);
+                    margin = branch_support(child);
+                }
+            } else if !curr.unwrap().children.is_empty() { // Children length > 1
+                // Sort placing children with largest branch support in the front,
+                // breaking ties with the span's starting ID
+
+                // NOTE: In C++, they sort the actual node's children vector.
+                //  In rust, we can't get a mutable reference to curr because then
+                //  we'd have a mutable reference to self.arena at the same time as having
+                //  a shared reference to self.arena. Therefore, this code sorts a temporary
+                //  clone of curr.children but does not update curr.children, unless they are
+                //  already in order, for example after `rebalance`
+                let by_support = |&index1: &Index, &index2: &Index| {
+                    let node1 = self.arena.get(index1).unwrap();
+                    let node2 = self.arena.get(index2).unwrap();
+                    let cmp = branch_support(node2).cmp(&branch_support(node1));
+                    match cmp {
+                        Ordering::Equal => {
+                            node2.span.start_id().cmp(&node1.span.start_id())
+                        }
+                        _ => cmp
+                    }
+                };
+                let children = &curr.unwrap().children;
+
+                // The hint's ancestor leads if it has more branch support than every other
+                // child, in which case there is no tie to report either
+                let end = curr.unwrap().span.end();
+                let leading = hint
+                    .filter(|hint| hint.seq() >= end)
+                    .and_then(|hint| {
+                        let start_id = hint.ancestor(end);
+                        children.iter().find(|c| self.arena.get(**c).unwrap().span.start_id() == start_id)
+                    })
+                    .and_then(|leader| {
+                        let second = children.iter().filter(|c| *c != leader).min_by(|a, b| by_support(a, b))?;
+                        let (leader_node, second_node) = (self.arena.get(*leader).unwrap(), self.arena.get(*second).unwrap());
+                        (branch_support(leader_node) > branch_support(second_node)).then_some((leader_node, second_node))
+                    });
+
+                let (first_child, second_child) = match leading {
+                    Some(leading) => leading,
+                    None => {
+                        let sorted_children;
+                        let children_to_sort = if children.windows(2).all(|w| by_support(&w[0], &w[1]) != Ordering::Greater) {
+                            children
+                        } else {
+                            sorted_children = {
+                                let mut sorted = children.clone();
+                                sorted.sort_by(by_support);
+                                sorted
+                            };
+                            &sorted_children
+                        };
+
+                        let first_child = self.arena.get(*children_to_sort.first().unwrap()).unwrap();
+                        let second_child = self.arena.get(*children_to_sort.get(1).unwrap()).unwrap();
+                        if branch_support(first_child) == branch_support(second_child) {
+                            tied = children_to_sort.iter()
+                                .take_while(|c| branch_support(self.arena.get(**c).unwrap()) == branch_support(first_child))
+                                .copied()
+                                .collect();
+                        }
+                        (first_child, second_child)
+                    }
+                };
+                best = Some(first_child);
+                margin = branch_support(first_child) - branch_support(second_child);
+
+                // If best holds the tie-breaker, gets one larger margin
+                // since the second best needs additional branchSupport
+                // to overcome the tie
+                if best.unwrap().span.start_id() > second_child.span.start_id() {
+                    margin += S::one();
+                }
+                fork_margin = margin;
+            }
+
+            // If the best child has margin exceeding the uncommitted support,
+            // continue from that child, otherwise we are done
+            match best {
+                Some(best) if (margin > uncommitted) || (uncommitted == S::zero()) => {
+                    path.push((best.idx, uncommitted));
+                    curr = Some(best);
+                }
+                _ => {
+                    done = true;
+                    ties = tied;
+                }
+            }
+        }
+
+        // A margin only counts if the walk stopped at the fork it was computed for
+        let margin = if curr.unwrap().children.len() > 1 { fork_margin } else { S::zero() };
+        let curr = curr.unwrap();
+        Some(PreferredWalk { tip: curr.span.tip(), support: branch_support(curr), ties, margin, path })
+    }
+
+    /// Iterate over the trie's support at each sequence number in order, with the support at
+    /// the sequence numbers in `overrides` replaced by theirs, without copying either map.
+    fn _seq_support_with<'a>(
+        &'a self,
+        overrides: &'a BTreeMap<LedgerIndex, S>,
+    ) -> impl Iterator<Item = (LedgerIndex, S)> + 'a {
+        let mut trie = self.seq_support.iter().peekable();
+        let mut overrides = overrides.iter().peekable();
+        core::iter::from_fn(move || {
+            let next = match (trie.peek(), overrides.peek()) {
+                (Some((seq, _)), Some((over, _))) if seq < over => trie.next(),
+                (Some((seq, _)), Some((over, _))) if seq == over => {
+                    trie.next();
+                    overrides.next()
+                }
+                (_, Some(_)) => overrides.next(),
+                (_, None) => trie.next(),
+            };
+            next.map(|(seq, support)| (*seq, *support))
+        })
+    }
+
+    fn _add_empty_and_get(&mut self, loc_idx: Index) -> (&mut Node<T, S>, &mut Node<T, S>) {
+        let children = self.free_children.pop().unwrap_or_default();
+        let new_node_idx = self.arena.insert_with(|idx| Node {
+            children,
+            ..Node::with_index(idx)
+        });
+
+        let (loc, new_node) = self.arena.get2_mut(loc_idx, new_node_idx);
+
+        (loc.unwrap(), new_node.unwrap())
+    }
+
+    /// Keep the `children` of a removed node for reuse by a new node, if it has allocated and
+    /// fewer than `MAX_FREE_CHILDREN` are already kept.
+    fn _recycle_children(&mut self, mut children: Vec<Index>) {
+        if children.capacity() != 0 && self.free_children.len() < MAX_FREE_CHILDREN {
+            children.clear();
+            self.free_children.push(children);
+        }
+    }
+
+    /// Find the node whose tip is the ledger with ID `ledger_id`. If the ledger's sequence
+    /// number `seq` is known, subtrees that start after it are skipped.
+    fn _find_by_ledger_id(
+        &self,
+        ledger_id: T::IdType,
+        seq: Option<LedgerIndex>,
+        parent: Option<&Index>,
+    ) -> Option<Index> {
+        let parent = match parent {
+            None => self.root,
+            Some(p) => *p
+        };
+
+        let parent_node = self.arena.get(parent).unwrap();
+        if ledger_id == parent_node.span.tip().id() {
+            return Some(parent);
+        }
+
+        // Every child starts just after this node's tip
+        if matches!(seq, Some(seq) if seq < parent_node.span.end()) {
+            return None;
+        }
+
+        for child in &parent_node.children {
+            let cl = self._find_by_ledger_id(ledger_id, seq, Some(&child));
+            if cl.is_some() {
+                return cl;
+            }
+        }
+
+        None
+    }
+    /// Find the node in the trie that represents the longest common ancestry
+    /// with the given ledger.
+    ///
+    /// # Return
+    /// A tuple of the found node's `Index` and the `LedgerIndex` of the first
+    /// ledger difference.
+    fn _find(&self, ledger: &T) -> (Index, LedgerIndex) {
+        self._find_by(|span| span.diff(ledger))
+    }
+
+    /// Find the node in the trie that represents the longest common ancestry
+    /// with a ledger, where `diff` returns the first difference between a
+    /// node's span and that ledger.
+    fn _find_by<F: Fn(&Span<T>) -> LedgerIndex>(&self, diff: F) -> (Index, LedgerIndex) {
+        // Root is always defined and is in common with all ledgers
+        self._find_below(self.root, diff)
+    }
+
+    /// Find the node that represents the longest common ancestry with a ledger like
+    /// [`ArenaLedgerTrie::_find_by`], searching only the node at `start` and its descendants.
+    fn _find_below<F: Fn(&Span<T>) -> LedgerIndex>(&self, start: Index, diff: F) -> (Index, LedgerIndex) {
+        let mut curr = self.arena.get(start).unwrap();
+
+        let mut pos = diff(&curr.span);
+
+        let mut done = false;
+
+        // Continue searching for a better span as long as the current position
+        // matches the entire span
+        while !done && pos == curr.span.end() {
+            done = true;
+
+            for child_idx in &curr.children {
+                let child = self.arena.get(*child_idx).unwrap();
+                let child_pos = diff(&child.span);
+
+                if child_pos > pos {
+                    done = false;
+                    pos = child_pos;
+                    curr = child;
+                    break;
+                }
+            }
+        }
+
+        (curr.idx, pos)
+    }
+
+
+    /// Return whether two tries have the same shape, with nodes for the same spans of ledger
+    /// history in the same parent/child topology, regardless of their support.
+    pub fn structural_eq<H2: BuildHasher + Clone>(&self, other: &ArenaLedgerTrie<T, S, H2>) -> bool {
+        self._structural_eq(self.root, other, other.root)
+    }
+
+    fn _structural_eq<H2: BuildHasher + Clone>(
+        &self,
+        idx: Index,
+        other: &ArenaLedgerTrie<T, S, H2>,
+        other_idx: Index,
+    ) -> bool {
+        let node = self.arena.get(idx).unwrap();
+        let other_node = other.arena.get(other_idx).unwrap();
+        if node.span.start() != other_node.span.start() ||
+            node.span.end() != other_node.span.end() ||
+            node.span.tip().id() != other_node.span.tip().id() ||
+            node.children.len() != other_node.children.len() {
+            return false;
+        }
+
+        // Siblings always start with different ledgers, so match children by their first ledger
+        node.children.iter().all(|child| {
+            let start_id = self.arena.get(*child).unwrap().span.start_id();
+            let other_child = other_node.children.iter()
+                .find(|other_child| other.arena.get(**other_child).unwrap().span.start_id() == start_id);
+            matches!(other_child, Some(other_child) if self._structural_eq(*child, other, *other_child))
+        })
+    }
+
+    /// Return the number of nodes in the trie, including the root, with each distinct amount of
+    /// tip support.
+    pub fn support_histogram(&self) -> BTreeMap<S, usize> {
+        let mut histogram = BTreeMap::new();
+
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            *histogram.entry(node.tip_support).or_insert(0) += 1;
+            nodes.extend_from_slice(&node.children);
+        }
+
+        histogram
+    }
+
+    /// Return the number of nodes other than the root with no children, which is the number of
+    /// distinct branches of ledger history in the trie.
+    pub fn leaf_count(&self) -> usize {
+        self._count_nodes(|node| node.idx != self.root && node.children.is_empty())
+    }
+
+    /// Return the number of nodes, including the root, with more than one child, which is the
+    /// number of ledgers where ledger history forks.
+    pub fn fork_count(&self) -> usize {
+        self._count_nodes(|node| node.children.len() > 1)
+    }
+
+    /// Sort the children of every node by descending branch support, breaking ties with the
+    /// larger starting ledger ID, which is the order [`LedgerTrie::get_preferred`] considers
+    /// them in.
+    ///
+    /// The walk for the preferred ledger sorts a copy of the children at each fork it reaches
+    /// unless they are already in order, so after a batch of changes this lets it skip the
+    /// copy until the support shifts again. The support in the trie is unchanged.
+    pub fn rebalance(&mut self) {
+        let forks: Vec<Index> = self.arena.iter()
+            .filter(|(_, node)| node.children.len() > 1)
+            .map(|(idx, _)| idx)
+            .collect();
+        for idx in forks {
+            let mut children = core::mem::take(&mut self.arena.get_mut(idx).unwrap().children);
+            children.sort_by_cached_key(|child| {
+                let child = self.arena.get(*child).unwrap();
+                Reverse((child.branch_support, child.span.start_id()))
+            });
+            self.arena.get_mut(idx).unwrap().children = children;
+        }
+    }
+
+    /// Return the leaf with the most branch support, which for a leaf is its own support.
+    ///
+    /// Unlike [`LedgerTrie::get_preferred`], this compares whole branches of history against
+    /// each other rather than walking down from the root, so it does not favor a branch whose
+    /// support is split among several forks near its tip, and it ignores uncommitted support.
+    /// Ties are broken in favor of the larger tip ID.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the leaf and its branch support, or `None` if the trie is empty.
+    pub fn most_supported(&self) -> Option<(SpanTip<T>, S)> {
+        self.arena.iter()
+            .map(|(_, node)| node)
+            .filter(|node| node.idx != self.root && node.children.is_empty())
+            .map(|node| (node.span.tip(), node.branch_support))
+            .max_by(|(a, a_support), (b, b_support)| a_support.cmp(b_support).then(a.id().cmp(&b.id())))
+    }
+
+    /// Return the tip IDs of each parent and child node in the trie, for rendering the trie with
+    /// graph tools. Parents come before their children. Format the IDs with
+    /// [`id_to_hex`](crate::ledger_trie::id_to_hex) to match the trie's other output.
+    pub fn edges(&self) -> Vec<(T::IdType, T::IdType)> {
+        let mut edges = vec![];
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            let parent_id = node.span.tip().id();
+            for child in &node.children {
+                edges.push((parent_id, self.arena.get(*child).unwrap().span.tip().id()));
+            }
+            nodes.extend_from_slice(&node.children);
+        }
+
+        edges
+    }
+
+    fn _count_nodes(&self, pred: impl Fn(&Node<T, S>) -> bool) -> usize {
+        let mut count = 0;
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            if pred(node) {
+                count += 1;
+            }
+            nodes.extend_from_slice(&node.children);
+        }
+
+        count
+    }
+
+    /// Return the fraction of the arena's slots that are not occupied by a node.
+    ///
+    /// Removing support frees nodes but never shrinks the arena, so this rises as the trie
+    /// churns. Call [`ArenaLedgerTrie::compact`] to reclaim the freed slots.
+    pub fn fragmentation(&self) -> f64 {
+        let capacity = self.arena.capacity();
+        if capacity == 0 {
+            return 0.0;
+        }
+
+        (capacity - self.arena.len()) as f64 / capacity as f64
+    }
+
+    /// Move every node reachable from the root into a new arena sized to fit them exactly, and
+    /// free the `children` kept from removed nodes.
+    ///
+    /// This moves nodes to new indices, so handles made before compacting should be made
+    /// again, see [`ArenaLedgerTrie::resolve`].
+    pub fn compact(&mut self) {
+        let mut reachable = vec![self.root];
+        let mut i = 0;
+        while i < reachable.len() {
+            reachable.extend_from_slice(&self.arena.get(reachable[i]).unwrap().children);
+            i += 1;
+        }
+
+        let mut arena = Arena::with_capacity(reachable.len());
+        let mut new_indices: HashMap<Index, Index, BuildHasherDefault<FxHasher>> =
+            HashMap::with_capacity_and_hasher(reachable.len(), Default::default());
+        for old_idx in reachable {
+            let node = self.arena.remove(old_idx).unwrap();
+            let new_idx = arena.insert_with(|idx| Node { idx, ..node });
+            new_indices.insert(old_idx, new_idx);
+        }
+
+        for (_, node) in arena.iter_mut() {
+            node.parent = node.parent.map(|parent| new_indices[&parent]);
+            node.children.iter_mut().for_each(|child| *child = new_indices[child]);
+        }
+
+        self.root = new_indices[&self.root];
+        self.arena = arena;
+        self.free_children = vec![];
+    }
+
+    /// Check the tip support of the trie's ledgers against the expected tip support of each
+    /// ledger ID. Ledgers missing from `expected` are expected to have no tip support.
+    ///
+    /// # Returns
+    /// `Err` with the ID, expected and actual tip support of each ledger that doesn't match,
+    /// ordered by ID.
+    pub fn verify_support<H2: BuildHasher>(
+        &self,
+        expected: &HashMap<T::IdType, S, H2>,
+    ) -> Result<(), Vec<(T::IdType, S, S)>> {
+        let mut actual: BTreeMap<T::IdType, S> = BTreeMap::new();
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            if node.tip_support != S::zero() {
+                actual.insert(node.span.tip().id(), node.tip_support);
+            }
+            nodes.extend_from_slice(&node.children);
+        }
+
+        let mut mismatches: Vec<(T::IdType, S, S)> = expected.iter()
+            .map(|(id, support)| (*id, *support, actual.get(id).copied().unwrap_or(S::zero())))
+            .chain(actual.iter()
+                .filter(|(id, _)| !expected.contains_key(*id))
+                .map(|(id, support)| (*id, S::zero(), *support)))
+            .filter(|(_, expected, actual)| expected != actual)
+            .collect();
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+
+        mismatches.sort_by_key(|(id, _, _)| *id);
+        Err(mismatches)
+    }
+
+    /// Write the trie to `w` as an indented tree, one node per line with its span and support,
+    /// without building the whole dump in memory first like `Debug` does.
+    #[cfg(feature = "std")]
+    pub fn write_tree(&self, w: &mut impl std::io::Write) -> std::io::Result<()> where T::IdType: LowerHex {
+        let mut nodes = vec![(self.root, 0)];
+        while let Some((idx, depth)) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            writeln!(
+                w,
+                "{:indent$}{:?} tip_support: {:?} branch_support: {:?}",
+                "",
+                node.span,
+                node.tip_support,
+                node.branch_support,
+                indent = 2 * depth,
+            )?;
+            // Push in reverse so children are written in order
+            nodes.extend(node.children.iter().rev().map(|child| (*child, depth + 1)));
+        }
+        Ok(())
+    }
+
+    /// Check the ancestry of every ledger in the trie against a trusted chain, for example to
+    /// catch validations for ledgers with malformed ancestry.
+    ///
+    /// # Params
+    /// **oracle** - Returns the ID of the trusted chain's ledger with the given sequence
+    /// number, or `None` if the chain does not reach that sequence number, in which case the
+    /// trie's ledgers with that sequence number are not checked.
+    ///
+    /// # Returns
+    /// `Err` with the smallest sequence number at which a ledger in the trie differs from the
+    /// trusted chain.
+    pub fn assert_consistent_with(
+        &self,
+        oracle: &impl Fn(LedgerIndex) -> Option<T::IdType>,
+    ) -> Result<(), LedgerIndex> {
+        let mut first_divergence: Option<LedgerIndex> = None;
+        let mut nodes = vec![self.root];
+        while let Some(idx) = nodes.pop() {
+            let node = self.arena.get(idx).unwrap();
+            let tip = node.span.tip();
+            // Every ledger after the first divergent one in the span diverges too
+            let divergence = node.span.seqs()
+                .find(|seq| matches!(oracle(*seq), Some(id) if id != tip.ancestor(*seq)));
+            if let Some(seq) = divergence {
+                first_divergence = Some(first_divergence.map_or(seq, |first| core::cmp::min(first, seq)));
+            }
+            nodes.extend_from_slice(&node.children);
+        }
+
+        match first_divergence {
+            None => Ok(()),
+            Some(seq) => Err(seq),
+        }
+    }
+
+    pub fn check_invariants(&self) -> bool {
+        let mut expected_seq_support: BTreeMap<LedgerIndex, S> = BTreeMap::new();
+
+        let mut reachable = 0;
+        let mut nodes: Vec<&Node<T, S>> = vec![];
+        nodes.push(self.arena.get(self.root).unwrap());
+        while !nodes.is_empty() {
+            let curr = nodes.pop();
+            if let Some(curr) = curr {
+                reachable += 1;
+
+                // Node with 0 tip and partial support must have multiple children
+                // unless it is the root node
+                if curr.idx != self.root && curr.tip_support == S::zero() && curr.partial_support == S::zero() &&
+                    curr.children.len() < 2 {
+                    return false;
+                }
+
+                // branchSupport = tipSupport + partialSupport + sum(child->branchSupport)
+                let mut support = curr.tip_support + curr.partial_support;
+                if support != S::zero() {
+                    let curr_support_value = expected_seq_support.get(&(curr.span.end() - 1)).copied().unwrap_or(S::zero());
+                    expected_seq_support.insert(
+                        curr.span.end() - 1,
+                        curr_support_value + support
+                    );
+                }
+
+                let mut height = 0;
+                for child in &curr.children {
+                    let child_node = self.arena.get(*child).unwrap();
+                    if child_node.parent != Some(curr.idx) {
+                        return false;
+                    }
+
+                    support += child_node.branch_support;
+                    height = core::cmp::max(height, child_node.height + 1);
+                    nodes.push(child_node);
+                }
+
+                if support != curr.branch_support || height != curr.height {
+                    return false;
+                }
+            }
+        }
+
+        // Every node in the arena must be reachable from the root
+        reachable == self.arena.len() && expected_seq_support == self.seq_support
+    }
+}
+
+/// Access to the nodes and support of a trie for white-box tests, see the accessors of [`Node`].
+#[cfg(any(test, feature = "test-utils"))]
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone> ArenaLedgerTrie<T, S, H> {
+    pub fn root_index(&self) -> Index {
+        self.root
+    }
+
+    pub fn node(&self, idx: Index) -> Option<&Node<T, S>> {
+        self.arena.get(idx)
+    }
+
+    pub fn node_mut(&mut self, idx: Index) -> Option<&mut Node<T, S>> {
+        self.arena.get_mut(idx)
+    }
+
+    /// Add the node returned by `create` for its index to the arena, without linking it to a
+    /// parent or counting its support.
+    pub fn insert_node_with<F: FnOnce(Index) -> Node<T, S>>(&mut self, create: F) -> Index {
+        self.arena.insert_with(create)
+    }
+
+    pub fn seq_support_mut(&mut self) -> &mut BTreeMap<LedgerIndex, S> {
+        &mut self.seq_support
+    }
+}
+
+struct NodeAndArena<'a, T: SpannableLedger, S: Support> {
+    node: &'a Node<T, S>,
+    arena: &'a Arena<Node<T, S>>
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T: SpannableLedger + Display, S: Support + Serialize> Serialize for NodeAndArena<'a, T, S> {
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> where Ser: Serializer {
+        let mut state = serializer.serialize_struct("Node", 7)?;
+        state.serialize_field("span", &self.node.span.to_string())?;
+        state.serialize_field("start_id", &self.node.span.start_id())?;
+        state.serialize_field("seq", &self.node.span.tip().seq())?;
+        state.serialize_field("tip_support", &self.node.tip_support)?;
+        state.serialize_field("partial_support", &self.node.partial_support)?;
+        state.serialize_field("branch_support", &self.node.branch_support)?;
+        if !self.node.children.is_empty() {
+            let child_nodes: Vec<NodeAndArena<T, S>> = self.node.children.iter()
+                .map(|c| self.arena.get(*c).unwrap())
+                .map(|n| NodeAndArena {
+                    node: n,
+                    arena: &self.arena,
+                })
+                .collect();
+            state.serialize_field("children", &child_nodes)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: SpannableLedger + Display, S: Support + Serialize, H: BuildHasher + Clone> Serialize
+    for ArenaLedgerTrie<T, S, H> {
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> where Ser: Serializer {
+        let mut state = serializer.serialize_struct("trie", 2)?;
+
+        let root = self.arena.get(self.root)
+            .map(|root| NodeAndArena {
+                node: root,
+                arena: &self.arena,
+            }).unwrap();
+        state.serialize_field("trie", &root)?;
+        state.serialize_field("seq_support", &self.seq_support)?;
+        state.end()
+    }
+}
+
+impl<'a, T: SpannableLedger, S: Support> Debug for NodeAndArena<'a, T, S> where T::IdType: LowerHex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let mut node = f.debug_struct("Node");
+        node.field("span", &self.node.span)
+            .field("tip_support", &self.node.tip_support)
+            .field("partial_support", &self.node.partial_support)
+            .field("branch_support", &self.node.branch_support);
+        if !self.node.children.is_empty() {
+            let child_nodes: Vec<NodeAndArena<T, S>> = self.node.children.iter()
+                .map(|c| NodeAndArena {
+                    node: self.arena.get(*c).unwrap(),
+                    arena: self.arena,
+                })
+                .collect();
+            node.field("children", &child_nodes);
+        }
+        node.finish()
+    }
+}
+
+impl<T: SpannableLedger, S: Support, H: BuildHasher + Clone> Debug for ArenaLedgerTrie<T, S, H> where T::IdType: LowerHex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let root = NodeAndArena {
+            node: self.arena.get(self.root).unwrap(),
+            arena: &self.arena,
+        };
+        f.debug_struct("ArenaLedgerTrie")
+            .field("root", &root)
+            .field("seq_support", &self.seq_support)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, HashMap};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasher;
+    use std::rc::Rc;
+
+    use proptest::prelude::*;
+    use rand::distributions::{Distribution, Uniform};
+    use rand::SeedableRng;
+    use xrpl_consensus_core::{Ledger, LedgerIndex};
+
+    use crate::arena_ledger_trie::{ArenaLedgerTrie, FreedNodes, MAX_FREE_CHILDREN, Node, PreferredResult};
+    use crate::ledger_trie::{id_to_hex, LedgerTrie, LedgerTrieExt, TrieError};
+    use crate::snapshot_ledger_trie::SnapshotLedgerTrie;
+    use crate::span::{Span, SpanError};
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, LedgerId, LedgerOracle, SimulatedLedger, Tx};
+    use crate::test_utils::validation::peer_id;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let (mut trie, mut h) = setup();
+        let ledger = h.get_or_create("abc");
+        insert(&mut trie, &ledger, None);
+        println!("{}", serde_json::to_string_pretty(&trie).unwrap());
+    }
+
+    #[test]
+    fn test_insert_single_entry() {
+        let (mut trie, mut h) = setup();
+        let ledger = h.get_or_create("abc");
+        insert(&mut trie, &ledger, None);
+        assert_eq!(trie.tip_support(&ledger), 1);
+        assert_eq!(trie.branch_support(&ledger), 1);
+
+        insert(&mut trie, &ledger, None);
+        assert_eq!(trie.tip_support(&ledger), 2);
+        assert_eq!(trie.branch_support(&ledger), 2);
+    }
+
+    #[test]
+    fn test_insert_suffix_of_existing() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, None);
+
+        // extend with no siblings
+        let abcd = h.get_or_create("abcd");
+        insert(&mut trie, &abcd, None);
+        println!("{:?}", trie);
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abc), 2);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 1);
+
+        // extend with existing sibling
+        let abce = h.get_or_create("abce");
+        insert(&mut trie, &abce, None);
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 1);
+        assert_eq!(trie.tip_support(&abce), 1);
+        assert_eq!(trie.branch_support(&abce), 1);
+    }
+
+    #[test]
+    fn test_insert_uncommitted_of_existing_node() {
+        let (mut trie, mut h) = setup();
+        let abcd = h.get_or_create("abcd");
+        insert(&mut trie, &abcd, None);
+
+        // uncommitted with no siblings
+        let abcdf = h.get_or_create("abcdf");
+        insert(&mut trie, &abcdf, None);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 2);
+        assert_eq!(trie.tip_support(&abcdf), 1);
+        assert_eq!(trie.branch_support(&abcdf), 1);
+
+        // uncommitted with existing child
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, None);
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 2);
+        assert_eq!(trie.tip_support(&abcdf), 1);
+        assert_eq!(trie.branch_support(&abcdf), 1);
+    }
+
+    #[test]
+    fn test_insert_at_split_node() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abd, None);
+
+        // ab was created by the split and has no tip support of its own
+        let ab = h.get_or_create("ab");
+        assert_eq!(trie.tip_support(&ab), 0);
+        assert_eq!(trie.branch_support(&ab), 2);
+        let nodes = trie.arena.len();
+
+        insert(&mut trie, &ab, None);
+        assert_eq!(trie.arena.len(), nodes);
+        assert_eq!(trie.tip_support(&ab), 1);
+        assert_eq!(trie.branch_support(&ab), 3);
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.tip_support(&abd), 1);
+        let ab_idx = trie._find_by_ledger_id(ab.id(), Some(ab.seq()), None).unwrap();
+        assert_eq!(trie.arena.get(ab_idx).unwrap().children.len(), 2);
+    }
+
+    #[test]
+    fn test_insert_suffix_and_uncommitted_existing_node() {
+        let (mut trie, mut h) = setup();
+        let abcd = h.get_or_create("abcd");
+        insert(&mut trie, &abcd, None);
+        let abce = h.get_or_create("abce");
+        insert(&mut trie, &abce, None);
+
+        let abc = h.get_or_create("abc");
+        assert_eq!(trie.tip_support(&abc), 0);
+        assert_eq!(trie.branch_support(&abc), 2);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 1);
+        assert_eq!(trie.tip_support(&abce), 1);
+        assert_eq!(trie.branch_support(&abce), 1);
+    }
+
+    #[test]
+    fn test_insert_suffix_and_uncommitted_with_existing_child() {
+        // abcd : abcde, abcf
+        let (mut trie, mut h) = setup();
+        let abcd = h.get_or_create("abcd");
+        let abcde = h.get_or_create("abcde");
+        let abcf = h.get_or_create("abcf");
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &abcde, None);
+        insert(&mut trie, &abcf, None);
+
+        let abc = h.get_or_create("abc");
+        assert_eq!(trie.tip_support(&abc), 0);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.tip_support(&abcd), 1);
+        assert_eq!(trie.branch_support(&abcd), 2);
+        assert_eq!(trie.tip_support(&abcf), 1);
+        assert_eq!(trie.branch_support(&abcf), 1);
+        assert_eq!(trie.tip_support(&abcde), 1);
+        assert_eq!(trie.branch_support(&abcde), 1);
+    }
+
+    #[test]
+    fn test_insert_multiple_counts() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        insert(&mut trie, &ab, Some(4));
+        assert_eq!(trie.tip_support(&ab), 4);
+        assert_eq!(trie.branch_support(&ab), 4);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 4);
+
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, Some(2));
+        assert_eq!(trie.tip_support(&abc), 2);
+        assert_eq!(trie.branch_support(&abc), 2);
+        assert_eq!(trie.tip_support(&ab), 4);
+        assert_eq!(trie.branch_support(&ab), 6);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 6);
+    }
+
+    #[test]
+    fn test_insert_count_tip_and_branch() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, Some(4));
+        assert_eq!(trie.tip_support(&abc), 4);
+        assert_eq!(trie.branch_support(&abc), 4);
+        assert_eq!(trie.seq_support[&abc.seq()], 4);
+
+        // Inserting where it splits an existing node moves that node's support to the suffix
+        // and gives the full count to the prefix
+        insert(&mut trie, &ab, Some(4));
+        assert_eq!(trie.tip_support(&ab), 4);
+        assert_eq!(trie.branch_support(&ab), 8);
+        assert_eq!(trie.tip_support(&abc), 4);
+        assert_eq!(trie.branch_support(&abc), 4);
+        assert_eq!(trie.seq_support[&ab.seq()], 4);
+    }
+
+    #[test]
+    fn test_id_formatting() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, None);
+        let start_id = abc.get_ancestor(1);
+
+        // The debug output shows IDs in hex, while serializing keeps the IDs themselves
+        let debug = format!("{:?}", trie);
+        assert_eq!(id_to_hex(&start_id), format!("{:x}", start_id));
+        assert!(debug.contains(&format!("start: {}, tip: {}", id_to_hex(&start_id), id_to_hex(&abc.id()))), "{}", debug);
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&trie).unwrap();
+            assert_eq!(json["trie"]["children"][0]["start_id"], serde_json::to_value(start_id).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_preferred_chain() {
+        //          A
+        //          |
+        //          B
+        //         / \
+        //     C(3)   E(1)
+        //       |
+        //       D
+        //      / \
+        //  F(1)   H(1)
+        //    |
+        //  G(2)
+        let (mut trie, mut h) = setup();
+        assert!(trie.preferred_chain(0).is_empty());
+
+        let abcd = h.get_or_create("abcd");
+        let abcdh = h.get_or_create("abcdh");
+        let abcdfg = h.get_or_create("abcdfg");
+        assert!(abcdfg.id() > abcdh.id());
+        insert(&mut trie, &abcd, Some(3));
+        insert(&mut trie, &h.get_or_create("abe"), None);
+        insert(&mut trie, &abcdh, None);
+        insert(&mut trie, &abcdfg, Some(2));
+
+        let chain = |trie: &ArenaLedgerTrie<SimulatedLedger>| -> Vec<LedgerId> {
+            trie.preferred_chain(0).iter().map(|tip| tip.id()).collect()
+        };
+        let genesis = h.get_or_create("").id();
+        let ab = h.get_or_create("ab").id();
+
+        // The validators on D could still move to H, so the walk stops at D
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abcd.id());
+        assert_eq!(chain(&trie), vec![genesis, ab, abcd.id()]);
+
+        // Once they leave, the walk passes D and stops partway along F-G
+        remove(&mut trie, &abcd, Some(3));
+        let abcdf = h.get_or_create("abcdf").id();
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abcdf);
+        assert_eq!(chain(&trie), vec![genesis, ab, abcd.id(), abcdf]);
+    }
+
+    #[test]
+    fn test_insert_node_count() {
+        let (mut trie, mut h) = setup();
+        assert_eq!(trie.arena.len(), 1);
+
+        // A new leaf below the root adds only that leaf
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        assert_eq!(trie.arena.len(), 2);
+
+        // Splitting a node adds only the old suffix
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        assert_eq!(trie.arena.len(), 3);
+
+        // A fork at the end of a node adds only the new leaf
+        insert(&mut trie, &h.get_or_create("abd"), None);
+        assert_eq!(trie.arena.len(), 4);
+
+        // A fork within a node adds the old suffix and the new leaf
+        insert(&mut trie, &h.get_or_create("abcefg"), None);
+        insert(&mut trie, &h.get_or_create("abcehi"), None);
+        assert_eq!(trie.arena.len(), 7);
+
+        // More support for a ledger already at a node's tip adds nothing
+        insert(&mut trie, &h.get_or_create("abcehi"), Some(2));
+        assert_eq!(trie.arena.len(), 7);
+        assert!(trie.check_invariants());
+    }
+
+    #[test]
+    fn test_insert_root_fork() {
+        let (mut trie, mut h) = setup();
+        let a = h.get_or_create("a");
+        let b = h.get_or_create("b");
+        assert_eq!(a.seq(), 1);
+        assert_eq!(b.seq(), 1);
+        assert!(a.id() < b.id());
+
+        // Two ledgers right after genesis fork directly below the root
+        insert(&mut trie, &a, Some(2));
+        insert(&mut trie, &b, None);
+        let root = trie.arena.get(trie.root).unwrap();
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.branch_support, 3);
+        for (ledger, support) in [(&a, 2), (&b, 1)] {
+            assert!(root.children.iter().any(|child| trie.arena.get(*child).unwrap().span.tip().id() == ledger.id()));
+            assert_eq!(trie.tip_support(ledger), support);
+            assert_eq!(trie.branch_support(ledger), support);
+        }
+
+        // The preferred ledger follows the support, with the larger ID breaking ties
+        assert_eq!(trie.get_preferred(0).unwrap().id(), a.id());
+        insert(&mut trie, &b, None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), b.id());
+        insert(&mut trie, &b, None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), b.id());
+        remove(&mut trie, &b, Some(2));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), a.id());
+    }
+
+    #[test]
+    fn test_insert_zero_count() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        insert(&mut trie, &abc, Some(3));
+        let nodes = trie.arena.len();
+
+        insert(&mut trie, &abd, Some(0));
+        insert(&mut trie, &abc, Some(0));
+        trie.insert_partial(&abd, Some(0)).unwrap();
+        let ab = h.get_or_create("ab");
+        assert!(matches!(trie.insert_by_id(ab.id(), 2, |s| abc.get_ancestor(s), || (*ab).clone(), Some(0)), Ok(None)));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.arena.len(), nodes);
+        assert_eq!(trie.tip_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abd), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 3);
+    }
+
+    #[test]
+    fn test_from_validations() {
+        let mut h = LedgerHistoryHelper::new();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let abe = h.get_or_create("abe");
+
+        let trie = ArenaLedgerTrie::from_validations(vec![
+            (peer_id(1), (*abc).clone()),
+            (peer_id(2), (*abd).clone()),
+            (peer_id(1), (*abe).clone()),
+            (peer_id(3), (*abd).clone()),
+        ]);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 0);
+        assert_eq!(trie.tip_support(&abd), 2);
+        assert_eq!(trie.tip_support(&abe), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 3);
+    }
+
+    #[test]
+    fn test_common_prefix_len() {
+        let mut h = LedgerHistoryHelper::new();
+        let common_prefix_len = |a: &str, b: &str, h: &mut LedgerHistoryHelper| {
+            ArenaLedgerTrie::common_prefix_len(&*h.get_or_create(a), &*h.get_or_create(b))
+        };
+
+        assert_eq!(common_prefix_len("abc", "abc", &mut h), 4);
+        assert_eq!(common_prefix_len("ab", "abc", &mut h), 3);
+        assert_eq!(common_prefix_len("abc", "ab", &mut h), 3);
+        assert_eq!(common_prefix_len("abcd", "abe", &mut h), 3);
+        assert_eq!(common_prefix_len("abcd", "fgh", &mut h), 1);
+        assert_eq!(common_prefix_len("", "abc", &mut h), 1);
+    }
+
+    #[test]
+    fn test_path_to() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abcdef = h.get_or_create("abcdef");
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &abcdef, None);
+
+        let path: Vec<(LedgerIndex, LedgerId)> = trie.path_to(abcdef.id()).unwrap().iter()
+            .map(|tip| (tip.seq(), tip.id()))
+            .collect();
+        assert_eq!(path, vec![
+            (0, abc.get_ancestor(0)),
+            (abc.seq(), abc.id()),
+            (abcd.seq(), abcd.id()),
+            (abcdef.seq(), abcdef.id()),
+        ]);
+
+        assert_eq!(trie.path_to(abcd.id()).unwrap().len(), 3);
+
+        // Ledgers in the middle of a span or not in the trie have no path
+        assert!(trie.path_to(ab.id()).is_none());
+        assert!(trie.path_to(h.get_or_create("abg").id()).is_none());
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abce = h.get_or_create("abce");
+
+        // A new node
+        let tip = trie.insert_get(&abcd, None).unwrap().unwrap();
+        assert_eq!((tip.seq(), tip.id()), (abcd.seq(), abcd.id()));
+
+        // An existing node
+        let tip = trie.insert_get(&abcd, Some(2)).unwrap().unwrap();
+        assert_eq!((tip.seq(), tip.id()), (abcd.seq(), abcd.id()));
+        assert_eq!(trie.tip_support(&abcd), 3);
+
+        // Splitting a node at the ledger
+        let tip = trie.insert_get(&abc, None).unwrap().unwrap();
+        assert_eq!((tip.seq(), tip.id()), (abc.seq(), abc.id()));
+        assert_eq!(trie.tip_support(&abc), 1);
+
+        // Splitting a node into a sibling
+        trie.remove(&abc, None).unwrap();
+        let tip = trie.insert_get(&abce, None).unwrap().unwrap();
+        assert_eq!((tip.seq(), tip.id()), (abce.seq(), abce.id()));
+        assert!(trie.check_invariants());
+
+        assert!(trie.insert_get(&abc, Some(0)).unwrap().is_none());
+        assert_eq!(trie.tip_support(&abc), 0);
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        let (mut trie, mut h) = setup();
+        let (mut expected, _) = setup();
+        let names = ["abcd", "abe", "abc", "f", "abcd", "abcg", "ab", "fh"];
+        let counts = [3, 1, 2, 4, 1, 0, 5, 2];
+        let ledgers: Vec<SimulatedLedger> = names.iter()
+            .map(|name| (*h.get_or_create(name)).clone())
+            .collect();
+
+        assert_eq!(trie.bootstrap(ledgers.iter().cloned().zip(counts)), Ok(()));
+        for (ledger, count) in ledgers.iter().zip(counts) {
+            insert(&mut expected, ledger, Some(count));
+        }
+
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.seq_support, expected.seq_support);
+        for ledger in &ledgers {
+            assert_eq!(trie.tip_support(ledger), expected.tip_support(ledger));
+            assert_eq!(trie.branch_support(ledger), expected.branch_support(ledger));
+        }
+        assert_eq!(trie.tip_support(&ledgers[0]), 4);
+
+        // Counts that overflow once summed are rejected before anything is inserted
+        let (mut trie, _) = setup();
+        let abc = ledgers[2].clone();
+        let overflowing = vec![(ledgers[0].clone(), 1), (abc.clone(), u32::MAX), (abc, 1)];
+        assert_eq!(
+            trie.bootstrap(overflowing),
+            Err(TrieError::SupportOverflow { total: u32::MAX, requested: 1 })
+        );
+        assert!(trie.empty());
+    }
+
+    #[test]
+    fn test_insert_out_of_order() {
+        let (mut trie, mut h) = setup();
+        let abcdef = h.get_or_create("abcdef");
+        insert(&mut trie, &abcdef, None);
+        assert!(trie.check_invariants());
+
+        // Every ancestor back to genesis is in the span of the tip's node, supported by the tip
+        let mut parent = h.get_or_create("");
+        for name in ["a", "ab", "abc", "abcd", "abcde", "abcdef"] {
+            let ledger = h.get_or_create(name);
+            assert_eq!(xrpl_consensus_core::SpannableLedger::parent_id(&*ledger), Some(parent.id()));
+            assert_eq!(trie.branch_support(&ledger), 1);
+            parent = ledger;
+        }
+        assert_eq!(trie.path_to(abcdef.id()).unwrap().len(), 2);
+
+        // An ancestor arriving later splits the path at itself
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.path_to(abc.id()).unwrap().len(), 2);
+        assert_eq!(trie.path_to(abcdef.id()).unwrap().len(), 3);
+        assert_eq!(trie.branch_support(&abc), 2);
+    }
+
+    #[test]
+    fn test_node_handle() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abe = h.get_or_create("abe");
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &abe, None);
+        assert!(trie.handle(abc.id()).is_none());
+
+        let handle = trie.handle(abe.id()).unwrap();
+        assert_eq!(trie.resolve(handle).unwrap().id(), abe.id());
+
+        // The handle outlives changes elsewhere in the trie
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abcd, None);
+        assert_eq!(trie.resolve(handle).unwrap().id(), abe.id());
+
+        // But not the removal of its node, even once the arena reuses the slot
+        assert!(remove(&mut trie, &abe, None));
+        assert!(trie.resolve(handle).is_none());
+        insert(&mut trie, &h.get_or_create("abf"), None);
+        assert!(trie.resolve(handle).is_none());
+
+        // Nor a split of its node, which leaves the node ending at the new ancestor
+        let abfhi = h.get_or_create("abfhi");
+        insert(&mut trie, &abfhi, None);
+        let split = trie.handle(abfhi.id()).unwrap();
+        insert(&mut trie, &h.get_or_create("abfh"), None);
+        assert!(trie.resolve(split).is_none());
+        assert_eq!(trie.resolve(trie.handle(abfhi.id()).unwrap()).unwrap().id(), abfhi.id());
+
+        // Compacting moves abcd back to the index its handle holds, as no node before it in
+        // the trie was removed, so the handle still resolves to abcd
+        let handle = trie.handle(abcd.id()).unwrap();
+        trie.compact();
+        assert_eq!(trie.handle(abcd.id()), Some(handle));
+        assert_eq!(trie.resolve(handle).unwrap().id(), abcd.id());
+    }
+
+    #[test]
+    fn test_adjust_tip() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abcd, None);
+
+        assert!(trie.adjust_tip(abc.id(), 2));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abc), 4);
+        assert_eq!(trie.branch_support(&ab), 4);
+        assert_eq!(trie.seq_support[&abc.seq()], 3);
+
+        // Dropping a tip to zero support removes it
+        assert!(trie.adjust_tip(abcd.id(), -1));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&abcd), 0);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert!(!trie.seq_support.contains_key(&abcd.seq()));
+
+        // Neither underflow nor overflow changes the trie
+        assert!(!trie.adjust_tip(abc.id(), -4));
+        assert!(!trie.adjust_tip(abc.id(), u32::MAX as i64));
+        assert_eq!(trie.tip_support(&abc), 3);
+        assert!(trie.check_invariants());
+
+        // Only ledgers with tip support can be adjusted
+        assert!(!trie.adjust_tip(ab.id(), 1));
+        assert!(!trie.adjust_tip(abcd.id(), 1));
+
+        assert!(trie.adjust_tip(abc.id(), -3));
+        assert!(trie.check_invariants());
+        assert!(trie.empty());
+    }
+
+    #[test]
+    fn test_structural_eq() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let abde = h.get_or_create("abde");
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abd, None);
+        assert!(trie.structural_eq(&trie));
+
+        // Same topology with different support and insertion order
+        let mut other = ArenaLedgerTrie::new();
+        insert(&mut other, &abd, Some(3));
+        insert(&mut other, &abc, None);
+        assert!(trie.structural_eq(&other));
+        assert!(other.structural_eq(&trie));
+
+        // A branch to a different ledger
+        let mut other = ArenaLedgerTrie::new();
+        insert(&mut other, &abc, None);
+        insert(&mut other, &abde, None);
+        assert!(!trie.structural_eq(&other));
+
+        // Support for the ledger where the branches split does not change the shape
+        let mut other = ArenaLedgerTrie::new();
+        insert(&mut other, &abc, None);
+        insert(&mut other, &abd, None);
+        insert(&mut other, &ab, None);
+        assert!(trie.structural_eq(&other));
+        insert(&mut trie, &ab, None);
+        assert!(trie.structural_eq(&other));
+
+        // The same split span without the other branch
+        let mut other = ArenaLedgerTrie::new();
+        insert(&mut other, &abc, None);
+        insert(&mut other, &ab, None);
+        assert!(!trie.structural_eq(&other));
+        assert!(!trie.structural_eq(&ArenaLedgerTrie::new()));
+    }
+
+    #[test]
+    fn test_with_genesis() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::with_genesis((*a).clone());
+        assert!(trie.empty());
+        assert!(trie.get_preferred(0).is_none());
+
+        insert(&mut trie, &abc, None);
+        // Uncommitted support can not move the preferred ledger before the genesis ledger
+        assert_eq!(trie.get_preferred(10).unwrap().id(), a.id());
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        insert(&mut trie, &abd, None);
+        let path: Vec<LedgerId> = trie.path_to(abd.id()).unwrap().iter().map(|tip| tip.id()).collect();
+        assert_eq!(path, vec![a.id(), ab.id(), abd.id()]);
+        assert_eq!(trie.branch_support(&a), 2);
+
+        // The genesis ledger itself can be supported
+        insert(&mut trie, &a, None);
+        assert_eq!(trie.tip_support(&a), 1);
+        assert_eq!(trie.branch_support(&a), 3);
+        assert!(remove(&mut trie, &a, None));
+        assert!(remove(&mut trie, &abc, None));
+        assert_eq!(trie.path_to(abd.id()).unwrap().len(), 2);
+
+        // Ledgers that diverge from the genesis ledger, or come before it, are rejected
+        let e = h.get_or_create("e");
+        assert_eq!(trie.checked_insert(&e, None), Err(TrieError::DivergesFromGenesis { seq: 1 }));
+        let genesis = h.get_or_create("");
+        assert_eq!(trie.checked_insert(&genesis, None), Err(TrieError::DivergesFromGenesis { seq: 1 }));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&a), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "DivergesFromGenesis")]
+    fn test_insert_diverging_from_genesis_panics() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::with_genesis((*a).clone());
+        trie.insert(&h.get_or_create("e"), None);
+    }
+
+    #[test]
+    fn test_retain() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let ef = h.get_or_create("ef");
+        let gh = h.get_or_create("gh");
+        let ghi = h.get_or_create("ghi");
+        let ghj = h.get_or_create("ghj");
+        insert(&mut trie, &abc, Some(3));
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &ef, None);
+        insert(&mut trie, &gh, Some(2));
+        insert(&mut trie, &ghi, None);
+        insert(&mut trie, &ghj, Some(2));
+
+        trie.retain(|_, tip_support, _| tip_support > 1);
+        assert!(trie.check_invariants());
+
+        assert_eq!(trie.tip_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abcd), 0);
+        assert_eq!(trie.branch_support(&ef), 0);
+        assert_eq!(trie.tip_support(&gh), 2);
+        assert_eq!(trie.branch_support(&gh), 4);
+        assert_eq!(trie.branch_support(&ghi), 0);
+        assert_eq!(trie.tip_support(&ghj), 2);
+        assert_eq!(trie.branch_support(&h.get_or_create("")), 7);
+        assert!(!trie.seq_support.contains_key(&abcd.seq()));
+
+        // Pruning a node prunes its descendants regardless of their support
+        trie.retain(|tip, _, _| tip.id() != gh.id());
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&ghj), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("")), 3);
+    }
+
+    #[test]
+    fn test_ancestor_support() {
+        let (mut trie, mut h) = setup();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        assert!(trie.ancestor_support(abcd.id()).is_empty());
+
+        insert(&mut trie, &a, Some(2));
+        insert(&mut trie, &ab, None);
+        insert(&mut trie, &abc, Some(3));
+        insert(&mut trie, &abcd, Some(1));
+
+        let profile = trie.ancestor_support(abcd.id());
+        assert_eq!(profile, vec![(0, 7), (1, 7), (2, 5), (3, 4), (4, 1)]);
+        assert!(profile.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        assert_eq!(trie.ancestor_support(ab.id()), vec![(0, 7), (1, 7), (2, 5)]);
+        assert!(trie.ancestor_support(h.get_or_create("abe").id()).is_empty());
+    }
+
+    #[test]
+    fn test_partial_support() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+
+        insert(&mut trie, &abc, None);
+        trie.insert_partial(&abcd, Some(2)).unwrap();
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abc), 3);
+        assert_eq!(trie.tip_support(&abcd), 0);
+        assert_eq!(trie.branch_support(&abcd), 2);
+
+        // Partial support still steers the preferred ledger
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abcd.id());
+
+        // Splitting a node moves its partial support along with its tip support
+        insert(&mut trie, &ab, None);
+        assert_eq!(trie.tip_support(&ab), 1);
+        assert_eq!(trie.branch_support(&ab), 4);
+        assert_eq!(trie.branch_support(&abcd), 2);
+
+        // Full and partial support are removed independently
+        assert!(!remove(&mut trie, &abcd, None));
+        assert_eq!(
+            trie.remove_partial(&abcd, Some(3)),
+            Err(TrieError::InsufficientSupport { available: 2, requested: 3 })
+        );
+        assert_eq!(trie.remove_partial(&abc, None), Ok(false));
+        assert_eq!(trie.remove_partial(&abcd, Some(2)), Ok(true));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&abcd), 0);
+        assert_eq!(trie.branch_support(&ab), 2);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ab.id());
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        assert_eq!(trie.try_insert(&abc, None), Ok(()));
+        assert_eq!(trie.tip_support(&abc), 1);
+
+        let genesis = h.get_or_create("");
+        assert_eq!(trie.try_insert(&genesis, None), Err(TrieError::InvalidSpan(SpanError::ZeroSequence)));
+        assert_eq!(trie.tip_support(&genesis), 0);
+        assert_eq!(trie.branch_support(&genesis), 1);
+        assert!(trie.check_invariants());
+
+        // A ledger that spans fine but doesn't descend from the trie's genesis ledger is an
+        // error too, rather than a panic
+        let a = h.get_or_create("a");
+        let e = h.get_or_create("e");
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::with_genesis((*a).clone());
+        assert_eq!(trie.try_insert(&abc, None), Ok(()));
+        assert_eq!(trie.try_insert(&e, None), Err(TrieError::DivergesFromGenesis { seq: 1 }));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&e), 0);
+        assert_eq!(trie.branch_support(&a), 1);
+
+        // So is support the trie can't count
+        assert_eq!(
+            trie.try_insert(&abc, Some(u32::MAX)),
+            Err(TrieError::SupportOverflow { total: 1, requested: u32::MAX })
+        );
+        assert_eq!(trie.tip_support(&abc), 1);
+    }
+
+    #[test]
+    fn test_branch_support_of_tip() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abe = h.get_or_create("abe");
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &abe, Some(2));
+
+        let preferred = trie.get_preferred(0).unwrap();
+        assert_eq!(preferred.id(), abe.id());
+        assert_eq!(trie.branch_support_of_tip(&preferred), 2);
+
+        // Tips within a node's span have that node's branch support
+        for ledger in [&abc, &abcd, &abe, &h.get_or_create("ab")] {
+            let tip = Span::from((**ledger).clone()).tip();
+            assert_eq!(trie.branch_support_of_tip(&tip), trie.branch_support(ledger));
+        }
+
+        // Ledgers outside the trie have no support
+        let abcdf = h.get_or_create("abcdf");
+        assert_eq!(trie.branch_support_of_tip(&Span::from((*abcdf).clone()).tip()), 0);
+        let abg = h.get_or_create("abg");
+        assert_eq!(trie.branch_support_of_tip(&Span::from((*abg).clone()).tip()), 0);
+    }
+
+    #[test]
+    fn test_support_of_preferred() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abe = h.get_or_create("abe");
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abcd, Some(3));
+        insert(&mut trie, &abe, None);
+
+        let preferred = trie.get_preferred(0).unwrap();
+        assert_eq!(preferred.id(), abcd.id());
+        assert_eq!(trie.tip_support_of(&preferred), 3);
+        assert_eq!(trie.branch_support_of(&preferred), 3);
+
+        // A tip within a node's span has no tip support of its own
+        let ab = Span::from((*h.get_or_create("ab")).clone()).tip();
+        assert_eq!(trie.tip_support_of(&ab), 0);
+        assert_eq!(trie.branch_support_of(&ab), 5);
+        let abc_tip = Span::from((*abc).clone()).tip();
+        assert_eq!(trie.tip_support_of(&abc_tip), 1);
+        assert_eq!(trie.branch_support_of(&abc_tip), 4);
+
+        // Ledgers outside the trie have no support
+        let abf = Span::from((*h.get_or_create("abf")).clone()).tip();
+        assert_eq!(trie.tip_support_of(&abf), 0);
+        assert_eq!(trie.branch_support_of(&abf), 0);
+    }
+
+    /// A ledger that counts how many ancestor lookups the trie makes.
+    #[derive(Clone, Debug)]
+    struct CountingLedger {
+        ledger: Rc<SimulatedLedger>,
+        lookups: Rc<Cell<usize>>,
+    }
+
+    impl xrpl_consensus_core::SpannableLedger for CountingLedger {
+        type IdType = LedgerId;
+
+        fn id(&self) -> LedgerId {
+            Ledger::id(&*self.ledger)
+        }
+
+        fn seq(&self) -> LedgerIndex {
+            Ledger::seq(&*self.ledger)
+        }
+
+        fn get_ancestor(&self, seq: LedgerIndex) -> LedgerId {
+            self.lookups.set(self.lookups.get() + 1);
+            Ledger::get_ancestor(&*self.ledger, seq)
+        }
+
+        fn make_genesis() -> Self {
+            CountingLedger {
+                ledger: Rc::new(<SimulatedLedger as Ledger>::make_genesis()),
+                lookups: Rc::new(Cell::new(0)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tip_lookups() {
+        let mut trie: ArenaLedgerTrie<CountingLedger> = ArenaLedgerTrie::new();
+        let mut h = LedgerHistoryHelper::new();
+        let lookups = Rc::new(Cell::new(0));
+        let counting = |ledger: Rc<SimulatedLedger>| CountingLedger { ledger, lookups: lookups.clone() };
+
+        // Ten branches off genesis, each four ledgers long
+        let mut ledgers = vec![];
+        for i in 0..10 {
+            let mut name = String::new();
+            for chars in ["ABCDEFGHIJ", "abcdefghij", "klmnopqrst", "uvwxyz0123"] {
+                name.push(chars.as_bytes()[i] as char);
+                ledgers.push(counting(h.get_or_create(&name)));
+            }
+        }
+        for ledger in &ledgers {
+            trie.insert(ledger, None);
+        }
+
+        // Tip IDs are cached in the spans, so reading them repeatedly looks up no ancestry
+        let last = &ledgers[ledgers.len() - 1];
+        let tip = Span::from(last.clone()).tip();
+        let span = Span::from(last.clone());
+        lookups.set(0);
+        for _ in 0..100 {
+            assert_eq!(span.tip().id(), tip.id());
+        }
+        assert_eq!(lookups.get(), 0);
+
+        // Following the tip's ancestry only looks at the first ledger of the branches it passes
+        lookups.set(0);
+        assert_eq!(trie.branch_support_of_tip(&tip), 1);
+        assert!(lookups.get() > 0 && lookups.get() < ledgers.len(), "{}", lookups.get());
+    }
+
+    #[test]
+    fn test_free_children_capped() {
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+        let mut oracle = LedgerOracle::new();
+        let genesis = SimulatedLedger::genesis();
+
+        // Many branches off genesis, each forking into two leaves
+        let mut leaves = vec![];
+        for i in 0..2 * MAX_FREE_CHILDREN as u32 {
+            let branch = oracle.accept("a", &genesis, Tx::new(3 * i));
+            leaves.push(oracle.accept("ab", &branch, Tx::new(3 * i + 1)));
+            leaves.push(oracle.accept("ac", &branch, Tx::new(3 * i + 2)));
+        }
+        for l in &leaves {
+            insert(&mut trie, l, None);
+        }
+
+        // Removing a leaf merges its branch's node into the other leaf, freeing its children
+        for l in leaves.iter().step_by(2) {
+            assert!(remove(&mut trie, l, None));
+        }
+        assert_eq!(trie.free_children.len(), MAX_FREE_CHILDREN);
+    }
+
+    #[test]
+    fn test_freed_handles() {
+        let (mut trie, mut h) = setup();
+        let names = ["abc", "abcd", "abce", "fgh", "fgi"];
+        for name in names {
+            insert(&mut trie, &h.get_or_create(name), None);
+        }
+        let handles: HashMap<&str, _> = names.iter().chain(["fg"].iter())
+            .map(|name| (*name, trie.handle(h.get_or_create(name).id()).unwrap()))
+            .collect();
+
+        // Pruning a subtree frees every node in it
+        let abc = h.get_or_create("abc");
+        let mut freed = trie.retain(|tip, _, _| tip.id() != abc.id());
+        freed.sort_by_key(|handle| handle.id);
+        assert_eq!(freed, vec![handles["abc"], handles["abcd"], handles["abce"]]);
+        for handle in &freed {
+            assert!(trie.resolve(*handle).is_none());
+        }
+
+        // Removing a leaf frees it, and its parent once that is combined with its only child
+        let removed = trie.remove_with_freed(&h.get_or_create("fgh"), None).unwrap();
+        assert!(removed.changed);
+        let mut freed = removed.freed;
+        freed.sort_by_key(|handle| handle.id);
+        assert_eq!(freed, vec![handles["fg"], handles["fgh"]]);
+        assert!(trie.check_invariants());
+        assert!(trie.resolve(handles["fg"]).is_none());
+        assert_eq!(trie.resolve(handles["fgi"]).unwrap().id(), h.get_or_create("fgi").id());
+
+        // Removing support that leaves a node in place frees nothing
+        insert(&mut trie, &h.get_or_create("fgi"), None);
+        let removed = |changed, freed| Ok(FreedNodes { changed, freed });
+        assert_eq!(trie.remove_with_freed(&h.get_or_create("fgi"), None), removed(true, vec![]));
+        assert_eq!(trie.remove_with_freed(&h.get_or_create("fgh"), None), removed(false, vec![]));
+        assert_eq!(trie.remove_with_freed(&h.get_or_create("fgi"), None), removed(true, vec![handles["fgi"]]));
+        assert!(trie.empty());
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let (mut batch, mut h) = setup();
+        let mut sequential = ArenaLedgerTrie::new();
+        let ledgers: Vec<SimulatedLedger> = ["ab", "abc", "abcd", "abce", "abcef", "abg"].iter()
+            .map(|s| (*h.get_or_create(s)).clone())
+            .collect();
+        for trie in [&mut batch, &mut sequential] {
+            insert(trie, &ledgers[0], None);
+            insert(trie, &ledgers[1], Some(2));
+            insert(trie, &ledgers[2], None);
+            insert(trie, &ledgers[3], None);
+            insert(trie, &ledgers[4], Some(2));
+            insert(trie, &ledgers[5], None);
+        }
+
+        let to_remove = vec![
+            (ledgers[2].clone(), 1),
+            (ledgers[1].clone(), 2),
+            (ledgers[4].clone(), 1),
+            (ledgers[0].clone(), 1),
+            (ledgers[4].clone(), 1),
+        ];
+        assert_eq!(batch.remove_all(&to_remove), Ok(()));
+        for (ledger, count) in &to_remove {
+            assert!(remove(&mut sequential, ledger, Some(*count)));
+        }
+
+        assert!(batch.check_invariants());
+        assert_eq!(batch.arena.len(), sequential.arena.len());
+        for ledger in &ledgers {
+            assert_eq!(batch.tip_support(ledger), sequential.tip_support(ledger));
+            assert_eq!(batch.branch_support(ledger), sequential.branch_support(ledger));
+        }
+        assert_eq!(batch.get_preferred(0).unwrap().id(), sequential.get_preferred(0).unwrap().id());
+
+        // Removing more support than remains stops the batch at that ledger
+        assert_eq!(
+            batch.remove_all(&[(ledgers[5].clone(), 1), (ledgers[3].clone(), 2)]),
+            Err(TrieError::InsufficientSupport { available: 1, requested: 2 })
+        );
+        assert!(batch.check_invariants());
+        assert_eq!(batch.tip_support(&ledgers[5]), 0);
+        assert_eq!(batch.tip_support(&ledgers[3]), 1);
+    }
+
+    #[test]
+    fn test_u64_support() {
+        let mut h = LedgerHistoryHelper::new();
+        let mut trie: ArenaLedgerTrie<SimulatedLedger, u64> = ArenaLedgerTrie::default();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let max = u32::MAX as u64;
+
+        trie.insert(&abc, Some(max));
+        trie.insert(&abc, Some(max));
+        trie.insert(&abd, Some(max));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 2 * max);
+        assert_eq!(trie.branch_support(&ab), 3 * max);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        assert_eq!(trie.remove(&abc, Some(2 * max)), Ok(true));
+        assert_eq!(
+            trie.remove(&abd, Some(2 * max)),
+            Err(TrieError::InsufficientSupport { available: max, requested: 2 * max })
+        );
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&abd), max);
+    }
+
+    #[test]
+    fn test_checked_insert_overflow() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        assert_eq!(trie.checked_insert(&abc, Some(u32::MAX - 1)), Ok(()));
+        assert_eq!(trie.checked_insert(&abd, None), Ok(()));
+
+        // Support on either branch would overflow the common ancestors' branch support
+        assert_eq!(
+            trie.checked_insert(&abd, None),
+            Err(TrieError::SupportOverflow { total: u32::MAX, requested: 1 })
+        );
+        assert_eq!(
+            trie.checked_insert(&h.get_or_create("e"), Some(2)),
+            Err(TrieError::SupportOverflow { total: u32::MAX, requested: 2 })
+        );
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), u32::MAX - 1);
+        assert_eq!(trie.tip_support(&abd), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("")), u32::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "SupportOverflow")]
+    fn test_insert_overflow_panics() {
+        let (mut trie, mut h) = setup();
+        trie.insert(&h.get_or_create("abc"), Some(u32::MAX));
+        trie.insert(&h.get_or_create("abd"), None);
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        #[derive(Clone, Default)]
+        struct CountingHasher(Rc<Cell<usize>>);
+
+        impl BuildHasher for CountingHasher {
+            type Hasher = DefaultHasher;
+
+            fn build_hasher(&self) -> DefaultHasher {
+                self.0.set(self.0.get() + 1);
+                DefaultHasher::new()
+            }
+        }
+
+        let mut h = LedgerHistoryHelper::new();
+        let hasher = CountingHasher::default();
+        let mut trie: ArenaLedgerTrie<SimulatedLedger, u32, CountingHasher> =
+            ArenaLedgerTrie::with_hasher(hasher.clone());
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        trie.insert(&abc, Some(2));
+        trie.insert(&abd, None);
+
+        assert_eq!(trie.remove_all(&[((*abc).clone(), 1), ((*abd).clone(), 1)]), Ok(()));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abd), 0);
+        assert!(hasher.0.get() > 0);
+    }
+
+    #[test]
+    fn test_remove_not_in_trie() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+
+        assert!(!remove(&mut trie, &h.get_or_create("ab"), None));
+        assert!(!remove(&mut trie, &h.get_or_create("a"), None));
+    }
+
+    #[test]
+    fn test_remove_in_trie_with_zero_tip() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abce"), None);
+
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+
+        assert!(!remove(&mut trie, &h.get_or_create("abc"), None));
+
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+    }
+
+    #[test]
+    fn test_remove_with_gt_one_tip_support() {
+        let (mut trie, mut h) = setup();
+
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 2);
+        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+
+        insert(&mut trie, &h.get_or_create("abc"), Some(1));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 2);
+        assert!(remove(&mut trie, &h.get_or_create("abc"), Some(2)));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+
+        insert(&mut trie, &h.get_or_create("abc"), Some(3));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 3);
+        assert!(remove(&mut trie, &h.get_or_create("abc"), Some(3)));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+    }
+
+    #[test]
+    fn test_remove_insufficient_support() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, Some(3));
+
+        assert_eq!(
+            trie.remove(&abc, Some(300)),
+            Err(TrieError::InsufficientSupport { available: 3, requested: 300 })
+        );
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 3);
+        assert_eq!(trie.branch_support(&abc), 3);
+
+        assert_eq!(trie.remove(&abc, Some(3)), Ok(true));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 0);
+
+        // No longer in the trie
+        assert_eq!(trie.remove(&abc, Some(1)), Ok(false));
+        assert_eq!(trie.remove(&h.get_or_create("abd"), None), Ok(false));
+    }
+
+    #[test]
+    fn test_remove_with_one_tip_support_no_children() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
+
+        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 0);
+    }
+
+    #[test]
+    fn test_remove_with_one_tip_support_one_child() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 1);
+
+        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 1);
+    }
+
+    #[test]
+    fn test_remove_with_one_tip_support_more_than_one_child() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abce"), None);
+
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 3);
+
+        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+    }
+
+    #[test]
+    fn test_remove_with_one_tip_support_parent_compaction() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abd"), None);
+
+        assert!(remove(&mut trie, &h.get_or_create("ab"), None));
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("abd")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
+
+        remove(&mut trie, &h.get_or_create("abd"), None);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
+    }
+
+    #[test]
+    fn test_remove_keeps_seq_support_of_other_branches() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let aef = h.get_or_create("aef");
+        insert(&mut trie, &abc, Some(2));
+        insert(&mut trie, &abd, Some(3));
+        insert(&mut trie, &aef, None);
+        assert_eq!(trie.seq_support[&3], 6);
+
+        // Removing part of one branch's support only subtracts that part
+        assert!(remove(&mut trie, &abd, Some(2)));
+        assert_eq!(trie.seq_support[&3], 4);
+
+        // As does removing a whole branch
+        assert!(remove(&mut trie, &abd, None));
+        assert_eq!(trie.seq_support[&3], 3);
+        assert!(remove(&mut trie, &abc, Some(2)));
+        assert_eq!(trie.seq_support[&3], 1);
+        assert_eq!(trie.tip_support(&aef), 1);
+
+        // The entry is only dropped once no branch has support at that sequence
+        assert!(remove(&mut trie, &aef, None));
+        assert!(!trie.seq_support.contains_key(&3));
+        assert!(trie.seq_support.is_empty());
+    }
+
+    #[test]
+    fn test_remove_collapses_split_node() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abce = h.get_or_create("abce");
+        let abcf = h.get_or_create("abcf");
+
+        // Inserting abce splits abcd at abc
+        insert(&mut trie, &abcd, None);
+        assert!(trie.check_invariants());
+        insert(&mut trie, &abce, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.arena.get(trie.root).unwrap().children.len(), 1);
+        assert_eq!(trie.branch_support(&abc), 2);
+
+        // Once abc has a single child it merges back with it
+        assert!(remove(&mut trie, &abcd, None));
+        assert!(trie.check_invariants());
+        let (mut expected, _) = setup();
+        insert(&mut expected, &abce, None);
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.branch_support(&abc), 1);
+        assert_eq!(trie.tip_support(&abc), 0);
+
+        assert!(remove(&mut trie, &abce, None));
+        assert!(trie.check_invariants());
+        assert!(trie.empty());
+        assert!(trie.arena.get(trie.root).unwrap().children.is_empty());
+
+        insert(&mut trie, &abcf, None);
+        assert!(trie.check_invariants());
+        let (mut expected, _) = setup();
+        insert(&mut expected, &abcf, None);
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.tip_support(&abcf), 1);
+        assert_eq!(trie.branch_support(&abc), 1);
+        assert_eq!(trie.branch_support(&abcd), 0);
+        assert_eq!(trie.branch_support(&abce), 0);
+
+        // Removing the support of a node between the root and a single child merges the two
+        insert(&mut trie, &ab, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.path_to(abcf.id()).unwrap().len(), 3);
+        assert!(remove(&mut trie, &ab, None));
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.tip_support(&ab), 0);
+        assert_eq!(trie.branch_support(&ab), 1);
+
+        // But a middle node with two children stays, without tip support
+        let abg = h.get_or_create("abg");
+        insert(&mut trie, &ab, None);
+        assert!(trie.check_invariants());
+        insert(&mut trie, &abg, None);
+        assert!(trie.check_invariants());
+        assert!(remove(&mut trie, &ab, None));
+        assert!(trie.check_invariants());
+        let (mut expected, _) = setup();
+        insert(&mut expected, &abcf, None);
+        insert(&mut expected, &abg, None);
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.path_to(abg.id()).unwrap().len(), 3);
+        assert_eq!(trie.tip_support(&ab), 0);
+        assert_eq!(trie.branch_support(&ab), 2);
+    }
+
+    #[test]
+    fn test_support() {
         let (mut trie, mut h) = setup();
-        let ledger = h.get_or_create("abc");
-        insert(&mut trie, &ledger, None);
-        assert_eq!(trie.tip_support(&ledger), 1);
-        assert_eq!(trie.branch_support(&ledger), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("axy")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("axy")), 0);
+
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, None);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 0);
+
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 0);
+
+        insert(&mut trie, &h.get_or_create("abe"), None);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.tip_support(&h.get_or_create("abe")), 1);
+
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 2);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abe")), 1);
+
+        let removed = remove(&mut trie, &h.get_or_create("abc"), None);
+        assert!(removed);
+        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.tip_support(&h.get_or_create("abe")), 1);
+
+        assert_eq!(trie.branch_support(&h.get_or_create("a")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 0);
+        assert_eq!(trie.branch_support(&h.get_or_create("abe")), 1);
+    }
+
+    #[test]
+    fn test_get_preferred_empty_trie() {
+        let trie = ArenaLedgerTrie::<SimulatedLedger>::new();
+        assert!(trie.get_preferred(0).is_none());
+        assert!(trie.get_preferred(2).is_none());
+    }
+
+    #[test]
+    fn test_get_preferred_genesis_support_not_empty() {
+        let (mut trie, mut h) = setup();
+        let genesis = h.get_or_create("");
+        insert(&mut trie, &genesis, None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), genesis.id());
+
+        assert!(remove(&mut trie, &genesis, None));
+        assert!(trie.get_preferred(0).is_none());
+
+        assert!(!remove(&mut trie, &genesis, None));
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_no_children() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_smaller_child_support() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_larger_child() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_smaller_child() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abce"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_smaller_children() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        insert(&mut trie, &h.get_or_create("abce"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+    }
+
+    #[test]
+    fn test_get_preferred_tie_breaker_by_id() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        insert(&mut trie, &h.get_or_create("abce"), Some(2));
+        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abce").id());
+
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+    }
+
+    #[test]
+    fn test_get_preferred_tie_breaker_not_needed() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abce"), Some(2));
+
+        // abce only has a margin of 1, but it owns the tie-breaker
+        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abce").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abce").id());
+
+        // Switch support from abce to abcd, tie-breaker now needed
+        remove(&mut trie, &h.get_or_create("abce"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+    }
+
+    #[test]
+    fn test_get_preferred_single_node_larger_grand_child() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        insert(&mut trie, &h.get_or_create("abcde"), Some(4));
+
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcde").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcde").id());
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abcde").id());
+    }
+
+    #[test]
+    fn test_get_preferred_too_much_uncommitted_support_from_competing_branches() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
+        insert(&mut trie, &h.get_or_create("abcfg"), Some(2));
+
+        // 'de' and 'fg' are tied without 'abc' vote
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abc").id());
+
+        remove(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+
+        // 'de' branch has 3 votes to 2, so earlier sequences see it as
+        // preferred
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcde").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcde").id());
+
+        // However, if you validated a ledger with Seq 5, potentially on
+        // a different branch, you do not yet know if they chose abcd
+        // or abcf because of you, so abc remains preferred
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abc").id());
+    }
+
+    #[test]
+    fn test_get_preferred_change_largest_seq_perspective_changes_preferred_branch() {
+        // Build the tree below with initial tip support annotated
+        //              A
+        //             / \
+        //         B(1)  C(1)
+        //        /  |   |
+        //       H   D   F(1)
+        //           |
+        //           E(2)
+        //           |
+        //           G
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("ac"), None);
+        insert(&mut trie, &h.get_or_create("acf"), None);
+        insert(&mut trie, &h.get_or_create("abde"), Some(2));
+
+        // B has more branch support
+        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
+
+        // But if you last validated D,F or E, you do not yet know
+        // if someone used that validation to commit to B or C
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("a").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
+
+        // One of E advancing to G doesn't change anything
+        //                    A
+        //                   / \
+        //                B(1)  C(1)
+        //               /  |   |
+        //              H   D   F(1)
+        //                  |
+        //                  E(1)
+        //                  |
+        //                  G(1)
+        remove(&mut trie, &h.get_or_create("abde"), None);
+        insert(&mut trie, &h.get_or_create("abdeg"), None);
+
+        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("a").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("a").id());
+
+        // C advancing to H does advance the seq 3 preferred ledger
+        //                    A
+        //                   / \
+        //                B(1)  C
+        //               /  |   |
+        //              H(1)D   F(1)
+        //                  |
+        //                  E(1)
+        //                  |
+        //                  G(1)
+        //
+        remove(&mut trie, &h.get_or_create("ac"), None);
+        insert(&mut trie, &h.get_or_create("abh"), None);
+        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("a").id());
 
-        insert(&mut trie, &ledger, None);
-        assert_eq!(trie.tip_support(&ledger), 2);
-        assert_eq!(trie.branch_support(&ledger), 2);
+        // F advancing to E also moves the preferred ledger forward
+        //                    A
+        //                   / \
+        //                B(1)  C
+        //               /  |   |
+        //              H(1)D   F
+        //                  |
+        //                  E(2)
+        //                  |
+        //                  G(1)
+        remove(&mut trie, &h.get_or_create("acf"), None);
+        insert(&mut trie, &h.get_or_create("abde"), None);
+        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("abde").id());
+        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("abde").id());
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abde").id());
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("ab").id());
     }
 
     #[test]
-    fn test_insert_suffix_of_existing() {
+    fn test_get_preferred_single_chain() {
         let (mut trie, mut h) = setup();
-        let abc = h.get_or_create("abc");
-        insert(&mut trie, &abc, None);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
 
-        // extend with no siblings
-        let abcd = h.get_or_create("abcd");
-        insert(&mut trie, &abcd, None);
-        println!("{}", serde_json::to_string_pretty(&trie).unwrap());
-        assert_eq!(trie.tip_support(&abc), 1);
-        assert_eq!(trie.branch_support(&abc), 2);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 1);
+        // Every validation is on the same chain, so its tip is preferred until we issued past it
+        for largest_issued in 0..=5 {
+            assert_eq!(trie.get_preferred(largest_issued).unwrap().id(), h.get_or_create("abcde").id());
+        }
 
-        // extend with existing sibling
-        let abce = h.get_or_create("abce");
-        insert(&mut trie, &abce, None);
-        assert_eq!(trie.tip_support(&abc), 1);
-        assert_eq!(trie.branch_support(&abc), 3);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 1);
-        assert_eq!(trie.tip_support(&abce), 1);
-        assert_eq!(trie.branch_support(&abce), 1);
+        // Past seq 5, every validator may have moved on to a ledger we have not heard of
+        assert_eq!(trie.get_preferred(6).unwrap().id(), h.get_or_create("").id());
     }
 
     #[test]
-    fn test_insert_uncommitted_of_existing_node() {
+    fn test_get_preferred_two_way_fork() {
+        //          A
+        //          |
+        //          B
+        //         / \
+        //     C(3)   D(2)
         let (mut trie, mut h) = setup();
-        let abcd = h.get_or_create("abcd");
-        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &h.get_or_create("abc"), Some(3));
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
 
-        // uncommitted with no siblings
-        let abcdf = h.get_or_create("abcdf");
-        insert(&mut trie, &abcdf, None);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 2);
-        assert_eq!(trie.tip_support(&abcdf), 1);
-        assert_eq!(trie.branch_support(&abcdf), 1);
+        // Both tips are at seq 3, so nothing is uncommitted before it and C wins 3 to 2
+        for largest_issued in 0..=3 {
+            assert_eq!(trie.get_preferred(largest_issued).unwrap().id(), h.get_or_create("abc").id());
+        }
 
-        // uncommitted with existing child
-        let abc = h.get_or_create("abc");
-        insert(&mut trie, &abc, None);
-        assert_eq!(trie.tip_support(&abc), 1);
-        assert_eq!(trie.branch_support(&abc), 3);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 2);
-        assert_eq!(trie.tip_support(&abcdf), 1);
-        assert_eq!(trie.branch_support(&abcdf), 1);
+        // Once we issued past seq 3, all five validators are uncommitted
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("").id());
+
+        // An even split is broken by the larger id
+        insert(&mut trie, &h.get_or_create("abd"), None);
+        let preferred = trie.get_preferred(0).unwrap().id();
+        assert_eq!(preferred, core::cmp::max(h.get_or_create("abc").id(), h.get_or_create("abd").id()));
     }
 
     #[test]
-    fn test_insert_suffix_and_uncommitted_existing_node() {
+    fn test_get_preferred_largest_issued_makes_support_uncommitted() {
+        //          A
+        //         / \
+        //     B(3)   C
+        //            |
+        //            D(1)
         let (mut trie, mut h) = setup();
-        let abcd = h.get_or_create("abcd");
-        insert(&mut trie, &abcd, None);
-        let abce = h.get_or_create("abce");
-        insert(&mut trie, &abce, None);
+        insert(&mut trie, &h.get_or_create("ab"), Some(3));
+        insert(&mut trie, &h.get_or_create("acd"), None);
 
-        let abc = h.get_or_create("abc");
-        assert_eq!(trie.tip_support(&abc), 0);
-        assert_eq!(trie.branch_support(&abc), 2);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 1);
-        assert_eq!(trie.tip_support(&abce), 1);
-        assert_eq!(trie.branch_support(&abce), 1);
+        // Without a validation of our own past seq 2, the walk only counts validators behind
+        // the fork as uncommitted, so B wins 3 to 1
+        for largest_issued in 0..=2 {
+            assert_eq!(trie.get_preferred(largest_issued).unwrap().id(), h.get_or_create("ab").id());
+        }
+
+        // Having issued seq 3, the validators on B may have followed us onto C, so their
+        // support at seq 2 is uncommitted when choosing between B and C
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("a").id());
+
+        // Once two of them are heard from past seq 2, their support is committed again
+        remove(&mut trie, &h.get_or_create("ab"), Some(2));
+        insert(&mut trie, &h.get_or_create("abe"), Some(2));
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abe").id());
     }
 
     #[test]
-    fn test_insert_suffix_and_uncommitted_with_existing_child() {
-        // abcd : abcde, abcf
+    fn test_get_preferred_with_cutoffs() {
+        //          A
+        //          |
+        //          B
+        //         / \
+        //     C(3)   D(2)
         let (mut trie, mut h) = setup();
-        let abcd = h.get_or_create("abcd");
-        let abcde = h.get_or_create("abcde");
-        let abcf = h.get_or_create("abcf");
-        insert(&mut trie, &abcd, None);
-        insert(&mut trie, &abcde, None);
-        insert(&mut trie, &abcf, None);
-
+        insert(&mut trie, &h.get_or_create("abc"), Some(3));
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
         let abc = h.get_or_create("abc");
-        assert_eq!(trie.tip_support(&abc), 0);
-        assert_eq!(trie.branch_support(&abc), 3);
-        assert_eq!(trie.tip_support(&abcd), 1);
-        assert_eq!(trie.branch_support(&abcd), 2);
-        assert_eq!(trie.tip_support(&abcf), 1);
-        assert_eq!(trie.branch_support(&abcf), 1);
-        assert_eq!(trie.tip_support(&abcde), 1);
-        assert_eq!(trie.branch_support(&abcde), 1);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_with_cutoffs(&BTreeMap::new(), 0).unwrap().id(), abc.id());
+
+        // Two validators projected to still be on B could yet move to D
+        let cutoffs = BTreeMap::from([(2, 2)]);
+        assert_eq!(trie.get_preferred_with_cutoffs(&cutoffs, 0).unwrap().id(), h.get_or_create("ab").id());
+
+        // Even one could tie the branches, and D has the larger ID
+        let cutoffs = BTreeMap::from([(2, 1)]);
+        assert!(h.get_or_create("abd").id() > abc.id());
+        assert_eq!(trie.get_preferred_with_cutoffs(&cutoffs, 0).unwrap().id(), h.get_or_create("ab").id());
+
+        // Projecting no support at seq 3 leaves nothing uncommitted even having issued past it
+        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("").id());
+        let cutoffs = BTreeMap::from([(3, 0)]);
+        assert_eq!(trie.get_preferred_with_cutoffs(&cutoffs, 4).unwrap().id(), abc.id());
+
+        // The trie itself is unchanged
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 5);
+
+        // The cutoffs are merged into the trie's support in order, replacing it where they overlap
+        let cutoffs = BTreeMap::from([(1, 4), (3, 1), (5, 2)]);
+        let merged: Vec<(LedgerIndex, u32)> = trie._seq_support_with(&cutoffs).collect();
+        assert_eq!(merged, vec![(1, 4), (3, 1), (5, 2)]);
+        let cutoffs = BTreeMap::from([(0, 1), (4, 2)]);
+        let merged: Vec<(LedgerIndex, u32)> = trie._seq_support_with(&cutoffs).collect();
+        assert_eq!(merged, vec![(0, 1), (3, 5), (4, 2)]);
     }
 
     #[test]
-    fn test_insert_multiple_counts() {
+    fn test_get_preferred_with_extra() {
+        //          A
+        //          |
+        //          B
+        //         / \
+        //     C(3)   D(1)
         let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        insert(&mut trie, &abc, Some(3));
+        insert(&mut trie, &h.get_or_create("abd"), None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_with_extra((2, 0), 0).unwrap().unwrap().id(), abc.id());
+
+        // One more validator still on B cannot make up C's lead
+        assert_eq!(trie.get_preferred_with_extra((2, 1), 0).unwrap().unwrap().id(), abc.id());
+
+        // Two more could, so the walk does not descend into C
         let ab = h.get_or_create("ab");
-        insert(&mut trie, &ab, Some(4));
-        assert_eq!(trie.tip_support(&ab), 4);
+        assert_eq!(trie.get_preferred_with_extra((2, 2), 0).unwrap().unwrap().id(), ab.id());
+
+        // Support at the fork itself is already committed to a child
+        assert_eq!(trie.get_preferred_with_extra((3, 2), 0).unwrap().unwrap().id(), abc.id());
+
+        // Extra support up to what a u32 can count, but no more
+        assert_eq!(trie.get_preferred_with_extra((2, u32::MAX), 0).unwrap().unwrap().id(), ab.id());
+        assert_eq!(trie.get_preferred_with_extra((3, u32::MAX - 4), 0).unwrap().unwrap().id(), abc.id());
+        assert_eq!(
+            trie.get_preferred_with_extra((3, u32::MAX - 3), 0).err(),
+            Some(TrieError::SupportOverflow { total: 4, requested: u32::MAX - 3 })
+        );
+
+        // The trie itself is unchanged
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
         assert_eq!(trie.branch_support(&ab), 4);
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 4);
+    }
+
+    #[test]
+    fn test_abandoned_branches() {
+        //          A(1)
+        //          |
+        //          B
+        //        / | \
+        //    E(3) C(4) D(1)
+        let (mut trie, mut h) = setup();
+        assert!(trie.abandoned_branches(0).is_empty());
 
+        let abe = h.get_or_create("abe");
         let abc = h.get_or_create("abc");
-        insert(&mut trie, &abc, Some(2));
-        assert_eq!(trie.tip_support(&abc), 2);
-        assert_eq!(trie.branch_support(&abc), 2);
-        assert_eq!(trie.tip_support(&ab), 4);
-        assert_eq!(trie.branch_support(&ab), 6);
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 6);
+        let abd = h.get_or_create("abd");
+        assert!(abc.id() > abe.id());
+        insert(&mut trie, &abe, Some(3));
+        insert(&mut trie, &abc, Some(4));
+        insert(&mut trie, &abd, None);
+        insert(&mut trie, &h.get_or_create("a"), None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        // E could still tie C with the uncommitted support on A, but D cannot catch up
+        let abandoned = trie.abandoned_branches(0);
+        assert_eq!(abandoned.len(), 1);
+        assert_eq!((abandoned[0].seq(), abandoned[0].id()), (abd.seq(), abd.id()));
+
+        // Without a preferred child of B, none of its children are abandoned yet
+        insert(&mut trie, &h.get_or_create("a"), None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("ab").id());
+        assert!(trie.abandoned_branches(0).is_empty());
     }
 
     #[test]
-    fn test_remove_not_in_trie() {
+    fn test_preferred_fork_margin() {
         let (mut trie, mut h) = setup();
+        assert_eq!(trie.preferred_fork_margin(0), None);
+
+        // Without a fork there is no margin
         insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
+        assert_eq!(trie.preferred_fork_margin(0), Some(0));
+        assert_eq!(trie.preferred_fork_margin(10), Some(0));
+
+        // A close fork held back by the uncommitted support on B
+        //          A
+        //          |
+        //          B(1)
+        //         / \
+        //     F(2)   G(1)
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("abf"), Some(2));
+        insert(&mut trie, &h.get_or_create("abg"), None);
+        assert!(h.get_or_create("abf").id() < h.get_or_create("abg").id());
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.preferred_fork_margin(0), Some(1));
+
+        // The tie-breaker counts towards the margin
+        insert(&mut trie, &h.get_or_create("abg"), None);
+        assert_eq!(trie.preferred_fork_margin(0), Some(1));
+
+        // A decisive lead that still doesn't exceed the uncommitted support
+        insert(&mut trie, &h.get_or_create("ab"), Some(9));
+        insert(&mut trie, &h.get_or_create("abf"), Some(6));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.preferred_fork_margin(0), Some(6));
+
+        // Once it does, the walk moves past the fork
+        remove(&mut trie, &h.get_or_create("ab"), Some(9));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("abf").id());
+        assert_eq!(trie.preferred_fork_margin(0), Some(0));
+    }
 
-        assert!(!remove(&mut trie, &h.get_or_create("ab"), None));
-        assert!(!remove(&mut trie, &h.get_or_create("a"), None));
+    #[test]
+    fn test_get_preferred_with_support() {
+        let (mut trie, mut h) = setup();
+        assert!(trie.get_preferred_with_support(0).is_none());
+
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abce = h.get_or_create("abce");
+        insert(&mut trie, &abc, Some(2));
+        insert(&mut trie, &abcd, Some(3));
+        insert(&mut trie, &abce, None);
+
+        // With more uncommitted support the preferred ledger moves into the middle of a span
+        for largest_issued in [0, 10] {
+            let (tip, support) = trie.get_preferred_with_support(largest_issued).unwrap();
+            assert_eq!(tip.id(), trie.get_preferred(largest_issued).unwrap().id());
+            let ledger = h.get_or_create(&"abcd"[..tip.seq() as usize]);
+            assert_eq!(ledger.id(), tip.id());
+            assert_eq!(support, trie.branch_support(&ledger));
+        }
     }
 
     #[test]
-    fn test_remove_in_trie_with_zero_tip() {
+    fn test_preferred_seq() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        insert(&mut trie, &h.get_or_create("abce"), None);
+        assert_eq!(trie.preferred_seq(0), None);
 
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+        // Forks at different depths, so the walk stops inside a span, at a fork or at a tip
+        // depending on the largest issued sequence
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
+        insert(&mut trie, &h.get_or_create("abcf"), Some(3));
+        insert(&mut trie, &h.get_or_create("ag"), None);
+        for largest_issued in 0..=6 {
+            assert_eq!(
+                trie.preferred_seq(largest_issued),
+                trie.get_preferred(largest_issued).map(|tip| tip.seq()),
+                "largest issued {}", largest_issued,
+            );
+        }
+    }
 
-        assert!(!remove(&mut trie, &h.get_or_create("abc"), None));
+    #[test]
+    fn test_get_preferred_confident() {
+        let (mut trie, mut h) = setup();
+        assert!(trie.get_preferred_confident(0, 0).is_none());
 
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred_confident(3, 1).unwrap().id(), h.get_or_create("abcd").id());
+        assert_eq!(trie.get_preferred_confident(3, 2).unwrap().id(), h.get_or_create("abcd").id());
+        assert!(trie.get_preferred_confident(3, 3).is_none());
+
+        // Tied children leave their parent as the preferred ledger
+        insert(&mut trie, &h.get_or_create("abcdef"), Some(3));
+        insert(&mut trie, &h.get_or_create("abcdeg"), Some(3));
+        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcde").id());
+        assert_eq!(trie.get_preferred_confident(3, 6).unwrap().id(), h.get_or_create("abcde").id());
+        assert!(trie.get_preferred_confident(3, 7).is_none());
     }
 
     #[test]
-    fn test_remove_with_gt_one_tip_support() {
+    fn test_get_preferred_diagnostic() {
         let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        assert!(matches!(trie.get_preferred_diagnostic(0), PreferredResult::Empty));
 
-        insert(&mut trie, &h.get_or_create("abc"), Some(2));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 2);
-        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &abd, None);
 
-        insert(&mut trie, &h.get_or_create("abc"), Some(1));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 2);
-        assert!(remove(&mut trie, &h.get_or_create("abc"), Some(2)));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        // With no uncommitted support the tie is broken by ID
+        match trie.get_preferred_diagnostic(0) {
+            PreferredResult::Decided(tip) => assert_eq!(tip.id(), trie.get_preferred(0).unwrap().id()),
+            _ => panic!("expected a decided preferred ledger"),
+        }
 
-        insert(&mut trie, &h.get_or_create("abc"), Some(3));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 3);
-        assert!(remove(&mut trie, &h.get_or_create("abc"), Some(300)));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
+        // Uncommitted support for ab stops the search at the tie
+        insert(&mut trie, &ab, None);
+        match trie.get_preferred_diagnostic(0) {
+            PreferredResult::Tied { candidates } => {
+                let mut ids: Vec<LedgerId> = candidates.iter().map(|tip| tip.id()).collect();
+                ids.sort();
+                let mut expected = vec![abc.id(), abd.id()];
+                expected.sort();
+                assert_eq!(ids, expected);
+            }
+            _ => panic!("expected a tie"),
+        }
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ab.id());
+
+        // Breaking the tie decides the preferred ledger
+        insert(&mut trie, &abd, Some(2));
+        match trie.get_preferred_diagnostic(0) {
+            PreferredResult::Decided(tip) => assert_eq!(tip.id(), abd.id()),
+            _ => panic!("expected a decided preferred ledger"),
+        }
     }
 
     #[test]
-    fn test_remove_with_one_tip_support_no_children() {
+    fn test_get_preferred_from() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("ab"), None);
-        insert(&mut trie, &h.get_or_create("abc"), None);
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let abde = h.get_or_create("abde");
+        assert!(trie.get_preferred_from(h.get_or_create("").id(), 0).is_none());
+
+        insert(&mut trie, &abc, Some(6));
+        insert(&mut trie, &abd, None);
+        insert(&mut trie, &abde, Some(4));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        // Anchored at abd, the larger abc branch is ignored. Its support is still
+        // uncommitted for abde.
+        assert_eq!(trie.get_preferred_from(abd.id(), 0).unwrap().id(), abd.id());
+        insert(&mut trie, &abde, Some(4));
+        assert_eq!(trie.get_preferred_from(abd.id(), 0).unwrap().id(), abde.id());
+
+        // Anchoring at the root is the same as not anchoring
+        assert_eq!(
+            trie.get_preferred_from(h.get_or_create("").id(), 0).unwrap().id(),
+            trie.get_preferred(0).unwrap().id()
+        );
+        assert_eq!(trie.get_preferred_from(ab.id(), 0).unwrap().id(), trie.get_preferred(0).unwrap().id());
+
+        // Ledgers that are not at the tip of a node can not be anchors
+        assert!(trie.get_preferred_from(h.get_or_create("a").id(), 0).is_none());
+        assert!(trie.get_preferred_from(h.get_or_create("abf").id(), 0).is_none());
+
+        // Heavy support outside the anchor is uncommitted past its sequence number, and holds
+        // the walk back at the anchor
+        let g = h.get_or_create("g");
+        insert(&mut trie, &g, Some(20));
+        assert_eq!(trie.get_preferred_from(abd.id(), 0).unwrap().id(), abd.id());
+        remove(&mut trie, &g, Some(20));
+        assert_eq!(trie.get_preferred_from(abd.id(), 0).unwrap().id(), abde.id());
+    }
 
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
+    #[test]
+    fn test_empty() {
+        let (mut trie, mut h) = setup();
+        assert!(trie.empty());
 
-        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 0);
+        insert(&mut trie, &h.get_or_create(""), None);
+        assert!(!trie.empty());
+        remove(&mut trie, &h.get_or_create(""), None);
+        assert!(trie.empty());
+
+        insert(&mut trie, &h.get_or_create("abc"), None);
+        assert!(!trie.empty());
+        remove(&mut trie, &h.get_or_create("abc"), None);
+        assert!(trie.empty());
     }
 
     #[test]
-    fn test_remove_with_one_tip_support_one_child() {
+    fn test_total_support() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("ab"), None);
+        assert_eq!(trie.total_support(), 0);
+
+        // Every validation counts once, wherever it is in the trie
         insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
+        insert(&mut trie, &h.get_or_create("abe"), Some(3));
+        assert_eq!(trie.total_support(), 6);
+        insert(&mut trie, &h.get_or_create(""), None);
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        assert_eq!(trie.total_support(), 8);
 
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
-        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 1);
+        remove(&mut trie, &h.get_or_create("abe"), Some(2));
+        remove(&mut trie, &h.get_or_create(""), None);
+        assert_eq!(trie.total_support(), 5);
+        assert!(!remove(&mut trie, &h.get_or_create("abf"), None));
+        assert_eq!(trie.total_support(), 5);
+
+        for name in ["abc", "abcd", "abe", "ab"] {
+            let ledger = h.get_or_create(name);
+            let tip_support = trie.tip_support(&ledger);
+            remove(&mut trie, &ledger, Some(tip_support));
+        }
+        assert_eq!(trie.total_support(), 0);
+        assert!(trie.empty());
+    }
 
-        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 1);
+    /// Support a ledger, then check the trie is empty again once the support is removed.
+    fn check_empty_after_remove<L: LedgerTrie<SimulatedLedger>>(ledger: &SimulatedLedger) {
+        let mut trie = L::default();
+        assert!(trie.empty());
+
+        trie.insert(ledger, None);
+        assert!(!trie.empty());
+        assert!(trie.remove(ledger, None).unwrap());
+        assert!(trie.empty());
     }
 
     #[test]
-    fn test_remove_with_one_tip_support_more_than_one_child() {
+    fn test_empty_generic() {
+        let (_, mut h) = setup();
+        let ledger = h.get_or_create("abc");
+        check_empty_after_remove::<ArenaLedgerTrie<SimulatedLedger>>(&ledger);
+        check_empty_after_remove::<ArenaLedgerTrie<SimulatedLedger, u64>>(&ledger);
+        check_empty_after_remove::<SnapshotLedgerTrie<SimulatedLedger>>(&ledger);
+    }
+
+    #[test]
+    fn test_root_related() {
+        // Since the root is a special node that breaks the no-single child
+        // invariant, do some tests that exercise it.
+
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("ab"), None);
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        insert(&mut trie, &h.get_or_create("abce"), None);
+        let genesis = h.get_or_create("");
+        assert!(!remove(&mut trie, &genesis, None));
+        assert_eq!(trie.branch_support(&genesis), 0);
+        assert_eq!(trie.tip_support(&genesis), 0);
+
+        let a = h.get_or_create("a");
+        insert(&mut trie, &a, None);
+        assert_eq!(trie.branch_support(&genesis), 1);
+        assert_eq!(trie.tip_support(&genesis), 0);
 
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 3);
+        let e = h.get_or_create("e");
+        insert(&mut trie, &e, None);
+        assert_eq!(trie.branch_support(&genesis), 2);
+        assert_eq!(trie.tip_support(&genesis), 0);
 
-        assert!(remove(&mut trie, &h.get_or_create("abc"), None));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 2);
+        assert!(remove(&mut trie, &e, None));
+        assert_eq!(trie.branch_support(&genesis), 1);
+        assert_eq!(trie.tip_support(&genesis), 0);
     }
 
     #[test]
-    fn test_remove_with_one_tip_support_parent_compaction() {
+    fn test_get_preferred_min_seq() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("ab"), None);
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abd"), None);
+        let ab = h.get_or_create("ab");
+        let abcde = h.get_or_create("abcde");
+        let abcfg = h.get_or_create("abcfg");
+        insert(&mut trie, &ab, Some(3));
+        insert(&mut trie, &abcde, Some(2));
+        insert(&mut trie, &abcfg, Some(1));
 
-        assert!(remove(&mut trie, &h.get_or_create("ab"), None));
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("abd")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
+        // Support for ab could still go to a sibling of abcde or abcfg
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ab.id());
+        assert_eq!(trie.get_preferred_min_seq(0, 0).unwrap().id(), ab.id());
 
-        remove(&mut trie, &h.get_or_create("abd"), None);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
+        // Once ab is validated history, the branch with the most support is preferred
+        assert_eq!(trie.get_preferred_min_seq(3, 0).unwrap().id(), abcde.id());
+        assert_eq!(trie.get_preferred_min_seq(5, 0).unwrap().id(), abcde.id());
+
+        // There is no preferred ledger past all of the trie's ledgers
+        assert!(trie.get_preferred_min_seq(6, 0).is_none());
+
+        // Nor one when the branches after min_seq are too close to call
+        let (mut trie, _) = setup();
+        insert(&mut trie, &abcde, None);
+        insert(&mut trie, &abcfg, None);
+        assert!(trie.get_preferred(6).unwrap().seq() < 4);
+        assert!(trie.get_preferred_min_seq(4, 6).is_none());
     }
 
     #[test]
-    fn test_support() {
+    fn test_preferred_changed_since() {
         let (mut trie, mut h) = setup();
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("axy")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("axy")), 0);
-
         let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
         insert(&mut trie, &abc, None);
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("abcd")), 0);
-
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abcd")), 0);
-
-        insert(&mut trie, &h.get_or_create("abe"), None);
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.tip_support(&h.get_or_create("abe")), 1);
+        let previous = trie.get_preferred(0).unwrap();
+        assert!(!trie.preferred_changed_since(&previous, 0));
 
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 2);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abe")), 1);
+        // More support for the preferred ledger doesn't change it
+        insert(&mut trie, &abc, None);
+        assert!(!trie.preferred_changed_since(&previous, 0));
+
+        // Support for a competing branch does once it takes the lead
+        insert(&mut trie, &abd, Some(3));
+        assert!(trie.preferred_changed_since(&previous, 0));
+        let previous = trie.get_preferred(0).unwrap();
+        assert_eq!(previous.id(), abd.id());
+        assert!(!trie.preferred_changed_since(&previous, 0));
+
+        // As does losing all support
+        remove(&mut trie, &abc, Some(2));
+        remove(&mut trie, &abd, Some(3));
+        assert!(trie.preferred_changed_since(&previous, 0));
+    }
 
-        let removed = remove(&mut trie, &h.get_or_create("abc"), None);
-        assert!(removed);
-        assert_eq!(trie.tip_support(&h.get_or_create("a")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("ab")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.tip_support(&h.get_or_create("abe")), 1);
+    #[test]
+    fn test_get_preferred_without_seq_support() {
+        let (mut trie, mut h) = setup();
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        insert(&mut trie, &h.get_or_create("abcde"), None);
+        insert(&mut trie, &h.get_or_create("abfg"), Some(2));
+        insert(&mut trie, &h.get_or_create("abfh"), Some(3));
+
+        // Without any uncommitted support the walk follows the most supported branch to its tip,
+        // even if the seq support no longer matches the nodes
+        trie.seq_support.clear();
+        assert!(!trie.check_invariants());
+        for largest_issued in [0, 3, 10] {
+            assert_eq!(trie.get_preferred(largest_issued).unwrap().id(), h.get_or_create("abfh").id());
+        }
 
-        assert_eq!(trie.branch_support(&h.get_or_create("a")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
-        assert_eq!(trie.branch_support(&h.get_or_create("abc")), 0);
-        assert_eq!(trie.branch_support(&h.get_or_create("abe")), 1);
+        // Ties are still broken by the larger ID
+        let (mut trie, _) = setup();
+        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
+        insert(&mut trie, &h.get_or_create("abfg"), Some(2));
+        trie.seq_support.clear();
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("abfg").id());
     }
 
     #[test]
-    fn test_get_preferred_empty_trie() {
-        let trie = ArenaLedgerTrie::<SimulatedLedger>::new();
-        assert!(trie.get_preferred(0).is_none());
-        assert!(trie.get_preferred(2).is_none());
+    fn test_get_preferred_incremental() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abe = h.get_or_create("abe");
+        insert(&mut trie, &abc, None);
+        let prev = trie.get_preferred(0).unwrap();
+        assert_eq!(trie.get_preferred_incremental(&prev, 0).unwrap().id(), abc.id());
+
+        // The preferred ledger extends the previous one, so the walk follows it
+        insert(&mut trie, &abcd, Some(2));
+        let prev = trie.get_preferred_incremental(&prev, 0).unwrap();
+        assert_eq!(prev.id(), abcd.id());
+
+        // A competing branch takes the lead, so the previous ledger is off the preferred path
+        insert(&mut trie, &abe, Some(4));
+        assert_eq!(trie.get_preferred_incremental(&prev, 0).unwrap().id(), abe.id());
+
+        // A ledger within a node's span is followed as far as the walk agrees with it
+        let a = Span::from((*h.get_or_create("a")).clone()).tip();
+        assert_eq!(trie.get_preferred_incremental(&a, 0).unwrap().id(), abe.id());
+
+        // Whichever ledger the walk follows, it finds the same preferred ledger
+        insert(&mut trie, &h.get_or_create("abcdf"), Some(3));
+        insert(&mut trie, &h.get_or_create("abcdg"), None);
+        insert(&mut trie, &h.get_or_create("hi"), Some(2));
+        for largest_issued in 0..=6 {
+            let expected = trie.get_preferred(largest_issued).map(|tip| tip.id());
+            for name in ["abc", "abcd", "abe", "abcdf", "abcdg", "hi", "ab", "a"] {
+                let prev = Span::from((*h.get_or_create(name)).clone()).tip();
+                let actual = trie.get_preferred_incremental(&prev, largest_issued).map(|tip| tip.id());
+                assert_eq!(actual, expected, "from {} with largest issued {}", name, largest_issued);
+            }
+        }
+
+        remove(&mut trie, &abe, Some(4));
+        assert_eq!(trie.get_preferred_incremental(&prev, 0).map(|tip| tip.id()), trie.get_preferred(0).map(|tip| tip.id()));
     }
 
     #[test]
-    fn test_get_preferred_genesis_support_not_empty() {
+    fn test_get_preferred_excluding() {
         let (mut trie, mut h) = setup();
-        let genesis = h.get_or_create("");
-        insert(&mut trie, &genesis, None);
-        assert_eq!(trie.get_preferred(0).unwrap().id(), genesis.id());
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        insert(&mut trie, &abc, Some(2));
+        insert(&mut trie, &abd, Some(2));
 
-        assert!(remove(&mut trie, &genesis, None));
-        assert!(trie.get_preferred(0).is_none());
+        // The tie goes to one branch, excluding a validation for it hands the other the lead
+        let preferred = trie.get_preferred(0).unwrap().id();
+        let other = if preferred == abc.id() { abd.id() } else { abc.id() };
+        assert_eq!(trie.get_preferred_excluding(preferred, 0).unwrap().id(), other);
+        assert_eq!(trie.get_preferred_excluding(other, 0).unwrap().id(), preferred);
 
-        assert!(!remove(&mut trie, &genesis, None));
+        // Excluding a ledger without tip support changes nothing
+        assert_eq!(trie.get_preferred_excluding(ab.id(), 0).unwrap().id(), preferred);
+
+        // The trie itself is unchanged
+        assert_eq!(trie.tip_support(&abc), 2);
+        assert_eq!(trie.tip_support(&abd), 2);
+        assert!(trie.check_invariants());
+
+        // Excluding the only validation leaves no preferred ledger
+        let (mut trie, _) = setup();
+        insert(&mut trie, &abc, None);
+        assert!(trie.get_preferred_excluding(abc.id(), 0).is_none());
+
+        // A validation for abc is uncommitted support that could still go to a sibling of abce,
+        // so excluding it is what lets the preferred ledger advance to abce
+        let abce = h.get_or_create("abce");
+        insert(&mut trie, &abce, None);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_excluding(abc.id(), 0).unwrap().id(), abce.id());
+        assert_eq!(trie.get_preferred_excluding(abce.id(), 0).unwrap().id(), abc.id());
     }
 
     #[test]
-    fn test_get_preferred_single_node_no_children() {
+    fn test_leaf_and_fork_count() {
         let (mut trie, mut h) = setup();
+        assert_eq!((trie.leaf_count(), trie.fork_count()), (0, 0));
+
+        // A linear chain
+        insert(&mut trie, &h.get_or_create("ab"), None);
         insert(&mut trie, &h.get_or_create("abc"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        assert_eq!((trie.leaf_count(), trie.fork_count()), (1, 0));
+
+        // A two-way fork
+        let (mut trie, _) = setup();
+        insert(&mut trie, &h.get_or_create("abcd"), None);
+        insert(&mut trie, &h.get_or_create("abe"), None);
+        assert_eq!((trie.leaf_count(), trie.fork_count()), (2, 1));
+
+        // Forking from genesis makes the root a fork
+        insert(&mut trie, &h.get_or_create("f"), None);
+        insert(&mut trie, &h.get_or_create("abcg"), None);
+        assert_eq!((trie.leaf_count(), trie.fork_count()), (4, 3));
     }
 
     #[test]
-    fn test_get_preferred_single_node_smaller_child_support() {
+    fn test_verify_support() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let abe = h.get_or_create("abe");
+        insert(&mut trie, &abc, Some(2));
+        insert(&mut trie, &abd, None);
+
+        let mut expected = HashMap::new();
+        expected.insert(abc.id(), 2);
+        expected.insert(abd.id(), 1);
+        expected.insert(ab.id(), 0);
+        assert_eq!(trie.verify_support(&expected), Ok(()));
+
+        // Wrong, missing and unexpected support are all reported
+        expected.insert(abc.id(), 3);
+        expected.remove(&abd.id());
+        expected.insert(abe.id(), 1);
+        let mut mismatches = vec![(abc.id(), 3, 2), (abd.id(), 0, 1), (abe.id(), 1, 0)];
+        mismatches.sort();
+        assert_eq!(trie.verify_support(&expected), Err(mismatches));
     }
 
     #[test]
-    fn test_get_preferred_single_node_larger_child() {
+    fn test_write_tree() {
         let (mut trie, mut h) = setup();
         insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcd").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
+
+        let mut out: Vec<u8> = vec![];
+        trie.write_tree(&mut out).unwrap();
+        let expected = format!(
+            "Span(0..1, start: {0:x}, tip: {0:x}) tip_support: 0 branch_support: 3\n\
+             \x20 Span(1..3, start: {1:x}, tip: {2:x}) tip_support: 0 branch_support: 3\n\
+             \x20   Span(3..4, start: {3:x}, tip: {3:x}) tip_support: 1 branch_support: 1\n\
+             \x20   Span(3..4, start: {4:x}, tip: {4:x}) tip_support: 2 branch_support: 2\n",
+            h.get_or_create("").id(),
+            h.get_or_create("a").id(),
+            h.get_or_create("ab").id(),
+            h.get_or_create("abc").id(),
+            h.get_or_create("abd").id(),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
     }
 
     #[test]
-    fn test_get_preferred_single_node_smaller_child() {
+    fn test_assert_consistent_with() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        insert(&mut trie, &h.get_or_create("abce"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+        let abcde = h.get_or_create("abcde");
+        let chain = |seq: LedgerIndex| if seq <= abcde.seq() { Some(abcde.get_ancestor(seq)) } else { None };
+        assert_eq!(trie.assert_consistent_with(&chain), Ok(()));
 
+        // Ledgers on the trusted chain, or past its end, are consistent with it
         insert(&mut trie, &h.get_or_create("abc"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+        insert(&mut trie, &abcde, Some(2));
+        insert(&mut trie, &h.get_or_create("abcdefg"), None);
+        assert_eq!(trie.assert_consistent_with(&chain), Ok(()));
+
+        // A fork is reported where it leaves the chain, and the earliest fork is reported
+        insert(&mut trie, &h.get_or_create("abcdh"), None);
+        assert_eq!(trie.assert_consistent_with(&chain), Err(5));
+        insert(&mut trie, &h.get_or_create("abij"), None);
+        assert_eq!(trie.assert_consistent_with(&chain), Err(3));
+
+        // Checking against a shorter chain ignores the forks after it ends
+        let short_chain = |seq: LedgerIndex| if seq <= 2 { Some(abcde.get_ancestor(seq)) } else { None };
+        assert_eq!(trie.assert_consistent_with(&short_chain), Ok(()));
     }
 
     #[test]
-    fn test_get_preferred_single_node_smaller_children() {
+    fn test_debug() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
-        insert(&mut trie, &h.get_or_create("abce"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
-
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcd").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+        insert(&mut trie, &h.get_or_create("ab"), None);
+        insert(&mut trie, &h.get_or_create("ac"), Some(2));
+        assert_eq!(
+            format!("{:?}", trie),
+            "ArenaLedgerTrie { root: Node { span: Span(0..1, start: 0, tip: 0), \
+            tip_support: 0, partial_support: 0, branch_support: 3, children: [\
+            Node { span: Span(1..2, start: 1, tip: 1), tip_support: 0, \
+            partial_support: 0, branch_support: 3, children: [\
+            Node { span: Span(2..3, start: 2, tip: 2), tip_support: 1, \
+            partial_support: 0, branch_support: 1 }, \
+            Node { span: Span(2..3, start: 3, tip: 3), tip_support: 2, \
+            partial_support: 0, branch_support: 2 }] }] }, seq_support: {2: 3} }"
+        );
     }
 
     #[test]
-    fn test_get_preferred_tie_breaker_by_id() {
+    fn test_edges() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
-        insert(&mut trie, &h.get_or_create("abce"), Some(2));
-        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abce").id());
+        assert!(trie.edges().is_empty());
 
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcd").id());
+        let abc = h.get_or_create("abc");
+        let abcd = h.get_or_create("abcd");
+        let abe = h.get_or_create("abe");
+        let f = h.get_or_create("f");
+        insert(&mut trie, &abcd, None);
+        insert(&mut trie, &abe, None);
+        insert(&mut trie, &abc, None);
+        insert(&mut trie, &f, None);
+
+        let genesis = abc.get_ancestor(0);
+        let ab = abc.get_ancestor(2);
+        let mut edges = trie.edges();
+        edges.sort();
+        let mut expected = vec![
+            (genesis, ab),
+            (genesis, f.id()),
+            (ab, abc.id()),
+            (ab, abe.id()),
+            (abc.id(), abcd.id()),
+        ];
+        expected.sort();
+        assert_eq!(edges, expected);
     }
 
     #[test]
-    fn test_get_preferred_tie_breaker_not_needed() {
+    fn test_support_histogram() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        insert(&mut trie, &h.get_or_create("abce"), Some(2));
+        assert_eq!(trie.support_histogram(), BTreeMap::from([(0, 1)]));
 
-        // abce only has a margin of 1, but it owns the tie-breaker
-        assert!(&h.get_or_create("abce").id() > &h.get_or_create("abcd").id());
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abce").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abce").id());
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
+        insert(&mut trie, &h.get_or_create("abde"), None);
+        insert(&mut trie, &h.get_or_create("f"), Some(3));
 
-        // Switch support from abce to abcd, tie-breaker now needed
-        remove(&mut trie, &h.get_or_create("abce"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
+        // The root and ab have no tip support
+        assert_eq!(trie.support_histogram(), BTreeMap::from([(0, 2), (1, 1), (2, 2), (3, 1)]));
     }
 
     #[test]
-    fn test_get_preferred_single_node_larger_grand_child() {
+    fn test_most_supported() {
         let (mut trie, mut h) = setup();
+        assert!(trie.most_supported().is_none());
+
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
+        insert(&mut trie, &h.get_or_create("f"), Some(3));
+
+        // The ab branch is preferred as a whole, but f is the best supported single leaf
+        let (tip, support) = trie.most_supported().unwrap();
+        assert_eq!(tip.id(), h.get_or_create("f").id());
+        assert_eq!(support, 3);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("ab").id());
+
+        // Ties go to the larger ID, like in the preferred walk
+        insert(&mut trie, &h.get_or_create("abd"), None);
         insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), Some(2));
-        insert(&mut trie, &h.get_or_create("abcde"), Some(4));
+        assert_eq!(trie.most_supported().unwrap().0.id(), h.get_or_create("f").id());
 
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcde").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcde").id());
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abcde").id());
+        // Support on an inner node doesn't make it a leaf
+        insert(&mut trie, &h.get_or_create("ab"), Some(5));
+        assert_eq!(trie.most_supported().unwrap().0.id(), h.get_or_create("f").id());
     }
 
     #[test]
-    fn test_get_preferred_too_much_uncommitted_support_from_competing_branches() {
+    fn test_rebalance() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcde"), Some(2));
-        insert(&mut trie, &h.get_or_create("abcfg"), Some(2));
-
-        // 'de' and 'fg' are tied without 'abc' vote
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abc").id());
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abc").id());
-
-        remove(&mut trie, &h.get_or_create("abc"), None);
-        insert(&mut trie, &h.get_or_create("abcd"), None);
+        // Insert the weaker branches first so the children start out of order
+        insert(&mut trie, &h.get_or_create("abc"), Some(1));
+        insert(&mut trie, &h.get_or_create("abd"), Some(3));
+        insert(&mut trie, &h.get_or_create("abe"), Some(2));
+        insert(&mut trie, &h.get_or_create("f"), Some(1));
+        insert(&mut trie, &h.get_or_create("g"), Some(1));
+        insert(&mut trie, &h.get_or_create("h"), Some(1));
+
+        let is_ordered = |trie: &ArenaLedgerTrie<SimulatedLedger>| {
+            trie.arena.iter().all(|(_, node)| {
+                node.children.windows(2).all(|w| {
+                    let first = trie.arena.get(w[0]).unwrap();
+                    let second = trie.arena.get(w[1]).unwrap();
+                    (first.branch_support, first.span.start_id())
+                        >= (second.branch_support, second.span.start_id())
+                })
+            })
+        };
+        assert!(!is_ordered(&trie));
 
-        // 'de' branch has 3 votes to 2, so earlier sequences see it as
-        // preferred
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abcde").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("abcde").id());
+        let before: Vec<_> = (0..8)
+            .map(|largest_issued| trie.get_preferred(largest_issued).map(|tip| tip.id()))
+            .collect();
+        let version = trie.version();
+        trie.rebalance();
 
-        // However, if you validated a ledger with Seq 5, potentially on
-        // a different branch, you do not yet know if they chose abcd
-        // or abcf because of you, so abc remains preferred
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("abc").id());
+        assert!(is_ordered(&trie));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.version(), version);
+        let after: Vec<_> = (0..8)
+            .map(|largest_issued| trie.get_preferred(largest_issued).map(|tip| tip.id()))
+            .collect();
+        assert_eq!(before, after);
+        assert_eq!(after[0], Some(h.get_or_create("ab").id()));
+
+        // Later changes can leave the children out of order again without affecting the walk
+        insert(&mut trie, &h.get_or_create("h"), Some(9));
+        assert!(!is_ordered(&trie));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("h").id());
     }
 
     #[test]
-    fn test_get_preferred_change_largest_seq_perspective_changes_preferred_branch() {
-        // Build the tree below with initial tip support annotated
-        //              A
-        //             / \
-        //         B(1)  C(1)
-        //        /  |   |
-        //       H   D   F(1)
-        //           |
-        //           E(2)
-        //           |
-        //           G
+    fn test_set_locked() {
         let (mut trie, mut h) = setup();
-        insert(&mut trie, &h.get_or_create("ab"), None);
-        insert(&mut trie, &h.get_or_create("ac"), None);
-        insert(&mut trie, &h.get_or_create("acf"), None);
-        insert(&mut trie, &h.get_or_create("abde"), Some(2));
-
-        // B has more branch support
-        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
-
-        // But if you last validated D,F or E, you do not yet know
-        // if someone used that validation to commit to B or C
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("a").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
+        let abc = h.get_or_create("abc");
+        let ade = h.get_or_create("ade");
+        insert(&mut trie, &abc, Some(1));
+        insert(&mut trie, &ade, Some(3));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ade.id());
+
+        // Only ledgers in the trie can be locked
+        assert!(!trie.set_locked(&h.get_or_create("x")));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ade.id());
+
+        // The support favors ade, but abc descends from the lock
+        assert!(trie.set_locked(&h.get_or_create("ab")));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_with_support(0).unwrap().1, 1);
+        assert_eq!(trie.get_preferred_cached(0).unwrap().id(), abc.id());
+        assert_eq!(
+            trie.get_preferred_incremental(&trie.get_preferred(0).unwrap(), 0).unwrap().id(),
+            abc.id(),
+        );
+
+        // So do the variants that adjust the support first, and the walk from an anchor. Without
+        // abc's support nothing descends from the lock, so the locked ledger is preferred.
+        assert_eq!(trie.get_preferred_excluding(abc.id(), 0).unwrap().id(), h.get_or_create("ab").id());
+        assert_eq!(trie.get_preferred_min_seq(2, 0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_with_cutoffs(&BTreeMap::from([(3, 0)]), 0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_with_extra((3, 1), 0).unwrap().unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_from(h.get_or_create("a").id(), 0).unwrap().id(), abc.id());
+        assert_eq!(trie.get_preferred_from(ade.id(), 0).unwrap().id(), abc.id());
+
+        // A descendant of a ledger in the trie can't be locked either
+        assert!(!trie.set_locked(&h.get_or_create("abcg")));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        // Past the lock, the walk still follows the support
+        insert(&mut trie, &h.get_or_create("abf"), Some(2));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("abf").id());
+
+        // A lock within a span, with ade descending from it
+        insert(&mut trie, &h.get_or_create("abf"), Some(2));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("abf").id());
+        assert!(trie.set_locked(&h.get_or_create("ad")));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ade.id());
+
+        // A lock that every ledger descends from doesn't change the preferred ledger
+        assert!(trie.set_locked(&h.get_or_create("a")));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), h.get_or_create("abf").id());
+
+        // Falls back to the locked ledger once all support diverges from it
+        assert!(trie.set_locked(&abc));
+        remove(&mut trie, &abc, Some(1));
+        let preferred = trie.get_preferred_with_support(0).unwrap();
+        assert_eq!(preferred.0.id(), abc.id());
+        assert_eq!(preferred.1, 0);
+    }
 
-        // One of E advancing to G doesn't change anything
-        //                    A
-        //                   / \
-        //                B(1)  C(1)
-        //               /  |   |
-        //              H   D   F(1)
-        //                  |
-        //                  E(1)
-        //                  |
-        //                  G(1)
-        remove(&mut trie, &h.get_or_create("abde"), None);
-        insert(&mut trie, &h.get_or_create("abdeg"), None);
+    #[test]
+    fn test_set_locked_within_span() {
+        let (mut trie, mut h) = setup();
+        let ab = h.get_or_create("ab");
+        let abcde = h.get_or_create("abcde");
+        let xy = h.get_or_create("xy");
+        insert(&mut trie, &abcde, Some(1));
+        insert(&mut trie, &xy, Some(3));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), xy.id());
+
+        // The walk restarts from the middle of abcde's span, where the support for xy is
+        // uncommitted and could still move to any descendant of ab
+        assert!(trie.set_locked(&ab));
+        let preferred = trie.get_preferred_with_support(0).unwrap();
+        assert_eq!(preferred.0.id(), ab.id());
+        assert_eq!(preferred.1, 1);
+
+        // Once the support descending from ab outweighs it, the walk moves along the span
+        insert(&mut trie, &abcde, Some(3));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abcde.id());
+    }
 
-        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("a").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("a").id());
+    #[test]
+    fn test_clear_removes_lock() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let ade = h.get_or_create("ade");
+        insert(&mut trie, &abc, Some(1));
+        insert(&mut trie, &ade, Some(3));
+        assert!(trie.set_locked(&abc));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        // The locked ledger is gone with the rest of the trie, so the lock doesn't hold it
+        trie.clear();
+        assert!(trie.locked.is_none());
+        assert!(trie.get_preferred(0).is_none());
+        insert(&mut trie, &ade, Some(1));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), ade.id());
+    }
 
-        // C advancing to H does advance the seq 3 preferred ledger
-        //                    A
-        //                   / \
-        //                B(1)  C
-        //               /  |   |
-        //              H(1)D   F(1)
-        //                  |
-        //                  E(1)
-        //                  |
-        //                  G(1)
-        //
-        remove(&mut trie, &h.get_or_create("ac"), None);
-        insert(&mut trie, &h.get_or_create("abh"), None);
-        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("a").id());
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("a").id());
+    #[test]
+    fn test_build_node_directly() {
+        let (mut expected, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        insert(&mut expected, &abc, Some(2));
+
+        // Build the node for abc under the root by hand
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+        let root = trie.root_index();
+        let span = Span::from((*abc).clone()).after(1).unwrap();
+        let idx = trie.insert_node_with(|idx| {
+            let mut node = Node::from_span(span, idx);
+            node.set_tip_support(2);
+            node.set_branch_support(2);
+            node.set_parent(Some(root));
+            node
+        });
+        trie.node_mut(root).unwrap().children_mut().push(idx);
+        trie.node_mut(root).unwrap().set_branch_support(2);
+        trie.node_mut(root).unwrap().set_height(1);
+        trie.seq_support_mut().insert(3, 2);
 
-        // F advancing to E also moves the preferred ledger forward
-        //                    A
-        //                   / \
-        //                B(1)  C
-        //               /  |   |
-        //              H(1)D   F
-        //                  |
-        //                  E(2)
-        //                  |
-        //                  G(1)
-        remove(&mut trie, &h.get_or_create("acf"), None);
-        insert(&mut trie, &h.get_or_create("abde"), None);
-        assert_eq!(trie.get_preferred(1).unwrap().id(), h.get_or_create("abde").id());
-        assert_eq!(trie.get_preferred(2).unwrap().id(), h.get_or_create("abde").id());
-        assert_eq!(trie.get_preferred(3).unwrap().id(), h.get_or_create("abde").id());
-        assert_eq!(trie.get_preferred(4).unwrap().id(), h.get_or_create("ab").id());
-        assert_eq!(trie.get_preferred(5).unwrap().id(), h.get_or_create("ab").id());
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+        let node = trie.node(idx).unwrap();
+        assert_eq!(node.span().start(), 1);
+        assert_eq!(node.span().tip().id(), abc.id());
+        assert_eq!(node.parent(), Some(root));
+        assert!(node.children().is_empty());
+
+        // Corrupting the support breaks the invariants
+        trie.node_mut(idx).unwrap().set_tip_support(1);
+        assert!(!trie.check_invariants());
     }
 
     #[test]
-    fn test_empty() {
+    fn test_insert_extends_last_tip() {
         let (mut trie, mut h) = setup();
-        assert!(trie.empty());
+        let (mut expected, _) = setup();
+
+        // Each step inserts or removes a ledger, and for inserts, whether it extends the last
+        // inserted tip
+        let steps = [
+            ("a", true, false),
+            ("ab", true, true),
+            ("abc", true, true),
+            // A fork from the parent of the last tip
+            ("abd", true, false),
+            ("abde", true, true),
+            // An ancestor of the last tip is inserted the usual way and keeps the last tip
+            ("ab", true, false),
+            ("abdef", true, true),
+            // Removing the last tip erases its node
+            ("abdef", false, false),
+            ("abdeg", true, false),
+            // abc is still a leaf, but no longer the last tip
+            ("abch", true, false),
+            ("abchi", true, true),
+            // The last tip gained a child
+            ("abchij", true, true),
+            ("abchi", true, false),
+            ("abchik", true, false),
+        ];
+
+        for (name, is_insert, extends) in steps {
+            let ledger = h.get_or_create(name);
+            if is_insert {
+                assert_eq!(trie._extends_last_tip(&ledger).is_some(), extends, "{}", name);
+                insert(&mut trie, &ledger, None);
+                // Always take the general path in the expected trie
+                expected.last_tip = None;
+                insert(&mut expected, &ledger, None);
+            } else {
+                remove(&mut trie, &ledger, None);
+                remove(&mut expected, &ledger, None);
+            }
 
-        insert(&mut trie, &h.get_or_create(""), None);
-        assert!(!trie.empty());
-        remove(&mut trie, &h.get_or_create(""), None);
+            assert!(trie.structural_eq(&expected));
+            assert_eq!(trie.seq_support, expected.seq_support);
+            for name in ["a", "ab", "abc", "abd", "abde", "abdef", "abdeg", "abch", "abchi", "abchij", "abchik"] {
+                let ledger = h.get_or_create(name);
+                assert_eq!(trie.tip_support(&ledger), expected.tip_support(&ledger));
+                assert_eq!(trie.branch_support(&ledger), expected.branch_support(&ledger));
+            }
+            assert_eq!(
+                trie.get_preferred(0).map(|tip| tip.id()),
+                expected.get_preferred(0).map(|tip| tip.id()),
+            );
+        }
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let (mut expected, mut h) = setup();
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::with_capacity(100);
         assert!(trie.empty());
 
-        insert(&mut trie, &h.get_or_create("abc"), None);
-        assert!(!trie.empty());
-        remove(&mut trie, &h.get_or_create("abc"), None);
+        // A binary tree of 100 ledgers, each ending in its own character
+        let mut names = vec![String::new()];
+        for i in 1..=100u8 {
+            let name = format!("{}{}", names[i as usize / 2], i as char);
+            names.push(name);
+        }
+        for (i, name) in names.iter().enumerate().skip(1) {
+            let ledger = h.get_or_create(name);
+            trie.insert(&ledger, Some(i as u32 % 3 + 1));
+            insert(&mut expected, &ledger, Some(i as u32 % 3 + 1));
+        }
+
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&expected));
+        for name in &names {
+            let ledger = h.get_or_create(name);
+            assert_eq!(trie.tip_support(&ledger), expected.tip_support(&ledger));
+            assert_eq!(trie.branch_support(&ledger), expected.branch_support(&ledger));
+        }
+        for largest_issued in 0..8 {
+            assert_eq!(
+                trie.get_preferred(largest_issued).map(|tip| tip.id()),
+                expected.get_preferred(largest_issued).map(|tip| tip.id())
+            );
+        }
+    }
+
+    #[test]
+    fn test_version() {
+        let (mut trie, mut h) = setup();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+        let mut version = trie.version();
+
+        // Every change to the support moves the version on
+        let mut changed = |trie: &ArenaLedgerTrie<SimulatedLedger>| {
+            assert!(trie.version() > version);
+            version = trie.version();
+        };
+        insert(&mut trie, &abc, None);
+        changed(&trie);
+        insert(&mut trie, &abd, Some(2));
+        changed(&trie);
+        assert!(remove(&mut trie, &abc, None));
+        changed(&trie);
+        trie.retain(|_, _, _| false);
+        changed(&trie);
+        insert(&mut trie, &abc, None);
+        changed(&trie);
+        trie.clear();
+        changed(&trie);
         assert!(trie.empty());
+
+        // Queries, and changes that fail or change nothing, leave it alone
+        insert(&mut trie, &abc, None);
+        let version = trie.version();
+        trie.get_preferred(0);
+        trie.tip_support(&abc);
+        trie.branch_support(&abc);
+        trie.compact();
+        insert(&mut trie, &abd, Some(0));
+        assert!(!remove(&mut trie, &abd, None));
+        assert!(trie.remove(&abc, Some(2)).is_err());
+        assert_eq!(trie.version(), version);
     }
 
     #[test]
-    fn test_root_related() {
-        // Since the root is a special node that breaks the no-single child
-        // invariant, do some tests that exercise it.
+    fn test_get_preferred_cached() {
+        let (mut trie, mut h) = setup();
+        assert!(trie.get_preferred_cached(0).is_none());
+
+        insert(&mut trie, &h.get_or_create("abc"), Some(2));
+        insert(&mut trie, &h.get_or_create("abd"), None);
+        assert_eq!(trie.get_preferred_cached(0).unwrap().id(), h.get_or_create("abc").id());
+        assert_eq!(trie.preferred_cache.as_ref().map(|(v, issued, _)| (*v, *issued)), Some((trie.version(), 0)));
+
+        // Another perspective is found again rather than reused
+        assert_eq!(trie.get_preferred_cached(4).unwrap().id(), h.get_or_create("").id());
+        assert_eq!(trie.get_preferred_cached(4).unwrap().id(), trie.get_preferred(4).unwrap().id());
+
+        // As is the preferred ledger once the trie changes
+        insert(&mut trie, &h.get_or_create("abd"), Some(2));
+        assert_eq!(trie.get_preferred_cached(0).unwrap().id(), h.get_or_create("abd").id());
+    }
 
+    #[test]
+    fn test_fragmentation() {
         let (mut trie, mut h) = setup();
-        let genesis = h.get_or_create("");
-        assert!(!remove(&mut trie, &genesis, None));
-        assert_eq!(trie.branch_support(&genesis), 0);
-        assert_eq!(trie.tip_support(&genesis), 0);
+        for ledger in ["abc", "abd", "abe", "abf", "abcg", "abch"] {
+            insert(&mut trie, &h.get_or_create(ledger), None);
+        }
+        let before = trie.fragmentation();
 
-        let a = h.get_or_create("a");
-        insert(&mut trie, &a, None);
-        assert_eq!(trie.branch_support(&genesis), 1);
-        assert_eq!(trie.tip_support(&genesis), 0);
+        for ledger in ["abd", "abe", "abf", "abcg", "abch"] {
+            assert!(remove(&mut trie, &h.get_or_create(ledger), None));
+        }
+        assert!(trie.fragmentation() > before);
 
-        let e = h.get_or_create("e");
-        insert(&mut trie, &e, None);
-        assert_eq!(trie.branch_support(&genesis), 2);
-        assert_eq!(trie.tip_support(&genesis), 0);
+        trie.compact();
+        assert_eq!(trie.fragmentation(), 0.0);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&h.get_or_create("abc")), 1);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 1);
 
-        assert!(remove(&mut trie, &e, None));
-        assert_eq!(trie.branch_support(&genesis), 1);
-        assert_eq!(trie.tip_support(&genesis), 0);
+        // The compacted trie is still usable
+        insert(&mut trie, &h.get_or_create("abd"), None);
+        assert_eq!(trie.branch_support(&h.get_or_create("ab")), 2);
     }
 
     #[test]
@@ -1169,11 +5283,540 @@ mod tests {
             if flip.sample(&mut rng) == 0 {
                 trie.insert(&h.get_or_create_string(curr), None);
             } else {
-                trie.remove(&h.get_or_create_string(curr), None);
+                trie.remove(&h.get_or_create_string(curr), None).unwrap();
+            }
+
+            assert!(trie.check_invariants());
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(usize, u32),
+        Remove(usize, u32),
+    }
+
+    /// Generate a tree of up to 11 ledgers as the index of each ledger's parent, or `None` for
+    /// the genesis ledger, along with a sequence of operations on those ledgers.
+    fn ledger_tree_and_ops() -> impl Strategy<Value = (Vec<Option<usize>>, Vec<Op>)> {
+        (1..12usize).prop_flat_map(|n| {
+            let parents: Vec<BoxedStrategy<Option<usize>>> = (0..n)
+                .map(|i| if i == 0 { Just(None).boxed() } else { prop::option::of(0..i).boxed() })
+                .collect();
+            let op = prop_oneof![
+                (0..n, 1..4u32).prop_map(|(l, count)| Op::Insert(l, count)),
+                (0..n, 1..4u32).prop_map(|(l, count)| Op::Remove(l, count)),
+            ];
+            (parents, prop::collection::vec(op, 1..64))
+        })
+    }
+
+    /// Create the ledgers of a tree generated by `ledger_tree_and_ops`.
+    fn ledger_tree(h: &mut LedgerHistoryHelper, parents: &[Option<usize>]) -> Vec<Rc<SimulatedLedger>> {
+        let mut names: Vec<String> = vec![];
+        for (i, parent) in parents.iter().enumerate() {
+            let mut name = parent.map_or_else(String::new, |p| names[p].clone());
+            name.push((b'a' + i as u8) as char);
+            names.push(name);
+        }
+        names.into_iter().map(|name| h.get_or_create_string(name)).collect()
+    }
+
+    // Failing cases are shrunk to a minimal sequence of operations, printed, and saved with
+    // their seed under proptest-regressions/ so that later runs replay them first.
+    proptest! {
+        #[test]
+        fn prop_insert_remove((parents, ops) in ledger_tree_and_ops()) {
+            let (mut trie, mut h) = setup();
+            let ledgers = ledger_tree(&mut h, &parents);
+            let genesis = h.get_or_create("");
+            let mut expected = vec![0u32; ledgers.len()];
+
+            for op in &ops {
+                match *op {
+                    Op::Insert(l, count) => {
+                        trie.insert(&ledgers[l], Some(count));
+                        expected[l] += count;
+                    }
+                    Op::Remove(l, count) => {
+                        let removed = trie.remove(&ledgers[l], Some(count));
+                        if expected[l] == 0 {
+                            prop_assert_eq!(removed, Ok(false));
+                        } else if count > expected[l] {
+                            prop_assert_eq!(
+                                removed,
+                                Err(TrieError::InsufficientSupport { available: expected[l], requested: count })
+                            );
+                        } else {
+                            prop_assert_eq!(removed, Ok(true));
+                            expected[l] -= count;
+                        }
+                    }
+                }
+
+                prop_assert!(trie.check_invariants(), "invariants broken by {:?}", op);
+                for (l, ledger) in ledgers.iter().enumerate() {
+                    prop_assert_eq!(trie.tip_support(ledger), expected[l], "ledger {} after {:?}", l, op);
+                }
+                prop_assert_eq!(trie.branch_support(&genesis), expected.iter().sum::<u32>());
+            }
+        }
+
+        #[test]
+        fn prop_insert_then_remove_restores_support(
+            (parents, ops) in ledger_tree_and_ops(),
+            ledger in any::<prop::sample::Index>(),
+            count in 1..4u32,
+        ) {
+            let (mut trie, mut h) = setup();
+            let mut baseline: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+            let ledgers = ledger_tree(&mut h, &parents);
+            for op in &ops {
+                if let Op::Insert(l, count) = *op {
+                    trie.insert(&ledgers[l], Some(count));
+                    baseline.insert(&ledgers[l], Some(count));
+                }
+            }
+
+            let ledger: &SimulatedLedger = &ledgers[ledger.index(ledgers.len())];
+            trie.insert(ledger, Some(count));
+            prop_assert_eq!(trie.remove(ledger, Some(count)), Ok(true));
+
+            prop_assert!(trie.check_invariants());
+            prop_assert!(trie.structural_eq(&baseline));
+            for l in &ledgers {
+                prop_assert_eq!(trie.tip_support(l), baseline.tip_support(l));
+                prop_assert_eq!(trie.branch_support(l), baseline.branch_support(l));
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_span() {
+        let (mut trie, mut h) = setup();
+        let (mut expected, _) = setup();
+        for (name, count) in [("abc", 1), ("abcde", 2), ("abf", 1), ("abc", 3), ("ab", 1)] {
+            let ledger = h.get_or_create(name);
+            assert!(trie.insert_span(Span::from((*ledger).clone()), Some(count)).unwrap());
+            insert(&mut expected, &ledger, Some(count));
+            assert!(trie.check_invariants());
+            assert_eq!(format!("{:?}", trie), format!("{:?}", expected));
+        }
+
+        // A span that only covers the ledgers past the trie's common ancestry is enough
+        let abfgh = h.get_or_create("abfgh");
+        let span = Span::from((*abfgh).clone()).after(4).unwrap();
+        assert!(trie.insert_span(span, None).unwrap());
+        insert(&mut expected, &abfgh, None);
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&expected));
+        assert_eq!(trie.tip_support(&abfgh), 1);
+
+        // But one that starts after its tip diverges from the trie is missing ledgers
+        let ijk = h.get_or_create("ijk");
+        let span = Span::from((*ijk).clone()).after(2).unwrap();
+        assert!(!trie.insert_span(span, None).unwrap());
+        assert!(!trie.insert_span(Span::from((*ijk).clone()), Some(0)).unwrap());
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&ijk), 0);
+        assert!(trie.structural_eq(&expected));
+    }
+
+    #[test]
+    fn test_insert_by_id() {
+        let (mut trie, mut h) = setup();
+        let (mut expected, _) = setup();
+        let abcd = h.get_or_create("abcd");
+        insert(&mut trie, &abcd, None);
+        insert(&mut expected, &abcd, None);
+
+        // Splits abcd into abc -> d, without materializing abc
+        let abc = h.get_or_create("abc");
+        let tip = trie.insert_by_id(abc.id(), abc.seq(), |s| abc.get_ancestor(s), || unreachable!(), Some(2));
+        assert_eq!(tip.unwrap().unwrap().id(), abc.id());
+        insert(&mut expected, &abc, Some(2));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abc), 2);
+        assert_eq!(trie.branch_support(&abc), 3);
+
+        // Increments an existing node
+        let tip = trie.insert_by_id(abcd.id(), abcd.seq(), |s| abcd.get_ancestor(s), || unreachable!(), None);
+        assert_eq!(tip.unwrap().unwrap().id(), abcd.id());
+        insert(&mut expected, &abcd, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abcd), 2);
+        assert_eq!(trie.branch_support(&abcd), 2);
+
+        assert_eq!(format!("{:?}", trie), format!("{:?}", expected));
+
+        // A ledger past the trie's history is materialized for its span
+        let abce = h.get_or_create("abce");
+        let tip = trie.insert_by_id(abce.id(), abce.seq(), |s| abce.get_ancestor(s), || (*abce).clone(), None);
+        assert_eq!(tip.unwrap().unwrap().id(), abce.id());
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abce), 1);
+        assert_eq!(trie.branch_support(&abc), 5);
+
+        // Inserting by ID checks the trie's limits like any other insert
+        let (trie, _) = setup();
+        let mut trie = trie.with_max_depth(1);
+        insert(&mut trie, &abcd, None);
+        let abf = h.get_or_create("abf");
+        let tip = trie.insert_by_id(abf.id(), abf.seq(), |s| abf.get_ancestor(s), || (*abf).clone(), None);
+        assert!(matches!(tip, Err(TrieError::TooDeep { max_depth: 1 })));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&abf), 0);
+    }
+
+    /// A ledger header that only knows the IDs of its ancestors.
+    #[derive(Clone, Debug)]
+    struct Header {
+        ancestors: Vec<LedgerId>,
+    }
+
+    impl Header {
+        fn child(&self, id: u32) -> Header {
+            let mut ancestors = self.ancestors.clone();
+            ancestors.push(LedgerId::new(id));
+            Header { ancestors }
+        }
+    }
+
+    impl xrpl_consensus_core::SpannableLedger for Header {
+        type IdType = LedgerId;
+
+        fn id(&self) -> LedgerId {
+            *self.ancestors.last().unwrap()
+        }
+
+        fn seq(&self) -> LedgerIndex {
+            self.ancestors.len() as LedgerIndex - 1
+        }
+
+        fn get_ancestor(&self, seq: LedgerIndex) -> LedgerId {
+            self.ancestors[seq as usize]
+        }
+
+        fn make_genesis() -> Self {
+            Header { ancestors: vec![LedgerId::new(0)] }
+        }
+    }
+
+    /// A ledger header that only knows the IDs of its ancestors at every `every`th sequence
+    /// number, and makes up the others.
+    #[derive(Clone, Debug)]
+    struct SparseHeader {
+        header: Header,
+        every: LedgerIndex,
+    }
+
+    impl xrpl_consensus_core::SpannableLedger for SparseHeader {
+        type IdType = LedgerId;
+
+        fn id(&self) -> LedgerId {
+            self.header.id()
+        }
+
+        fn seq(&self) -> LedgerIndex {
+            self.header.seq()
+        }
+
+        fn get_ancestor(&self, seq: LedgerIndex) -> LedgerId {
+            if self.known_ancestor_seq(seq) == seq {
+                self.header.get_ancestor(seq)
+            } else {
+                LedgerId::new(1000 * self.seq() + seq)
+            }
+        }
+
+        fn make_genesis() -> Self {
+            SparseHeader { header: Header::make_genesis(), every: 1 }
+        }
+
+        fn known_ancestor_seq(&self, seq: LedgerIndex) -> LedgerIndex {
+            if seq == self.seq() {
+                seq
+            } else {
+                seq - seq % self.every
             }
+        }
+    }
+
+    #[test]
+    fn test_spannable_ledger() {
+        use xrpl_consensus_core::SpannableLedger;
+
+        let a = Header::make_genesis().child(1);
+        let ab = a.child(2);
+        let abc = ab.child(3);
+        let abd = ab.child(4);
+
+        let mut trie = ArenaLedgerTrie::new();
+        trie.insert(&abc, Some(2));
+        trie.insert(&abd, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&ab), 0);
+        assert_eq!(trie.branch_support(&ab), 3);
+        assert_eq!(trie.tip_support(&abc), 2);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abc.id());
+
+        assert_eq!(trie.remove(&abc, Some(2)), Ok(true));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&a), 1);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), abd.id());
+    }
+
+    #[test]
+    fn test_sparse_ancestry() {
+        use xrpl_consensus_core::SpannableLedger;
+
+        let sparse = |ids: &[u32]| SparseHeader {
+            header: Header { ancestors: ids.iter().map(|id| LedgerId::new(*id)).collect() },
+            every: 4,
+        };
+        let p = sparse(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        // Forks from p at 10, which is first known to differ at 12
+        let q = sparse(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 20, 21, 22, 23, 24, 25]);
+        // Forks from p and q at 6, which is first known to differ at 8
+        let r = sparse(&[0, 1, 2, 3, 4, 5, 30, 31, 32, 33, 34, 35, 36, 37, 38]);
+        assert_eq!(p.mismatch(&q), 12);
+        assert_eq!(q.mismatch(&r), 8);
+        assert_eq!(Span::from(p.clone()).diff(&q), 12);
+
+        let mut trie = ArenaLedgerTrie::new();
+        trie.insert(&p, None);
+        let (loc, diff_seq) = trie._find(&q);
+        assert_eq!(diff_seq, 12);
+        assert_eq!(trie.arena.get(loc).unwrap().span.tip().id(), p.id());
+
+        trie.insert(&q, None);
+        assert!(trie.check_invariants());
+        let (loc, diff_seq) = trie._find(&r);
+        assert_eq!(diff_seq, 8);
+        let loc = trie.arena.get(loc).unwrap();
+        assert_eq!((loc.span.start(), loc.span.end()), (1, 12));
+        assert_eq!(loc.branch_support, 2);
+
+        trie.insert(&r, None);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.fork_count(), 2);
+        for ledger in [&p, &q, &r] {
+            assert_eq!(trie.tip_support(ledger), 1);
+        }
+        assert_eq!(trie.remove(&q, None), Ok(true));
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&p), 1);
+        assert_eq!(trie.branch_support(&q), 0);
+    }
+
+    #[test]
+    fn test_sparse_preferred() {
+        use xrpl_consensus_core::SpannableLedger;
+
+        let sparse = |ids: &[u32]| SparseHeader {
+            header: Header { ancestors: ids.iter().map(|id| LedgerId::new(*id)).collect() },
+            every: 4,
+        };
+        // All three are first known to differ at 4
+        let p = sparse(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        let q = sparse(&[0, 1, 50, 51, 52, 53, 54, 55, 56, 57]);
+        let r = sparse(&[0, 1, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69]);
+
+        let mut trie = ArenaLedgerTrie::new();
+        trie.insert(&p, Some(4));
+        trie.insert(&q, Some(3));
+        trie.insert(&r, None);
+        assert!(trie.check_invariants());
+
+        // The support for q and r is uncommitted past their tips, which stops the walk along
+        // p's span at 11, whose ID p doesn't know, so it steps back to 8
+        let (preferred, support) = trie.get_preferred_with_support(0).unwrap();
+        assert_eq!((preferred.seq(), preferred.id()), (8, LedgerId::new(8)));
+        assert_eq!(support, 4);
+        assert_eq!(trie.preferred_seq(0), Some(8));
+
+        // Stubs only hold IDs the ledger knows
+        let stub = preferred.to_ledger_stub_with_ancestors(2);
+        assert_eq!((stub.seq, stub.id), (8, LedgerId::new(8)));
+        assert!(stub.ancestors.is_empty());
+        let tip = trie.get_preferred_from(p.id(), 0).unwrap();
+        assert_eq!(tip.to_ledger_stub_with_ancestors(3).ancestors, vec![LedgerId::new(12)]);
+    }
+
+    #[test]
+    fn test_strict_ancestry() {
+        use xrpl_consensus_core::SpannableLedger;
+
+        let a = Header::make_genesis().child(1);
+        let ab = a.child(2);
+        let abc = ab.child(3);
+        // Claims abc as its parent, but a different ledger before it
+        let malformed = Header { ancestors: vec![LedgerId::new(0), LedgerId::new(1), LedgerId::new(9), LedgerId::new(3)] }
+            .child(5);
+        assert_eq!(malformed.mismatch(&abc), 2);
+
+        // Without strict ancestry, the malformed ledger forks from a
+        let mut trie = ArenaLedgerTrie::new();
+        trie.insert(&abc, None);
+        assert!(trie.checked_insert(&malformed, None).is_ok());
+        assert!(trie.check_invariants());
+        assert_eq!(trie.tip_support(&malformed), 1);
+        assert_eq!(trie.branch_support(&a), 2);
+        assert_eq!(trie.branch_support(&ab), 1);
+        assert_eq!(trie.fork_count(), 1);
+
+        // With strict ancestry, it is rejected
+        let mut trie = ArenaLedgerTrie::new().with_strict_ancestry(true);
+        trie.insert(&abc, None);
+        let version = trie.version();
+        assert_eq!(trie.checked_insert(&malformed, None), Err(TrieError::ConflictingAncestry { seq: 3 }));
+        assert!(matches!(trie.insert_get(&malformed, None), Err(TrieError::ConflictingAncestry { seq: 3 })));
+        assert_eq!(trie.version(), version);
+        assert!(trie.check_invariants());
+        assert_eq!(trie.branch_support(&malformed), 0);
+        assert_eq!(trie.branch_support(&ab), 1);
+
+        // Every other insert is checked too
+        let span = Span::from(malformed.clone());
+        assert_eq!(trie.insert_span(span, None), Err(TrieError::ConflictingAncestry { seq: 3 }));
+        assert_eq!(trie.insert_partial(&malformed, None), Err(TrieError::ConflictingAncestry { seq: 3 }));
+        let handle = trie.handle(a.get_ancestor(0)).unwrap();
+        assert_eq!(trie.insert_under(handle, &malformed, None), Err(TrieError::ConflictingAncestry { seq: 3 }));
+        let inserted = trie.insert_by_id(
+            malformed.id(),
+            malformed.seq(),
+            |s| malformed.get_ancestor(s),
+            || malformed.clone(),
+            None,
+        );
+        assert!(matches!(inserted, Err(TrieError::ConflictingAncestry { seq: 3 })));
+        assert_eq!(trie.version(), version);
+        assert!(trie.check_invariants());
+
+        // But a well-formed fork still inserts, and clearing keeps the mode
+        let abd = ab.child(4);
+        assert!(trie.checked_insert(&abd, None).is_ok());
+        assert_eq!(trie.branch_support(&ab), 2);
+        trie.clear();
+        trie.insert(&abc, None);
+        assert!(trie.checked_insert(&malformed, None).is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        const CHAIN: &str = "abcdefghijk";
+        let mut h = LedgerHistoryHelper::new();
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new().with_max_depth(10);
+        let mut unlimited: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+
+        // Forking after each of the first nine ledgers of a chain of ten splits it into ten
+        // levels of nodes
+        let mut ledgers = vec![h.get_or_create(&CHAIN[..10])];
+        for i in 1..10 {
+            ledgers.push(h.get_or_create(&format!("{}{}", &CHAIN[..i], CHAIN[i..=i].to_uppercase())));
+        }
+        for ledger in &ledgers {
+            assert_eq!(trie.checked_insert(ledger, None), Ok(()));
+            unlimited.insert(ledger, None);
+        }
+        assert!(trie.check_invariants());
+        assert!(trie.structural_eq(&unlimited));
+        assert_eq!(trie.path_to(ledgers[0].id()).unwrap().len(), 11);
+
+        // Extending the chain would add an eleventh level
+        let deepest = h.get_or_create(CHAIN);
+        let version = trie.version();
+        assert_eq!(trie.checked_insert(&deepest, None), Err(TrieError::TooDeep { max_depth: 10 }));
+        assert!(matches!(trie.insert_get(&deepest, None), Err(TrieError::TooDeep { max_depth: 10 })));
+        assert_eq!(trie.insert_partial(&deepest, None), Err(TrieError::TooDeep { max_depth: 10 }));
+        let span = Span::from((*deepest).clone());
+        assert_eq!(trie.insert_span(span, None), Err(TrieError::TooDeep { max_depth: 10 }));
+        assert_eq!(trie.version(), version);
+        assert_eq!(trie.branch_support(&deepest), 0);
+        unlimited.insert(&deepest, None);
+        assert_eq!(unlimited.branch_support(&deepest), 1);
+
+        // More support at an existing level is still fine, and clearing keeps the limit
+        assert_eq!(trie.checked_insert(&ledgers[0], None), Ok(()));
+        trie.clear();
+        assert_eq!(trie.checked_insert(&deepest, None), Ok(()));
+        assert_eq!(trie.path_to(deepest.id()).unwrap().len(), 2);
+
+        // A fork that splits a node moves its whole subtree down a level, even though the new
+        // leaf itself is shallow
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new().with_max_depth(2);
+        assert_eq!(trie.checked_insert(&h.get_or_create("xyz"), None), Ok(()));
+        assert_eq!(trie.checked_insert(&h.get_or_create("xyw"), None), Ok(()));
+        assert_eq!(trie.checked_insert(&h.get_or_create("xV"), None), Err(TrieError::TooDeep { max_depth: 2 }));
+        assert_eq!(trie.checked_insert(&h.get_or_create("U"), None), Ok(()));
+        assert!(trie.check_invariants());
 
+        // Removing the fork that split the node makes room for a fork of the chain again
+        assert!(remove(&mut trie, &h.get_or_create("xyw"), None));
+        assert_eq!(trie.checked_insert(&h.get_or_create("xV"), None), Ok(()));
+        assert!(trie.check_invariants());
+    }
+
+    #[test]
+    #[should_panic(expected = "ConflictingAncestry")]
+    fn test_insert_conflicting_ancestry_panics() {
+        let abc = <Header as xrpl_consensus_core::SpannableLedger>::make_genesis().child(1).child(2).child(3);
+        let malformed = Header { ancestors: vec![LedgerId::new(0), LedgerId::new(1), LedgerId::new(9), LedgerId::new(3)] }
+            .child(5);
+        let mut trie = ArenaLedgerTrie::new().with_strict_ancestry(true);
+        trie.insert(&abc, None);
+        trie.insert(&malformed, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "TooDeep")]
+    fn test_insert_too_deep_panics() {
+        let mut h = LedgerHistoryHelper::new();
+        let mut trie: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new().with_max_depth(1);
+        trie.insert(&h.get_or_create("abc"), None);
+        trie.insert(&h.get_or_create("abd"), None);
+    }
+
+    #[test]
+    fn test_insert_under() {
+        let (mut trie, mut h) = setup();
+        let (mut expected, _) = setup();
+        let names = ["ab", "abc", "abcde", "abf"];
+        for name in names {
+            insert(&mut trie, &h.get_or_create(name), None);
+            insert(&mut expected, &h.get_or_create(name), None);
+        }
+
+        // Under the node of an ancestor, whether the ledger extends it, forks from it, lands
+        // in one of its children's spans or is the ancestor itself
+        let ab = trie.handle(h.get_or_create("ab").id()).unwrap();
+        for name in ["abcdg", "abh", "abcd", "ab"] {
+            let ledger = h.get_or_create(name);
+            assert_eq!(trie.insert_under(ab, &ledger, Some(2)), Ok(()), "{}", name);
+            insert(&mut expected, &ledger, Some(2));
             assert!(trie.check_invariants());
+            assert!(trie.structural_eq(&expected), "{}", name);
+        }
+        for name in ["ab", "abc", "abcd", "abcde", "abcdg", "abf", "abh"] {
+            let ledger = h.get_or_create(name);
+            assert_eq!(trie.tip_support(&ledger), expected.tip_support(&ledger));
+            assert_eq!(trie.branch_support(&ledger), expected.branch_support(&ledger));
         }
+
+        // A node that isn't an ancestor of the ledger is rejected
+        let version = trie.version();
+        let abf = trie.handle(h.get_or_create("abf").id()).unwrap();
+        let abcde = h.get_or_create("abcde");
+        assert_eq!(trie.insert_under(abf, &abcde, None), Err(TrieError::InvalidParent));
+        assert_eq!(trie.insert_under(abf, &h.get_or_create("a"), None), Err(TrieError::InvalidParent));
+
+        // As is a handle to a node that has since been removed
+        let abh = trie.handle(h.get_or_create("abh").id()).unwrap();
+        remove(&mut trie, &h.get_or_create("abh"), Some(2));
+        let version_after_remove = trie.version();
+        assert!(version_after_remove > version);
+        assert_eq!(trie.insert_under(abh, &h.get_or_create("abhi"), None), Err(TrieError::InvalidParent));
+        assert_eq!(trie.version(), version_after_remove);
     }
 
     fn setup() -> (ArenaLedgerTrie<SimulatedLedger>, LedgerHistoryHelper) {
@@ -1185,12 +5828,12 @@ mod tests {
     fn insert(trie: &mut ArenaLedgerTrie<SimulatedLedger>, ledger: &SimulatedLedger, count: Option<u32>) {
         println!("Inserting {}", ledger.to_string());
         trie.insert(&ledger, count);
-        println!("After inserting: {}", serde_json::to_string_pretty(&trie).unwrap());
+        println!("After inserting: {:?}", trie);
         assert!(trie.check_invariants());
     }
 
     fn remove(trie: &mut ArenaLedgerTrie<SimulatedLedger>, ledger: &SimulatedLedger, count: Option<u32>) -> bool {
-        let removed = trie.remove(&ledger, count);
+        let removed = trie.remove(&ledger, count).unwrap();
         assert!(trie.check_invariants());
         removed
     }
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use xrpl_consensus_core::SpannableLedger;
+
+use crate::arena_ledger_trie::ArenaLedgerTrie;
+use crate::ledger_trie::{LedgerTrie, Support, TrieError};
+
+/// An [`ArenaLedgerTrie`] whose support decays over time, so older validations count for less
+/// than newer ones. Create one with [`ArenaLedgerTrie::with_decay`].
+///
+/// Decay is kept out of [`ArenaLedgerTrie`] itself, which has no clock and builds without
+/// `std`, so [`DecayingLedgerTrie::tick`] and the insert timestamps live on this wrapper. There
+/// is no default half-life, so the wrapper is not a [`LedgerTrie`], which must be `Default`.
+/// Support is changed through the wrapper and read through [`DecayingLedgerTrie::trie`].
+///
+/// Support inserted with [`LedgerTrie::insert`] is stamped with the time of the last
+/// [`DecayingLedgerTrie::tick`], or the creation of the trie, and halves every half-life after
+/// that. The trie can only be changed through this wrapper, so all of its support decays.
+///
+/// Support for a ledger inserted between the same two ticks is logged together, so the log of
+/// a ledger holds one entry for each tick it was inserted at until that support decays to
+/// nothing, which takes a few half-lives.
+pub struct DecayingLedgerTrie<T: SpannableLedger, S: Support = u32> {
+    trie: ArenaLedgerTrie<T, S>,
+    half_life: Duration,
+    /// The time of the last `tick`, which stamps newly inserted support.
+    now: Instant,
+    /// The support inserted for each ledger with support in the trie.
+    contributions: HashMap<T::IdType, Contributions<T, S>>,
+}
+
+/// The support inserted for a ledger, oldest first.
+struct Contributions<T, S> {
+    ledger: T,
+    log: VecDeque<Contribution<S>>,
+}
+
+/// Support inserted for a ledger at one time.
+struct Contribution<S> {
+    /// The support as inserted, less any support removed since.
+    count: S,
+    /// The support still in the trie after decaying.
+    current: S,
+    inserted: Instant,
+}
+
+impl<T: SpannableLedger, S: Support> ArenaLedgerTrie<T, S> {
+    /// Create an empty trie whose support halves every `half_life`, advanced with
+    /// [`DecayingLedgerTrie::tick`].
+    ///
+    /// # Panics
+    /// If `half_life` is zero.
+    pub fn with_decay(half_life: Duration) -> DecayingLedgerTrie<T, S> {
+        assert!(!half_life.is_zero(), "half_life must be positive");
+        DecayingLedgerTrie {
+            trie: ArenaLedgerTrie::default(),
+            half_life,
+            now: Instant::now(),
+            contributions: HashMap::new(),
+        }
+    }
+}
+
+impl<T: SpannableLedger, S: Support> DecayingLedgerTrie<T, S> {
+    /// Create an empty trie whose support halves every `half_life`, see
+    /// [`ArenaLedgerTrie::with_decay`].
+    pub fn new(half_life: Duration) -> Self {
+        ArenaLedgerTrie::with_decay(half_life)
+    }
+
+    /// Return the decaying trie.
+    pub fn trie(&self) -> &ArenaLedgerTrie<T, S> {
+        &self.trie
+    }
+
+    /// Return the time it takes support to halve.
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    /// Advance the clock to `now`, decaying the support of every contribution by its age.
+    /// Support is rounded to the nearest whole count, and ledgers whose support rounds to zero
+    /// are removed from the trie.
+    pub fn tick(&mut self, now: Instant) {
+        let half_life = self.half_life.as_secs_f64();
+        let trie = &mut self.trie;
+        self.contributions.retain(|_, contributions| {
+            let mut decayed = 0u64;
+            contributions.log.retain_mut(|c| {
+                let age = now.saturating_duration_since(c.inserted).as_secs_f64();
+                let count: u64 = c.count.into();
+                let target = (count as f64 * 0.5f64.powf(age / half_life)).round() as u64;
+                let current: u64 = c.current.into();
+                if target < current {
+                    // Less than `current`, so it fits in `S`
+                    c.current = S::try_from(target).ok().unwrap();
+                    decayed += current - target;
+                }
+                c.current != S::zero()
+            });
+
+            // The decayed support is at most the ledger's tip support, which is the sum of the
+            // current support of its contributions
+            if decayed != 0 {
+                let decayed = S::try_from(decayed).ok().unwrap();
+                trie.remove(&contributions.ledger, Some(decayed)).unwrap();
+            }
+            !contributions.log.is_empty()
+        });
+        self.now = now;
+    }
+
+    /// Insert support for `ledger` stamped with the time of the last tick, see
+    /// [`LedgerTrie::insert`].
+    pub fn insert(&mut self, ledger: &T, count: Option<S>) {
+        self.trie.insert(ledger, count);
+
+        let count = count.unwrap_or(S::one());
+        let now = self.now;
+        let log = &mut self.contributions.entry(ledger.id())
+            .or_insert_with(|| Contributions { ledger: ledger.clone(), log: VecDeque::new() })
+            .log;
+        match log.back_mut() {
+            Some(last) if last.inserted == now => {
+                last.count += count;
+                last.current += count;
+            }
+            _ => log.push_back(Contribution { count, current: count, inserted: now }),
+        }
+    }
+
+    /// Remove support for `ledger`, taken from its oldest support first, see
+    /// [`LedgerTrie::remove`].
+    pub fn remove(&mut self, ledger: &T, count: Option<S>) -> Result<bool, TrieError<S>> {
+        let removed = self.trie.remove(ledger, count)?;
+        if removed {
+            self._take(ledger.id(), count.unwrap_or(S::one()));
+        }
+        Ok(removed)
+    }
+
+    /// Take support removed from the trie from the oldest contributions for the ledger first.
+    fn _take(&mut self, id: T::IdType, mut remaining: S) {
+        let contributions = match self.contributions.get_mut(&id) {
+            Some(contributions) => contributions,
+            None => return,
+        };
+
+        while remaining != S::zero() {
+            let oldest = match contributions.log.front_mut() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            let taken = core::cmp::min(remaining, oldest.current);
+            remaining -= taken;
+            oldest.current -= taken;
+            oldest.count -= core::cmp::min(taken, oldest.count);
+            if oldest.current == S::zero() {
+                contributions.log.pop_front();
+            }
+        }
+
+        if contributions.log.is_empty() {
+            self.contributions.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use xrpl_consensus_core::Ledger;
+
+    use crate::arena_ledger_trie::ArenaLedgerTrie;
+    use crate::decaying_ledger_trie::DecayingLedgerTrie;
+    use crate::ledger_trie::LedgerTrie;
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, SimulatedLedger};
+
+    #[test]
+    fn test_decay() {
+        let half_life = Duration::from_secs(60);
+        let mut trie: DecayingLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::with_decay(half_life);
+        let mut h = LedgerHistoryHelper::new();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+
+        let start = Instant::now();
+        trie.tick(start);
+        trie.insert(&abc, Some(8));
+
+        trie.tick(start + half_life);
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().tip_support(&abc), 4);
+        assert_eq!(trie.trie().branch_support(&ab), 4);
+
+        trie.tick(start + half_life * 3);
+        assert_eq!(trie.trie().tip_support(&abc), 1);
+
+        // Fresh support outweighs the stale branch, which would have been preferred without decay
+        trie.insert(&abd, Some(4));
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().get_preferred(0).unwrap().id(), abd.id());
+
+        // Removed support is taken from the contributions, so it does not decay again
+        assert!(trie.remove(&abd, None).unwrap());
+        trie.tick(start + half_life * 4);
+        assert_eq!(trie.trie().tip_support(&abd), 2);
+        assert_eq!(trie.trie().tip_support(&abc), 1);
+
+        // Stale branches that decay to nothing are pruned, along with their contributions
+        trie.tick(start + half_life * 5);
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().branch_support(&abc), 0);
+        assert_eq!(trie.trie().tip_support(&abd), 1);
+        assert_eq!(trie.trie().get_preferred(0).unwrap().id(), abd.id());
+        assert_eq!(trie.contributions.len(), 1);
+    }
+
+    #[test]
+    fn test_decay_log() {
+        let half_life = Duration::from_secs(60);
+        let mut trie: DecayingLedgerTrie<SimulatedLedger> = DecayingLedgerTrie::new(half_life);
+        let mut h = LedgerHistoryHelper::new();
+        let abc = h.get_or_create("abc");
+
+        // Support inserted between the same ticks shares an entry
+        let start = Instant::now();
+        trie.tick(start);
+        trie.insert(&abc, Some(2));
+        trie.insert(&abc, Some(2));
+        trie.tick(start + half_life);
+        trie.insert(&abc, Some(4));
+        assert_eq!(trie.contributions[&abc.id()].log.len(), 2);
+        assert_eq!(trie.trie().tip_support(&abc), 6);
+
+        // Removing support takes the oldest first, until the log is empty
+        assert!(trie.remove(&abc, Some(3)).unwrap());
+        assert_eq!(trie.contributions[&abc.id()].log.len(), 1);
+        assert!(trie.remove(&abc, Some(3)).unwrap());
+        assert!(trie.contributions.is_empty());
+        assert!(trie.trie().empty());
+
+        // Removing support the trie doesn't have leaves the log alone
+        trie.insert(&abc, Some(1));
+        assert!(trie.remove(&abc, Some(2)).is_err());
+        assert_eq!(trie.contributions[&abc.id()].log.len(), 1);
+        trie.tick(start + half_life * 10);
+        assert!(trie.contributions.is_empty());
+        assert!(trie.trie().empty());
+    }
+}
@@ -0,0 +1,397 @@
+use core::cmp::Ordering;
+use core::hash::BuildHasherDefault;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use rustc_hash::FxHasher;
+
+use xrpl_consensus_core::{LedgerIndex, SpannableLedger};
+
+use crate::ledger_trie::{LedgerTrie, LedgerTrieExt, Support, TrieError};
+use crate::span::SpanTip;
+
+/// A `LedgerTrie` that keeps every ledger in the ancestry of a supported ledger in a `HashMap`,
+/// each pointing to its parent.
+///
+/// Unlike [`ArenaLedgerTrie`](crate::arena_ledger_trie::ArenaLedgerTrie), ledgers with a single
+/// child are not compressed into spans, so each insert and remove takes time in proportion to
+/// the ledger's sequence number. It is meant as a simple reference to check other tries
+/// against rather than for use in consensus.
+pub struct HashMapLedgerTrie<T: SpannableLedger, S: Support = u32> {
+    /// The ID of the genesis ledger, which is always in the trie.
+    root: T::IdType,
+    nodes: HashMap<T::IdType, Node<T, S>, BuildHasherDefault<FxHasher>>,
+    seq_support: BTreeMap<LedgerIndex, S>,
+}
+
+/// A single ledger in a [`HashMapLedgerTrie`].
+struct Node<T: SpannableLedger, S: Support> {
+    id: T::IdType,
+    seq: LedgerIndex,
+    /// A ledger descended from this one, used to look up this ledger's ancestry.
+    ledger: Arc<T>,
+    parent: Option<T::IdType>,
+    children: Vec<T::IdType>,
+    tip_support: S,
+    branch_support: S,
+}
+
+impl<T: SpannableLedger, S: Support> Default for HashMapLedgerTrie<T, S> {
+    fn default() -> Self {
+        let genesis = T::make_genesis();
+        let root = genesis.id();
+        let mut nodes = HashMap::with_hasher(Default::default());
+        nodes.insert(root, Node {
+            id: root,
+            seq: 0,
+            ledger: Arc::new(genesis),
+            parent: None,
+            children: vec![],
+            tip_support: S::zero(),
+            branch_support: S::zero(),
+        });
+
+        HashMapLedgerTrie {
+            root,
+            nodes,
+            seq_support: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: SpannableLedger> HashMapLedgerTrie<T> {
+    /// Create an empty trie that counts support with `u32`.
+    pub fn new() -> Self {
+        HashMapLedgerTrie::default()
+    }
+}
+
+impl<T: SpannableLedger, S: Support> HashMapLedgerTrie<T, S> {
+    /// Walk the trie for the preferred ledger, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Returns
+    /// The node of the preferred ledger and the margin of its best child over the second best,
+    /// or zero if it has fewer than two children, or `None` if no preferred ledger exists.
+    fn _get_preferred(&self, largest_issued: LedgerIndex) -> Option<(&Node<T, S>, S)> {
+        let mut curr = &self.nodes[&self.root];
+        if curr.branch_support == S::zero() {
+            return None;
+        }
+
+        let mut uncommitted = S::zero();
+        let mut seq_support = self.seq_support.iter().peekable();
+        loop {
+            // Validators that have not validated past this ledger, or past the largest
+            // sequence we issued, may yet support any of its children
+            let next_seq = core::cmp::max(curr.seq + 1, largest_issued);
+            while let Some((_, support)) = seq_support.next_if(|(seq, _)| **seq < next_seq) {
+                uncommitted += *support;
+            }
+
+            // Order the children by branch support, breaking ties with the larger ID
+            let mut children: Vec<&Node<T, S>> = curr.children.iter().map(|id| &self.nodes[id]).collect();
+            children.sort_by(|a, b| match b.branch_support.cmp(&a.branch_support) {
+                Ordering::Equal => b.id.cmp(&a.id),
+                cmp => cmp,
+            });
+            let best = match children.first() {
+                Some(best) => *best,
+                None => break,
+            };
+            let margin = match children.get(1) {
+                None => best.branch_support,
+                Some(second) if best.id > second.id => best.branch_support - second.branch_support + S::one(),
+                Some(second) => best.branch_support - second.branch_support,
+            };
+
+            if margin > uncommitted || uncommitted == S::zero() {
+                curr = best;
+            } else if children.len() > 1 {
+                return Some((curr, margin));
+            } else {
+                break;
+            }
+        }
+
+        Some((curr, S::zero()))
+
+    }
+}
+
+impl<T: SpannableLedger, S: Support> LedgerTrie<T> for HashMapLedgerTrie<T, S> {
+    type Support = S;
+
+    fn insert(&mut self, ledger: &T, count: Option<S>) {
+        let count = count.unwrap_or(S::one());
+        if count == S::zero() {
+            return;
+        }
+        let total = self.nodes[&self.root].branch_support;
+        if total.checked_add(count).is_none() {
+            panic!("{:?}", TrieError::SupportOverflow { total, requested: count });
+        }
+
+        // Every ledger in the trie has its ancestors in the trie, so once one of the ledger's
+        // ancestors is missing, so are the rest
+        let mut parent = self.root;
+        let mut seq = 1;
+        while seq <= ledger.seq() && self.nodes.contains_key(&ledger.get_ancestor(seq)) {
+            parent = ledger.get_ancestor(seq);
+            seq += 1;
+        }
+        let shared = Arc::new(ledger.clone());
+        for seq in seq..=ledger.seq() {
+            let id = ledger.get_ancestor(seq);
+            self.nodes.insert(id, Node {
+                id,
+                seq,
+                ledger: shared.clone(),
+                parent: Some(parent),
+                children: vec![],
+                tip_support: S::zero(),
+                branch_support: S::zero(),
+            });
+            self.nodes.get_mut(&parent).unwrap().children.push(id);
+            parent = id;
+        }
+
+        self.nodes.get_mut(&ledger.id()).unwrap().tip_support += count;
+        *self.seq_support.entry(ledger.seq()).or_insert(S::zero()) += count;
+        let mut curr = Some(ledger.id());
+        while let Some(id) = curr {
+            let node = self.nodes.get_mut(&id).unwrap();
+            node.branch_support += count;
+            curr = node.parent;
+        }
+    }
+
+    fn remove(&mut self, ledger: &T, count: Option<S>) -> Result<bool, TrieError<S>> {
+        let count = count.unwrap_or(S::one());
+        let available = match self.nodes.get(&ledger.id()) {
+            Some(node) if node.seq == ledger.seq() && node.tip_support != S::zero() => node.tip_support,
+            _ => return Ok(false),
+        };
+        if count > available {
+            return Err(TrieError::InsufficientSupport { available, requested: count });
+        }
+
+        self.nodes.get_mut(&ledger.id()).unwrap().tip_support -= count;
+        let seq_support = self.seq_support.get_mut(&ledger.seq()).unwrap();
+        *seq_support -= count;
+        if *seq_support == S::zero() {
+            self.seq_support.remove(&ledger.seq());
+        }
+        let mut curr = Some(ledger.id());
+        while let Some(id) = curr {
+            let node = self.nodes.get_mut(&id).unwrap();
+            node.branch_support -= count;
+            curr = node.parent;
+        }
+
+        // Drop the ledgers left without any support in their branch
+        let mut id = ledger.id();
+        while id != self.root && self.nodes[&id].branch_support == S::zero() {
+            let parent = self.nodes.remove(&id).unwrap().parent.unwrap();
+            self.nodes.get_mut(&parent).unwrap().children.retain(|child| *child != id);
+            id = parent;
+        }
+        Ok(true)
+    }
+
+    fn get_preferred(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self.get_preferred_with_support(largest_issued).map(|(tip, _)| tip)
+    }
+
+    fn get_preferred_with_support(&self, largest_issued: LedgerIndex) -> Option<(SpanTip<T>, S)> {
+        let (curr, _) = self._get_preferred(largest_issued)?;
+        Some((SpanTip::new(curr.seq, curr.id, curr.ledger.clone()), curr.branch_support))
+    }
+
+    fn empty(&self) -> bool {
+        self.nodes[&self.root].branch_support == S::zero()
+    }
+
+    fn tip_support(&self, ledger: &T) -> S {
+        match self.nodes.get(&ledger.id()) {
+            Some(node) if node.seq == ledger.seq() => node.tip_support,
+            _ => S::zero(),
+        }
+    }
+
+    fn branch_support(&self, ledger: &T) -> S {
+        match self.nodes.get(&ledger.id()) {
+            Some(node) if node.seq == ledger.seq() => node.branch_support,
+            _ => S::zero(),
+        }
+    }
+}
+
+impl<T: SpannableLedger, S: Support> LedgerTrieExt<T> for HashMapLedgerTrie<T, S> {
+    fn preferred_fork_margin(&self, largest_issued: LedgerIndex) -> Option<S> {
+        self._get_preferred(largest_issued).map(|(_, margin)| margin)
+    }
+
+    fn abandoned_branches(&self, largest_issued: LedgerIndex) -> Vec<SpanTip<T>> {
+        let mut leader = match self._get_preferred(largest_issued) {
+            Some((preferred, _)) => preferred,
+            None => return vec![],
+        };
+
+        let mut abandoned = vec![];
+        while let Some(parent) = leader.parent.map(|id| &self.nodes[&id]) {
+            for sibling in parent.children.iter().map(|id| &self.nodes[id]) {
+                let uncommitted = self.seq_support
+                    .range(..core::cmp::max(sibling.seq, largest_issued))
+                    .fold(S::zero(), |total, (_, support)| total + *support);
+                if sibling.id != leader.id && sibling.branch_support + uncommitted < leader.branch_support {
+                    abandoned.push(SpanTip::new(sibling.seq, sibling.id, sibling.ledger.clone()));
+                }
+            }
+            leader = parent;
+        }
+        abandoned
+    }
+
+    fn total_support(&self) -> S {
+        self.nodes[&self.root].branch_support
+    }
+
+    fn tip_support_of(&self, tip: &SpanTip<T>) -> S {
+        match self.nodes.get(&tip.id()) {
+            Some(node) if node.seq == tip.seq() => node.tip_support,
+            _ => S::zero(),
+        }
+    }
+
+    fn branch_support_of(&self, tip: &SpanTip<T>) -> S {
+        match self.nodes.get(&tip.id()) {
+            Some(node) if node.seq == tip.seq() => node.branch_support,
+            _ => S::zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::{Distribution, Uniform};
+    use rand::SeedableRng;
+    use xrpl_consensus_core::LedgerIndex;
+
+    use crate::arena_ledger_trie::ArenaLedgerTrie;
+    use crate::hash_map_ledger_trie::HashMapLedgerTrie;
+    use crate::ledger_trie::{LedgerTrie, LedgerTrieExt};
+    use crate::span::SpanTip;
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, LedgerId, SimulatedLedger};
+
+    /// Return the sequence numbers and IDs of `tips` in order.
+    fn sorted_tips(tips: Vec<SpanTip<SimulatedLedger>>) -> Vec<(LedgerIndex, LedgerId)> {
+        let mut tips: Vec<_> = tips.iter().map(|tip| (tip.seq(), tip.id())).collect();
+        tips.sort();
+        tips
+    }
+
+    /// Check that two tries agree on the support of each ledger and on the preferred ledger
+    /// from the perspective of every sequence number up to `max_seq`.
+    fn assert_same(
+        arena: &ArenaLedgerTrie<SimulatedLedger>,
+        reference: &HashMapLedgerTrie<SimulatedLedger>,
+        ledgers: &[&SimulatedLedger],
+        max_seq: u32,
+    ) {
+        for ledger in ledgers {
+            assert_eq!(arena.tip_support(ledger), reference.tip_support(ledger), "tip support of {}", ledger);
+            assert_eq!(arena.branch_support(ledger), reference.branch_support(ledger), "branch support of {}", ledger);
+        }
+        for largest_issued in 0..=max_seq {
+            let expected = reference.get_preferred_with_support(largest_issued).map(|(tip, s)| (tip.id(), s));
+            let actual = arena.get_preferred_with_support(largest_issued).map(|(tip, s)| (tip.id(), s));
+            assert_eq!(actual, expected, "preferred ledger for largest issued {}", largest_issued);
+            assert_eq!(arena.preferred_fork_margin(largest_issued), reference.preferred_fork_margin(largest_issued));
+            assert_eq!(arena.preferred_seq(largest_issued), reference.preferred_seq(largest_issued));
+            assert_eq!(
+                sorted_tips(arena.abandoned_branches(largest_issued)),
+                sorted_tips(reference.abandoned_branches(largest_issued)),
+                "abandoned branches for largest issued {}", largest_issued,
+            );
+            if let Some(tip) = reference.get_preferred(largest_issued) {
+                assert_eq!(arena.tip_support_of(&tip), reference.tip_support_of(&tip));
+                assert_eq!(arena.branch_support_of(&tip), reference.branch_support_of(&tip));
+            }
+        }
+        assert_eq!(arena.empty(), reference.empty());
+        assert_eq!(arena.total_support(), reference.total_support());
+    }
+
+    #[test]
+    fn test_same_as_arena() {
+        let mut h = LedgerHistoryHelper::new();
+        let names = ["", "a", "ab", "abc", "abcd", "abce", "abf", "abfg", "ah", "ahi", "j"];
+        let ledgers: Vec<_> = names.iter().map(|name| h.get_or_create(name)).collect();
+        let ledgers: Vec<&SimulatedLedger> = ledgers.iter().map(|l| &**l).collect();
+
+        let mut arena: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+        let mut reference: HashMapLedgerTrie<SimulatedLedger> = HashMapLedgerTrie::new();
+        assert_same(&arena, &reference, &ledgers, 5);
+
+        for (name, count) in [("abcd", 2), ("abce", 2), ("abfg", 3), ("ahi", 1), ("ab", 1), ("j", 2)] {
+            let ledger = h.get_or_create(name);
+            arena.insert(&ledger, Some(count));
+            reference.insert(&ledger, Some(count));
+            assert_same(&arena, &reference, &ledgers, 5);
+        }
+
+        for (name, count) in [("abfg", 2), ("j", 2), ("abce", 2), ("ab", 1)] {
+            let ledger = h.get_or_create(name);
+            assert_eq!(arena.remove(&ledger, Some(count)), reference.remove(&ledger, Some(count)));
+            assert_same(&arena, &reference, &ledgers, 5);
+        }
+    }
+
+    #[test]
+    fn stress_test_same_as_arena() {
+        let mut h = LedgerHistoryHelper::new();
+        let mut arena: ArenaLedgerTrie<SimulatedLedger> = ArenaLedgerTrie::new();
+        let mut reference: HashMapLedgerTrie<SimulatedLedger> = HashMapLedgerTrie::new();
+
+        // Randomly add and remove support for ledgers in a history of depth 3 where each ledger
+        // has 4 children, numbering each child after its parent's children so that no two
+        // ledgers end in the same character
+        let mut names = vec![String::new()];
+        let mut i = 0;
+        while i < names.len() {
+            if names[i].len() < 3 {
+                let offset = names[i].chars().last().map_or(0, |c| (c as u8 + 1) * 4);
+                for a in offset..offset + 4 {
+                    names.push(format!("{}{}", names[i], a as char));
+                }
+            }
+            i += 1;
+        }
+        let ledgers: Vec<_> = names.iter().map(|name| h.get_or_create(name)).collect();
+        let checked: Vec<&SimulatedLedger> = ledgers.iter().map(|l| &**l).collect();
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let ledger_dist = Uniform::from(0..ledgers.len());
+        let count_dist = Uniform::new_inclusive(1u32, 3);
+        let flip = Uniform::new_inclusive(0, 1);
+
+        for _ in 0..2000 {
+            let ledger = checked[ledger_dist.sample(&mut rng)];
+            let count = Some(count_dist.sample(&mut rng));
+            if flip.sample(&mut rng) == 0 {
+                arena.insert(ledger, count);
+                reference.insert(ledger, count);
+            } else {
+                assert_eq!(arena.remove(ledger, count), reference.remove(ledger, count));
+            }
+            assert_same(&arena, &reference, &checked, 5);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use core::hash::{BuildHasherDefault, Hash};
+
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use rustc_hash::FxHasher;
+
+use xrpl_consensus_core::{NodeId, SpannableLedger};
+
+use crate::arena_ledger_trie::ArenaLedgerTrie;
+use crate::ledger_trie::{LedgerTrie, Support, TrieError};
+
+/// An [`ArenaLedgerTrie`] that counts the support of each key, by default the [`NodeId`] of a
+/// validator, at most once.
+///
+/// Each key supports the ledger it last inserted, and inserting another ledger for the key
+/// moves its support there. The trie can only be changed through this wrapper, so the trie
+/// always holds the support of the ledger each key last inserted.
+pub struct KeyedLedgerTrie<T: SpannableLedger, N = NodeId, S: Support = u32> {
+    trie: ArenaLedgerTrie<T, S>,
+    /// The ledger each key last inserted.
+    last_inserted: HashMap<N, T, BuildHasherDefault<FxHasher>>,
+}
+
+impl<T: SpannableLedger, N: Eq + Hash, S: Support> Default for KeyedLedgerTrie<T, N, S> {
+    fn default() -> Self {
+        KeyedLedgerTrie::with_trie(ArenaLedgerTrie::default())
+    }
+}
+
+impl<T: SpannableLedger, N: Eq + Hash, S: Support> KeyedLedgerTrie<T, N, S> {
+    pub fn new() -> Self {
+        KeyedLedgerTrie::default()
+    }
+
+    /// Wrap an empty trie, for example one created
+    /// [`ArenaLedgerTrie::with_strict_ancestry`].
+    pub fn with_trie(trie: ArenaLedgerTrie<T, S>) -> Self {
+        assert!(trie.empty(), "trie must be empty");
+        KeyedLedgerTrie {
+            trie,
+            last_inserted: HashMap::default(),
+        }
+    }
+
+    /// Return the trie holding the support of every key.
+    pub fn trie(&self) -> &ArenaLedgerTrie<T, S> {
+        &self.trie
+    }
+
+    /// Return the ledger `key` supports, if any.
+    pub fn get(&self, key: &N) -> Option<&T> {
+        self.last_inserted.get(key)
+    }
+
+    /// Insert one validation of support for `ledger` on behalf of `key`, first removing the
+    /// support of the ledger `key` last inserted, so that `key` is never counted more than once.
+    ///
+    /// Inserting the ledger `key` already supports leaves the trie unchanged.
+    ///
+    /// # Returns
+    /// Any error from [`ArenaLedgerTrie::checked_insert`], in which case `key` still supports
+    /// the ledger it last inserted.
+    pub fn insert(&mut self, key: N, ledger: &T) -> Result<(), TrieError<S>> {
+        let prior = match self.last_inserted.get(&key) {
+            Some(prior) if prior.id() == ledger.id() => return Ok(()),
+            Some(prior) => Some(prior.clone()),
+            None => None,
+        };
+
+        // Remove the prior support first, so that moving it never overflows the total support
+        if let Some(prior) = &prior {
+            assert!(self.trie.remove(prior, None)?, "a key's ledger must have its support");
+        }
+        if let Err(e) = self.trie.checked_insert(ledger, None) {
+            if let Some(prior) = &prior {
+                self.trie.insert(prior, None);
+            }
+            return Err(e);
+        }
+        self.last_inserted.insert(key, ledger.clone());
+        Ok(())
+    }
+
+    /// Remove the support of `key`.
+    ///
+    /// # Returns
+    /// Whether `key` supported a ledger.
+    pub fn remove(&mut self, key: &N) -> Result<bool, TrieError<S>> {
+        match self.last_inserted.remove(key) {
+            Some(prior) => self.trie.remove(&prior, None),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xrpl_consensus_core::Ledger;
+
+    use crate::arena_ledger_trie::ArenaLedgerTrie;
+    use crate::keyed_ledger_trie::KeyedLedgerTrie;
+    use crate::ledger_trie::{LedgerTrie, TrieError};
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, SimulatedLedger};
+    use crate::test_utils::validation::peer_id;
+
+    #[test]
+    fn test_insert() {
+        let mut trie: KeyedLedgerTrie<SimulatedLedger> = KeyedLedgerTrie::new();
+        let (alice, bob) = (peer_id(1), peer_id(2));
+        let mut h = LedgerHistoryHelper::new();
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+
+        trie.insert(alice, &abc).unwrap();
+        trie.insert(bob, &abc).unwrap();
+        assert_eq!(trie.trie().tip_support(&abc), 2);
+
+        // Re-inserting the same ledger does not double count
+        trie.insert(alice, &abc).unwrap();
+        assert_eq!(trie.trie().tip_support(&abc), 2);
+
+        // A new ledger moves the key's support instead of adding to it
+        trie.insert(alice, &abd).unwrap();
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().tip_support(&abc), 1);
+        assert_eq!(trie.trie().tip_support(&abd), 1);
+        assert_eq!(trie.trie().branch_support(&ab), 2);
+
+        trie.insert(bob, &abd).unwrap();
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().branch_support(&abc), 0);
+        assert_eq!(trie.trie().tip_support(&abd), 2);
+        assert_eq!(trie.trie().branch_support(&ab), 2);
+
+        assert_eq!(trie.remove(&alice), Ok(true));
+        assert_eq!(trie.remove(&alice), Ok(false));
+        assert_eq!(trie.trie().tip_support(&abd), 1);
+        assert!(trie.get(&alice).is_none());
+    }
+
+    #[test]
+    fn test_insert_error() {
+        let trie = ArenaLedgerTrie::new().with_max_depth(1);
+        let mut trie: KeyedLedgerTrie<SimulatedLedger> = KeyedLedgerTrie::with_trie(trie);
+        let (alice, bob) = (peer_id(1), peer_id(2));
+        let mut h = LedgerHistoryHelper::new();
+        let abc = h.get_or_create("abc");
+        let abd = h.get_or_create("abd");
+
+        // Forking at `ab` would make the trie too deep, so the key keeps its support
+        trie.insert(alice, &abc).unwrap();
+        trie.insert(bob, &abc).unwrap();
+        assert_eq!(trie.insert(alice, &abd), Err(TrieError::TooDeep { max_depth: 1 }));
+        assert!(trie.trie().check_invariants());
+        assert_eq!(trie.trie().tip_support(&abc), 2);
+        assert_eq!(trie.trie().tip_support(&abd), 0);
+        assert_eq!(trie.get(&alice).unwrap().id(), abc.id());
+
+        // Once no other key holds the trie at `abc`, the last one can move
+        trie.remove(&bob).unwrap();
+        trie.insert(alice, &abd).unwrap();
+        assert_eq!(trie.trie().tip_support(&abd), 1);
+        assert_eq!(trie.trie().tip_support(&abc), 0);
+    }
+}
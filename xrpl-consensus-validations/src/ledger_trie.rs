@@ -1,6 +1,13 @@
-use xrpl_consensus_core::{Ledger, LedgerIndex};
+use core::fmt::{Debug, LowerHex};
+use core::ops::{Add, AddAssign, Sub, SubAssign};
 
-use crate::span::SpanTip;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use xrpl_consensus_core::{LedgerId, LedgerIndex, SpannableLedger};
+
+use crate::span::{SpanError, SpanTip};
 
 /// Ancestry trie of ledgers.
 /// 
@@ -30,7 +37,7 @@ use crate::span::SpanTip;
 ///    node.branchSupport += child.branchSupport;
 /// ```
 /// 
-/// The generic [`Ledger`] `T` type represents a ledger which has a unique history.
+/// The generic [`SpannableLedger`] `T` type represents a ledger which has a unique history.
 ///
 /// The unique history invariant of ledgers requires any ledgers that agree
 /// on the id of a given sequence number agree on ALL ancestors before that
@@ -44,15 +51,22 @@ use crate::span::SpanTip;
 ///         assert(a[p] == b[p]);
 /// }
 /// ```
-pub trait LedgerTrie<T: Ledger>: Default {
+pub trait LedgerTrie<T: SpannableLedger>: Default {
+    /// The type used to count support for ledgers.
+    type Support: Support;
 
     /// Insert and/or increment the support for the given ledger.
     ///
     /// # Params
     /// **ledger** - A `T` and its ancestry.
     ///
-    /// **count** - The count of support for this ledger.
-    fn insert(&mut self, ledger: &T, count: Option<u32>);
+    /// **count** - The count of support for this ledger. A count of zero leaves the trie
+    /// unchanged.
+    ///
+    /// # Panics
+    /// Implementations may panic if the total support in the trie would overflow
+    /// `Self::Support`.
+    fn insert(&mut self, ledger: &T, count: Option<Self::Support>);
 
     /// Decrease support for a ledger, removing and compressing if possible.
     ///
@@ -62,8 +76,10 @@ pub trait LedgerTrie<T: Ledger>: Default {
     /// **count** - The amount of tip support to remove.
     ///
     /// # Returns
-    /// Whether a matching node was decremented and possibly removed.
-    fn remove(&mut self, ledger: &T, count: Option<u32>) -> bool;
+    /// `Ok` with whether a matching node was decremented and possibly removed, or
+    /// `Err(TrieError::InsufficientSupport)` without changing the trie if the matching node has
+    /// less tip support than `count`.
+    fn remove(&mut self, ledger: &T, count: Option<Self::Support>) -> Result<bool, TrieError<Self::Support>>;
 
     /// Return the preferred ledger ID
     ///
@@ -121,15 +137,168 @@ pub trait LedgerTrie<T: Ledger>: Default {
     ///
     /// # Params
     /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    /// While walking past a sequence number below `largest_issued`, the support of every
+    /// validator whose latest validation has a smaller sequence number is counted as
+    /// uncommitted, since those validators may have since validated a ledger at our
+    /// sequence that we have not yet heard about. Passing `0` only counts validators
+    /// behind the walk itself as uncommitted.
     ///
     /// # Returns
     /// The `SpanTip` of the preferred ledger or `None` if no preferred ledger exists.
     fn get_preferred(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>>;
 
+    /// Return the preferred ledger along with its branch support, found in the same walk
+    /// of the trie, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the preferred ledger and its branch support, or `None` if no preferred
+    /// ledger exists.
+    fn get_preferred_with_support(&self, largest_issued: LedgerIndex) -> Option<(SpanTip<T>, Self::Support)>;
+
+    /// Return only the sequence number of the preferred ledger, for callers that don't need
+    /// its ID or the ledger itself, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The sequence number of the preferred ledger or `None` if no preferred ledger exists.
+    fn preferred_seq(&self, largest_issued: LedgerIndex) -> Option<LedgerIndex> {
+        self.get_preferred(largest_issued).map(|tip| tip.seq())
+    }
+
+    /// Return whether no ledger in the trie has any support.
+    fn empty(&self) -> bool;
+
     /// Return count of tip support for the specific ledger.
-    fn tip_support(&self, ledger: &T) -> u32;
+    fn tip_support(&self, ledger: &T) -> Self::Support;
 
     /// Return count of tip support for the specific ledger.
-    fn branch_support(&self, ledger: &T) -> u32;
+    fn branch_support(&self, ledger: &T) -> Self::Support;
+}
+
+/// Queries of a [`LedgerTrie`] that need more of its structure than [`LedgerTrie`] exposes,
+/// kept apart so that implementing [`LedgerTrie`] does not require them.
+pub trait LedgerTrieExt<T: SpannableLedger>: LedgerTrie<T> {
+    /// Return how far the best child of the preferred ledger leads the second best in branch
+    /// support, showing how close the fork that stopped the walk for the preferred ledger is,
+    /// see [`LedgerTrie::get_preferred`].
+    ///
+    /// Like the margin in the walk itself, this includes one extra support if the best child
+    /// holds the tie-breaker.
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The margin, which is zero if the preferred ledger has fewer than two children, or `None`
+    /// if no preferred ledger exists.
+    fn preferred_fork_margin(&self, largest_issued: LedgerIndex) -> Option<Self::Support>;
+
+    /// Return the branches that can no longer become preferred, for example to decide what to
+    /// prune.
+    ///
+    /// A branch off the path to the preferred ledger is abandoned if its branch support, plus
+    /// all the support that is uncommitted where it forks, is less than the branch support of
+    /// the sibling leading towards the preferred ledger, see [`LedgerTrie::get_preferred`].
+    ///
+    /// # Params
+    /// **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the first ledger of each abandoned branch, in no particular order.
+    fn abandoned_branches(&self, largest_issued: LedgerIndex) -> Vec<SpanTip<T>>;
+
+    /// Return the sum of the tip support of every ledger in the trie, including the genesis
+    /// ledger, which is the number of validations the trie currently holds.
+    fn total_support(&self) -> Self::Support;
+
+    /// Return count of tip support for the ledger at the tip of `tip`, such as one returned by
+    /// [`LedgerTrie::get_preferred`], without needing the full ledger.
+    fn tip_support_of(&self, tip: &SpanTip<T>) -> Self::Support;
+
+    /// Return count of branch support for the ledger at the tip of `tip`, such as one returned
+    /// by [`LedgerTrie::get_preferred`], without needing the full ledger.
+    fn branch_support_of(&self, tip: &SpanTip<T>) -> Self::Support;
+}
+
+
+/// Format a ledger ID the way every output of a [`LedgerTrie`] shows it, in hex.
+pub fn id_to_hex<I: LedgerId + LowerHex>(id: &I) -> String {
+    format!("{:x}", id)
+}
+
+/// A count of support for ledgers in a [`LedgerTrie`].
+pub trait Support:
+    Copy + Ord + Debug + Add<Output = Self> + Sub<Output = Self> + AddAssign + SubAssign + Into<u64> +
+    TryFrom<u64> {
+    /// No support.
+    fn zero() -> Self;
+
+    /// The support of a single validation.
+    fn one() -> Self;
+
+    /// Add `other` to this support, or return `None` if the sum is more than `Self` can count.
+    fn checked_add(self, other: Self) -> Option<Self> {
+        let (a, b): (u64, u64) = (self.into(), other.into());
+        Self::try_from(a.checked_add(b)?).ok()
+    }
+}
+
+impl Support for u32 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+}
+
+impl Support for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
 }
 
+/// Errors related to updating the support in a [`LedgerTrie`].
+#[derive(Eq, PartialEq, Debug)]
+pub enum TrieError<S: Support = u32> {
+    /// Tried to remove more tip support than a ledger has.
+    InsufficientSupport {
+        available: S,
+        requested: S,
+    },
+    /// Tried to add more support than `S` can count in total.
+    SupportOverflow {
+        total: S,
+        requested: S,
+    },
+    /// Tried to insert a ledger whose ancestor with sequence number `seq` contradicts the
+    /// ancestry it shares with the trie.
+    ConflictingAncestry {
+        seq: LedgerIndex,
+    },
+    /// Tried to insert a ledger that would make a path from the root of the trie longer than
+    /// `max_depth` nodes.
+    TooDeep {
+        max_depth: usize,
+    },
+    /// Tried to insert a ledger under a node that no longer exists, no longer ends with the
+    /// same ledger, or is not an ancestor of the ledger.
+    InvalidParent,
+    /// Tried to insert a ledger that neither is nor descends from the genesis ledger of the
+    /// trie, differing from it at sequence number `seq`.
+    DivergesFromGenesis {
+        seq: LedgerIndex,
+    },
+    /// Tried to insert a ledger whose ancestry can't be spanned.
+    InvalidSpan(SpanError),
+}
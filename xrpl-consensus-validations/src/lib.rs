@@ -1,19 +1,44 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)] // FIXME: Remove this eventually
 #![allow(unused_variables)] // FIXME: Remove this eventually
 
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("enable the `std` feature, or the `alloc` feature to build without `std`");
+
+#[cfg(feature = "std")]
 pub mod validations;
+#[cfg(feature = "std")]
 pub mod adaptor;
 pub mod ledger_trie;
+#[cfg(feature = "std")]
 pub mod validation_params;
+#[cfg(feature = "std")]
 pub(crate) mod seq_enforcer;
 pub(crate) mod span;
 pub mod arena_ledger_trie;
+pub mod keyed_ledger_trie;
+/// A simple reference ledger trie to check the others against, enabled by the `test-utils`
+/// feature.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod hash_map_ledger_trie;
+#[cfg(feature = "std")]
+pub mod snapshot_ledger_trie;
+#[cfg(feature = "std")]
+pub mod decaying_ledger_trie;
 
+#[cfg(feature = "std")]
 pub use validations::Validations;
+#[cfg(feature = "std")]
 pub use validations::ValidationError;
+#[cfg(feature = "std")]
 pub use adaptor::Adaptor;
+pub use ledger_trie::{Support, TrieError};
+pub use span::{LedgerStub, SpanError};
+#[cfg(feature = "std")]
 pub use validation_params::ValidationParams;
 
-#[cfg(test)]
-mod test_utils;
-
+/// Simulated ledgers and clocks for tests and benchmarks, enabled by the `test-utils` feature.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
@@ -1,5 +1,5 @@
 use std::ops::Add;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use xrpl_consensus_core::LedgerIndex;
 
@@ -19,9 +19,9 @@ impl SeqEnforcer {
     pub fn new() -> Self {
         SeqEnforcer {
             seq: 0,
-            // TODO: Not clear what this should be. In c++, this is a
-            //  std::chrono::steady_clock::time_point, which may default to the start of the Unix epoch
-            when: SystemTime::now(),
+            // Like the default time_point in rippled, start at the epoch rather than reading
+            // the wall clock, so that only the caller's clock decides when validations expire
+            when: UNIX_EPOCH,
         }
     }
 
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use xrpl_consensus_core::{LedgerIndex, SpannableLedger};
+
+use crate::arena_ledger_trie::ArenaLedgerTrie;
+use crate::ledger_trie::{LedgerTrie, LedgerTrieExt, Support, TrieError};
+use crate::span::SpanTip;
+
+/// An [`ArenaLedgerTrie`] whose preferred ledger can be read from other threads while it is
+/// being updated.
+///
+/// [`SnapshotLedgerTrie::publish`] stores an immutable copy of the trie, which
+/// [`SnapshotReader`]s read without taking a lock, so a reader never holds up a writer for the
+/// length of a preferred ledger walk. Readers only see updates once they are published, and may
+/// still see the previous snapshot for a short while after.
+pub struct SnapshotLedgerTrie<T: SpannableLedger, S: Support = u32> {
+    trie: ArenaLedgerTrie<T, S>,
+    snapshot: Arc<ArcSwap<ArenaLedgerTrie<T, S>>>,
+}
+
+/// A handle to the latest snapshot of a [`SnapshotLedgerTrie`], which can be sent to other
+/// threads.
+#[derive(Clone)]
+pub struct SnapshotReader<T: SpannableLedger, S: Support = u32> {
+    snapshot: Arc<ArcSwap<ArenaLedgerTrie<T, S>>>,
+}
+
+impl<T: SpannableLedger, S: Support> Default for SnapshotLedgerTrie<T, S> {
+    fn default() -> Self {
+        SnapshotLedgerTrie {
+            trie: ArenaLedgerTrie::default(),
+            snapshot: Arc::new(ArcSwap::from_pointee(ArenaLedgerTrie::default())),
+        }
+    }
+}
+
+impl<T: SpannableLedger, S: Support> SnapshotLedgerTrie<T, S> {
+    pub fn new() -> Self {
+        SnapshotLedgerTrie::default()
+    }
+
+    /// Return a handle for reading the snapshots of this trie.
+    pub fn reader(&self) -> SnapshotReader<T, S> {
+        SnapshotReader {
+            snapshot: self.snapshot.clone(),
+        }
+    }
+
+    /// Return the preferred ledger of the latest snapshot, see [`LedgerTrie::get_preferred`].
+    pub fn get_preferred_snapshot(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self.snapshot.load().get_preferred(largest_issued)
+    }
+
+    /// Return the trie being updated.
+    pub fn trie(&self) -> &ArenaLedgerTrie<T, S> {
+        &self.trie
+    }
+
+    /// Publish the trie as it is now to the readers.
+    ///
+    /// Publishing clones the whole trie, which takes time and memory in proportion to its
+    /// number of nodes, so callers should publish once after a batch of updates, such as all
+    /// the validations received for a round, rather than after each one.
+    pub fn publish(&self) {
+        self.snapshot.store(Arc::new(self.trie.clone()));
+    }
+}
+
+impl<T: SpannableLedger, S: Support> SnapshotReader<T, S> {
+    /// Return the preferred ledger of the latest snapshot, see [`LedgerTrie::get_preferred`].
+    pub fn get_preferred_snapshot(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self.snapshot.load().get_preferred(largest_issued)
+    }
+}
+
+impl<T: SpannableLedger, S: Support> LedgerTrie<T> for SnapshotLedgerTrie<T, S> {
+    type Support = S;
+
+    fn insert(&mut self, ledger: &T, count: Option<S>) {
+        self.trie.insert(ledger, count);
+    }
+
+    fn remove(&mut self, ledger: &T, count: Option<S>) -> Result<bool, TrieError<S>> {
+        self.trie.remove(ledger, count)
+    }
+
+    fn get_preferred(&self, largest_issued: LedgerIndex) -> Option<SpanTip<T>> {
+        self.trie.get_preferred(largest_issued)
+    }
+
+    fn get_preferred_with_support(&self, largest_issued: LedgerIndex) -> Option<(SpanTip<T>, S)> {
+        self.trie.get_preferred_with_support(largest_issued)
+    }
+
+    fn empty(&self) -> bool {
+        self.trie.empty()
+    }
+
+    fn tip_support(&self, ledger: &T) -> S {
+        self.trie.tip_support(ledger)
+    }
+
+    fn branch_support(&self, ledger: &T) -> S {
+        self.trie.branch_support(ledger)
+    }
+}
+
+impl<T: SpannableLedger, S: Support> LedgerTrieExt<T> for SnapshotLedgerTrie<T, S> {
+    fn preferred_fork_margin(&self, largest_issued: LedgerIndex) -> Option<S> {
+        self.trie.preferred_fork_margin(largest_issued)
+    }
+
+    fn abandoned_branches(&self, largest_issued: LedgerIndex) -> Vec<SpanTip<T>> {
+        self.trie.abandoned_branches(largest_issued)
+    }
+
+    fn total_support(&self) -> S {
+        self.trie.total_support()
+    }
+
+    fn tip_support_of(&self, tip: &SpanTip<T>) -> S {
+        self.trie.tip_support_of(tip)
+    }
+
+    fn branch_support_of(&self, tip: &SpanTip<T>) -> S {
+        self.trie.branch_support_of(tip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use xrpl_consensus_core::Ledger;
+
+    use crate::ledger_trie::LedgerTrie;
+    use crate::snapshot_ledger_trie::SnapshotLedgerTrie;
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, SimulatedLedger};
+
+    #[test]
+    fn test_get_preferred_snapshot() {
+        let mut h = LedgerHistoryHelper::new();
+        let ledgers: Vec<SimulatedLedger> = ["a", "ab", "abc", "abcd", "abcde", "abcdef"].iter()
+            .map(|name| (*h.get_or_create(name)).clone())
+            .collect();
+        let last = ledgers.last().unwrap().id();
+
+        let mut trie: SnapshotLedgerTrie<SimulatedLedger> = SnapshotLedgerTrie::new();
+        assert!(trie.get_preferred_snapshot(0).is_none());
+
+        // Read while a validator moves along the chain, until the last ledger is preferred
+        let reader = trie.reader();
+        let handle = thread::spawn(move || {
+            let mut reads = 0;
+            while !matches!(reader.get_preferred_snapshot(0), Some(tip) if tip.id() == last) {
+                reads += 1;
+            }
+            reads
+        });
+
+        trie.insert(&ledgers[0], None);
+        trie.publish();
+        for pair in ledgers.windows(2) {
+            trie.insert(&pair[1], None);
+            assert!(trie.remove(&pair[0], None).unwrap());
+            trie.publish();
+        }
+        handle.join().unwrap();
+
+        assert_eq!(trie.get_preferred_snapshot(0).unwrap().id(), last);
+        assert_eq!(trie.get_preferred(0).unwrap().id(), last);
+        assert!(trie.trie().check_invariants());
+
+        // Updates are not seen until they are published
+        let next = h.get_or_create("abcdefg");
+        trie.insert(&next, Some(2));
+        assert_eq!(trie.get_preferred(0).unwrap().id(), next.id());
+        assert_eq!(trie.get_preferred_snapshot(0).unwrap().id(), last);
+        trie.publish();
+        assert_eq!(trie.get_preferred_snapshot(0).unwrap().id(), next.id());
+    }
+}
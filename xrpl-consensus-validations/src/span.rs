@@ -1,18 +1,28 @@
-use std::fmt::{Display, Formatter};
+use core::fmt::{Debug, Display, Formatter, LowerHex};
 
-use xrpl_consensus_core::{Ledger, LedgerIndex};
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use xrpl_consensus_core::{first_mismatch, LedgerIndex, SpannableLedger};
+
+use crate::ledger_trie::id_to_hex;
 
 /// The tip of a span of ledger ancestry.
-pub struct SpanTip<T: Ledger> {
+#[derive(Clone)]
+pub struct SpanTip<T: SpannableLedger> {
     /// The sequence number of the tip ledger.
     seq: LedgerIndex,
     /// The ID of the tip ledger.
     id: T::IdType,
-    ledger: T,
+    ledger: Arc<T>,
 }
 
-impl<T: Ledger> SpanTip<T> {
-    pub(crate) fn new(seq: LedgerIndex, id: T::IdType, ledger: T) -> Self {
+impl<T: SpannableLedger> SpanTip<T> {
+    pub(crate) fn new(seq: LedgerIndex, id: T::IdType, ledger: Arc<T>) -> Self {
         SpanTip {
             seq,
             id,
@@ -20,14 +30,30 @@ impl<T: Ledger> SpanTip<T> {
         }
     }
 
-    pub(crate) fn id(&self) -> T::IdType {
+    pub fn id(&self) -> T::IdType {
         self.id
     }
 
-    pub(crate) fn seq(&self) -> LedgerIndex {
+    pub fn seq(&self) -> LedgerIndex {
         self.seq
     }
 
+    /// Lookup the ID of an ancestor of the tip ledger by `LedgerIndex`.
+    ///
+    /// # Params
+    /// seq - The sequence number of the ancestor.
+    ///
+    /// # Returns
+    /// The ID of the ancestor, which is the tip ledger itself if `seq` is the tip's sequence
+    /// number, or `None` if `seq` is greater than the tip's sequence number.
+    pub fn ancestor_id(&self, seq: LedgerIndex) -> Option<T::IdType> {
+        if seq > self.seq {
+            return None;
+        }
+
+        Some(self.ledger.get_ancestor(seq))
+    }
+
     /// Lookup the ID of an ancestor of the tip ledger by `LedgerIndex`. `seq` must be
     /// less than or equal to the sequence number of the tip ledger.
     ///
@@ -36,18 +62,78 @@ impl<T: Ledger> SpanTip<T> {
     pub(crate) fn ancestor(&self, seq: LedgerIndex) -> T::IdType {
         self.ledger.get_ancestor(seq)
     }
+
+    /// Return this tip, or if the ledger only knows sparse ancestry and doesn't know the ID at
+    /// the tip's sequence number, the tip of its closest ancestor whose ID it knows, see
+    /// [`SpannableLedger::known_ancestor_seq`].
+    pub(crate) fn known(&self) -> SpanTip<T> {
+        let seq = self.ledger.known_ancestor_seq(self.seq);
+        if seq == self.seq {
+            return self.clone();
+        }
+        SpanTip::new(seq, self.ledger.get_ancestor(seq), self.ledger.clone())
+    }
+
+    /// Return a standalone copy of the tip's sequence number and ID, for example to persist the
+    /// preferred ledger without keeping the ledger itself.
+    pub fn to_ledger_stub(&self) -> LedgerStub<T::IdType> {
+        self.to_ledger_stub_with_ancestors(0)
+    }
+
+    /// Return a standalone copy of the tip's sequence number and ID like
+    /// [`SpanTip::to_ledger_stub`], along with the IDs of up to `count` of its most recent
+    /// ancestors.
+    ///
+    /// For a ledger that only knows sparse ancestry, see
+    /// [`SpannableLedger::known_ancestor_seq`], the stub is of the closest ledger whose ID it
+    /// knows, and the ancestors stop before the first one whose ID it doesn't know.
+    pub fn to_ledger_stub_with_ancestors(&self, count: LedgerIndex) -> LedgerStub<T::IdType> {
+        let tip = self.known();
+        let first = tip.seq.saturating_sub(count);
+        let mut ancestors: Vec<T::IdType> = (first..tip.seq).rev()
+            .take_while(|seq| tip.ledger.known_ancestor_seq(*seq) == *seq)
+            .map(|seq| tip.ancestor(seq))
+            .collect();
+        ancestors.reverse();
+        LedgerStub {
+            seq: tip.seq,
+            id: tip.id,
+            ancestors,
+        }
+    }
+}
+
+/// A lightweight record of a ledger made from a [`SpanTip`], serializable with the `serde`
+/// feature.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LedgerStub<I> {
+    /// The sequence number of the ledger.
+    pub seq: LedgerIndex,
+    /// The ID of the ledger.
+    pub id: I,
+    /// The IDs of the ledger's most recent ancestors in sequence order, ending with its parent,
+    /// if any were requested.
+    #[cfg_attr(feature = "serde", serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty"))]
+    pub ancestors: Vec<I>,
 }
 
 /// Represents a span of ancestry of a ledger.
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-pub struct Span<T: Ledger> {
+///
+/// The ledger is shared between a span and the spans and tips derived from it, so splitting a
+/// span never clones the ledger itself.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Span<T: SpannableLedger> {
     start: LedgerIndex,
     end: LedgerIndex,
-    ledger: T
+    ledger: Arc<T>,
+    /// The ID of the ledger at `end - 1`, looked up once when the span is created since
+    /// [`Span::tip`] is called far more often than spans are created.
+    tip_id: T::IdType,
 }
 
-impl<T: Ledger> Display for Span<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl<T: SpannableLedger + Display> Display for Span<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         if self.start == 0 {
             write!(f, "{}-{}[{}, {})", "genesis", self.tip().id(), self.start(), self.end())
         } else {
@@ -57,22 +143,56 @@ impl<T: Ledger> Display for Span<T> {
     }
 }
 
-impl<T: Ledger> From<T> for Span<T> {
+/// Formats the span's sequence numbers and the IDs of its first and last ledgers in hex, see
+/// [`id_to_hex`].
+impl<T: SpannableLedger> Debug for Span<T> where T::IdType: LowerHex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Span({}..{}, start: {}, tip: {})", self.start, self.end, id_to_hex(&self.start_id()), id_to_hex(&self.tip().id()))
+    }
+}
+
+impl<T: SpannableLedger> From<T> for Span<T> {
     fn from(value: T) -> Span<T> {
-        Span {
-            start: 0,
-            end: value.seq() + 1,
-            ledger: value
-        }
+        Span::_new(0, value.seq() + 1, Arc::new(value))
     }
 }
 
-impl<T: Ledger> Span<T> {
-    fn _new(start: LedgerIndex, end: LedgerIndex, ledger: T) -> Self {
+/// Errors from creating a [`Span`] out of a ledger with [`Span::try_from_ledger`].
+#[derive(Eq, PartialEq, Debug)]
+pub enum SpanError {
+    /// The ledger is the genesis ledger, which has no ancestry to span.
+    ZeroSequence,
+    /// The ledger's ancestry does not end with the ledger itself or does not start with the
+    /// genesis ledger.
+    InconsistentAncestry,
+}
+
+impl<T: SpannableLedger> Span<T> {
+    /// Create the Span of the ancestry of `ledger`, checking that it has a positive sequence
+    /// number and that its ancestry runs from the genesis ledger to the ledger itself.
+    ///
+    /// This is a fallible alternative to `Span::from`, which accepts any ledger. It can't be
+    /// `TryFrom` since `From` already provides an infallible one.
+    pub fn try_from_ledger(ledger: T) -> Result<Span<T>, SpanError> {
+        if ledger.seq() == 0 {
+            return Err(SpanError::ZeroSequence);
+        }
+
+        if ledger.get_ancestor(ledger.seq()) != ledger.id() ||
+            ledger.get_ancestor(0) != T::make_genesis().id() {
+            return Err(SpanError::InconsistentAncestry);
+        }
+
+        Ok(Span::from(ledger))
+    }
+
+    fn _new(start: LedgerIndex, end: LedgerIndex, ledger: Arc<T>) -> Self {
+        let tip_id = ledger.get_ancestor(end - 1);
         Span {
             start,
             end,
             ledger,
+            tip_id,
         }
     }
 
@@ -84,6 +204,11 @@ impl<T: Ledger> Span<T> {
         self.end
     }
 
+    /// Return an iterator over the sequence numbers in [start,end).
+    pub fn seqs(&self) -> impl Iterator<Item = LedgerIndex> {
+        self.start..self.end
+    }
+
     /// Return the Span from [spot,end) or `None` if no such valid span exists.
     pub fn after(&self, spot: LedgerIndex) -> Option<Span<T>> {
         self._sub(spot, self.end)
@@ -94,25 +219,57 @@ impl<T: Ledger> Span<T> {
         self._sub(self.start, spot)
     }
 
+    /// Return the Spans from [start,spot) and [spot,end) in one call, either of which is `None`
+    /// if no such valid span exists.
+    pub fn split_at(&self, spot: LedgerIndex) -> (Option<Span<T>>, Option<Span<T>>) {
+        (self.before(spot), self.after(spot))
+    }
+
+    /// Return the Span of ancestry shared by this span and another, or `None` if they have no
+    /// ledgers in common.
+    pub fn common_with(&self, other: &Span<T>) -> Option<Span<T>> {
+        let diff = core::cmp::min(self.ledger.mismatch(&other.ledger), other.end);
+        self._sub(core::cmp::max(self.start, other.start), diff)
+    }
+
     /// Return the ID of the ledger that starts this span.
     pub fn start_id(&self) -> T::IdType {
         self.ledger.get_ancestor(self.start)
     }
 
+    /// Return the sequence number of the first ancestor that differs between the ledger backing
+    /// this span and a given ledger, or the end of the span if they agree up to it.
+    ///
+    /// Unlike [`Span::diff`], this is not raised to the start of the span, so it also tells
+    /// where the ledgers differ before the span starts.
+    pub fn first_mismatch(&self, other: &T) -> LedgerIndex {
+        core::cmp::min(self.ledger.mismatch(other), self.end)
+    }
+
     /// Return the ledger sequence number of the first possible difference
     /// between this span and a given ledger.
     pub fn diff(&self, other: &T) -> LedgerIndex {
-        self._clamp(self.ledger.mismatch(other))
+        self._clamp(self.first_mismatch(other))
+    }
+
+    /// Return the ledger sequence number of the first possible difference
+    /// between this span and a ledger known only by its sequence number and
+    /// an ancestry lookup.
+    ///
+    /// `ancestor` is only called for the sequences needed to binary search
+    /// for the first difference, all of which are less than or equal to `seq`.
+    pub(crate) fn diff_by<F: Fn(LedgerIndex) -> T::IdType>(&self, seq: LedgerIndex, ancestor: F) -> LedgerIndex {
+        let last = core::cmp::min(self.ledger.seq(), seq);
+        self._clamp(first_mismatch(last, |curr| self.ledger.get_ancestor(curr) == ancestor(curr)))
     }
 
     /// Return The tip `SpanTip` of this span.
     pub fn tip(&self) -> SpanTip<T> {
-        let tip_seq = self.end - 1;
-        SpanTip::new(tip_seq, self.ledger.get_ancestor(tip_seq), self.ledger.clone())
+        SpanTip::new(self.end - 1, self.tip_id, self.ledger.clone())
     }
 
     fn _clamp(&self, seq: LedgerIndex) -> LedgerIndex {
-        std::cmp::min(std::cmp::max(self.start, seq), self.end)
+        core::cmp::min(core::cmp::max(self.start, seq), self.end)
     }
 
     fn _sub(&self, from: LedgerIndex, to: LedgerIndex) -> Option<Span<T>> {
@@ -128,26 +285,278 @@ impl<T: Ledger> Span<T> {
         // Return combined span, using ledger_ from higher sequence span
         if a.end < b.end {
             return Span::_new(
-                std::cmp::min(a.start, b.start),
+                core::cmp::min(a.start, b.start),
                 b.end,
                 b.ledger.clone()
             );
         }
 
         return Span::_new(
-            std::cmp::min(a.start, b.start),
+            core::cmp::min(a.start, b.start),
             a.end,
             a.ledger.clone()
         )
     }
 }
 
-impl<T: Ledger> Default for Span<T> {
+impl<T: SpannableLedger> Default for Span<T> {
     fn default() -> Self {
-        Span {
-            start: 0,
-            end: 1,
-            ledger: T::make_genesis()
+        Span::_new(0, 1, Arc::new(T::make_genesis()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use xrpl_consensus_core::{Ledger, LedgerIndex};
+
+    use crate::span::{Span, SpanError};
+    use crate::test_utils::ledgers::{LedgerHistoryHelper, LedgerId, SimulatedLedger};
+
+    /// A ledger that reports a different ID than its ancestry says it has.
+    #[derive(Clone, Debug)]
+    struct MislabeledLedger(Rc<SimulatedLedger>, LedgerId);
+
+    impl xrpl_consensus_core::SpannableLedger for MislabeledLedger {
+        type IdType = LedgerId;
+
+        fn id(&self) -> LedgerId {
+            self.1
+        }
+
+        fn seq(&self) -> LedgerIndex {
+            Ledger::seq(&*self.0)
+        }
+
+        fn get_ancestor(&self, seq: LedgerIndex) -> LedgerId {
+            Ledger::get_ancestor(&*self.0, seq)
+        }
+
+        fn make_genesis() -> Self {
+            let genesis = <SimulatedLedger as Ledger>::make_genesis();
+            let id = Ledger::id(&genesis);
+            MislabeledLedger(Rc::new(genesis), id)
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_try_from_ledger() {
+        let mut h = LedgerHistoryHelper::new();
+        let abc = h.get_or_create("abc");
+        let ab = h.get_or_create("ab");
+
+        let span = Span::try_from_ledger((*abc).clone()).unwrap();
+        assert_eq!(span, Span::from((*abc).clone()));
+
+        let genesis = h.get_or_create("");
+        assert_eq!(Span::try_from_ledger((*genesis).clone()), Err(SpanError::ZeroSequence));
+
+        let labeled = MislabeledLedger(abc.clone(), abc.id());
+        assert!(Span::try_from_ledger(labeled).is_ok());
+        let mislabeled = MislabeledLedger(abc.clone(), ab.id());
+        assert_eq!(Span::try_from_ledger(mislabeled).unwrap_err(), SpanError::InconsistentAncestry);
+    }
+
+    #[test]
+    fn test_split_at() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone());
+
+        let (before, after) = span.split_at(3);
+        let (before, after) = (before.unwrap(), after.unwrap());
+        assert_eq!((before.start(), before.end()), (0, 3));
+        assert_eq!((after.start(), after.end()), (3, 6));
+        assert_eq!(before.tip().id(), abcde.get_ancestor(2));
+        assert_eq!(after.start_id(), abcde.get_ancestor(3));
+
+        let merged = Span::merge(&before, &after);
+        assert_eq!((merged.start(), merged.end()), (span.start(), span.end()));
+        assert_eq!(merged.tip().id(), span.tip().id());
+
+        // Splitting at either end leaves one half empty
+        let (before, after) = span.split_at(span.start());
+        assert!(before.is_none());
+        assert_eq!(after, Some(span.clone()));
+
+        let (before, after) = span.split_at(span.end());
+        assert_eq!(before, Some(span.clone()));
+        assert!(after.is_none());
+    }
+
+    #[test]
+    fn test_common_with() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = Span::from((*h.get_or_create("abcde")).clone());
+        let abcfg = Span::from((*h.get_or_create("abcfg")).clone());
+        let hi = Span::from((*h.get_or_create("hi")).clone());
+
+        let common = abcde.common_with(&abcfg).unwrap();
+        assert_eq!((common.start(), common.end()), (0, 4));
+        assert_eq!(common.tip().id(), abcfg.before(4).unwrap().tip().id());
+        assert_eq!(abcfg.common_with(&abcde).unwrap().tip().id(), common.tip().id());
+
+        // Overlapping spans only share the sequences they both contain
+        let common = abcde.after(2).unwrap().common_with(&abcfg.before(5).unwrap()).unwrap();
+        assert_eq!((common.start(), common.end()), (2, 4));
+
+        // Different histories still share the genesis ledger
+        let common = abcde.common_with(&hi).unwrap();
+        assert_eq!((common.start(), common.end()), (0, 1));
+
+        // Disjoint spans share nothing
+        assert!(abcde.after(4).unwrap().common_with(&abcfg).is_none());
+        assert!(abcde.before(2).unwrap().common_with(&abcde.after(3).unwrap()).is_none());
+        assert!(abcde.after(1).unwrap().common_with(&hi).is_none());
+    }
+
+    #[test]
+    fn test_first_mismatch() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone());
+
+        // Identical ledgers agree on the whole span
+        assert_eq!(span.first_mismatch(&abcde), 6);
+        assert_eq!(span.diff(&abcde), 6);
+
+        // An ancestor agrees up to its own sequence number, and a descendant up to the span's end
+        let abc = h.get_or_create("abc");
+        assert_eq!(span.first_mismatch(&abc), 4);
+        assert_eq!(Span::from((*abc).clone()).first_mismatch(&abcde), 4);
+
+        // Divergent ledgers differ at their first different ancestor
+        let abfg = h.get_or_create("abfg");
+        assert_eq!(span.first_mismatch(&abfg), 3);
+        assert_eq!(span.diff(&abfg), 3);
+        let hi = h.get_or_create("hi");
+        assert_eq!(span.first_mismatch(&hi), 1);
+
+        // Unlike diff, a difference before the span starts is not raised to its start
+        let after = span.after(4).unwrap();
+        assert_eq!(after.first_mismatch(&abfg), 3);
+        assert_eq!(after.diff(&abfg), 4);
+        assert_eq!(span.before(2).unwrap().first_mismatch(&abfg), 2);
+    }
+
+    #[test]
+    fn test_span_tip() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone()).before(4).unwrap();
+
+        let tip = span.tip();
+        assert_eq!(tip.seq(), span.end() - 1);
+        assert_eq!(tip.id(), abcde.get_ancestor(3));
+        for seq in 0..=tip.seq() {
+            assert_eq!(tip.ancestor_id(seq), Some(abcde.get_ancestor(seq)));
+        }
+        assert_eq!(tip.ancestor_id(tip.seq()), Some(tip.id()));
+        assert_eq!(tip.ancestor_id(tip.seq() + 1), None);
+    }
+
+    #[test]
+    fn test_cached_tip() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let abcfg = Span::from((*h.get_or_create("abcfg")).clone());
+        let span = Span::from((*abcde).clone());
+
+        let mut derived = vec![span.clone(), Span::default()];
+        for spot in span.seqs() {
+            derived.extend(span.before(spot));
+            derived.extend(span.after(spot));
+            derived.extend(span.after(spot).and_then(|after| after.before(spot + 2)));
+        }
+        derived.extend(span.common_with(&abcfg));
+        derived.push(Span::merge(&span.before(2).unwrap(), &span.after(2).unwrap()));
+
+        // Every tip matches the ledger's ancestry at the end of its span, however it was derived
+        for span in derived {
+            let tip = span.tip();
+            assert_eq!(tip.seq(), span.end() - 1);
+            assert_eq!(tip.id(), tip.ancestor(tip.seq()), "{:?}", span);
+            assert_eq!(tip.id(), span.tip().id());
+        }
+    }
+
+    #[test]
+    fn test_ledger_stub() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let tip = Span::from((*abcde).clone()).before(4).unwrap().tip();
+
+        let stub = tip.to_ledger_stub();
+        assert_eq!(stub.seq, 3);
+        assert_eq!(stub.id, abcde.get_ancestor(3));
+        assert!(stub.ancestors.is_empty());
+
+        // Ancestors stop at the genesis ledger
+        let stub = tip.to_ledger_stub_with_ancestors(2);
+        assert_eq!(stub.ancestors, vec![abcde.get_ancestor(1), abcde.get_ancestor(2)]);
+        let stub = tip.to_ledger_stub_with_ancestors(10);
+        assert_eq!(stub.ancestors, (0..3).map(|seq| abcde.get_ancestor(seq)).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ledger_stub_serde() {
+        use crate::span::LedgerStub;
+
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let tip = Span::from((*abcde).clone()).tip();
+
+        // Stubs without ancestors leave them out
+        let stub = tip.to_ledger_stub();
+        let json = serde_json::to_string(&stub).unwrap();
+        assert!(!json.contains("ancestors"));
+        assert_eq!(serde_json::from_str::<LedgerStub<LedgerId>>(&json).unwrap(), stub);
+
+        let stub = tip.to_ledger_stub_with_ancestors(2);
+        let json = serde_json::to_string(&stub).unwrap();
+        assert_eq!(serde_json::from_str::<LedgerStub<LedgerId>>(&json).unwrap(), stub);
+    }
+
+    #[test]
+    fn test_derived_spans_share_ledger() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone());
+        assert_eq!(Arc::strong_count(&span.ledger), 1);
+
+        let (before, after) = span.split_at(3);
+        let (before, after) = (before.unwrap(), after.unwrap());
+        let merged = Span::merge(&before, &after);
+        let tip = after.tip();
+        assert!(Arc::ptr_eq(&span.ledger, &before.ledger));
+        assert!(Arc::ptr_eq(&span.ledger, &after.ledger));
+        assert!(Arc::ptr_eq(&span.ledger, &merged.ledger));
+        assert!(Arc::ptr_eq(&span.ledger, &tip.ledger));
+        assert_eq!(Arc::strong_count(&span.ledger), 5);
+
+        drop((before, after, merged, tip));
+        assert_eq!(Arc::strong_count(&span.ledger), 1);
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut h = LedgerHistoryHelper::new();
+        h.get_or_create("ABCDEFGHIJ");
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone()).after(2).unwrap();
+        assert_eq!(format!("{:?}", span), "Span(2..6, start: c, tip: f)");
+    }
+
+    #[test]
+    fn test_seqs() {
+        let mut h = LedgerHistoryHelper::new();
+        let abcde = h.get_or_create("abcde");
+        let span = Span::from((*abcde).clone()).after(2).unwrap().before(5).unwrap();
+
+        assert_eq!(span.seqs().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(Span::_new(3, 3, Arc::new((*abcde).clone())).seqs().count(), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::{HashMap, HashSet};
-use std::fmt::{Display, Formatter};
+use std::fmt::{Display, Formatter, LowerHex};
 use std::hash::Hash;
 use std::ops::{Add, Div, Sub};
 use std::rc::Rc;
@@ -9,15 +9,15 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use bimap::BiMap;
 use derivative::Derivative;
 use once_cell::sync::Lazy;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use xrpl_consensus_core::{Ledger, LedgerIndex};
 
-pub(crate) type TxSetType = Vec<Tx>;
-pub(crate) type TxId = u32;
+pub type TxSetType = Vec<Tx>;
+pub type TxId = u32;
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, Serialize)]
-pub(crate) struct LedgerId(u32);
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
+pub struct LedgerId(u32);
 
 impl LedgerId {
     pub fn new(id: u32) -> Self {
@@ -30,12 +30,18 @@ impl Display for LedgerId {
         write!(f, "{}", self.0)
     }
 }
+impl LowerHex for LedgerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        LowerHex::fmt(&self.0, f)
+    }
+}
+
 impl xrpl_consensus_core::LedgerId for LedgerId {
 
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Hash, Debug)]
-pub(crate) struct Tx {
+pub struct Tx {
     id: TxId,
 }
 
@@ -51,7 +57,7 @@ impl Tx {
 
 #[derive(Derivative)]
 #[derivative(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Debug)]
-pub(crate) struct LedgerInstance {
+pub struct LedgerInstance {
     string: String,
     seq: LedgerIndex,
     txs: TxSetType,
@@ -117,7 +123,7 @@ impl LedgerInstance {
 /// distinct ids, even if they have the same set of transactions, sequence
 /// number and close time.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Debug)]
-pub(crate) struct SimulatedLedger {
+pub struct SimulatedLedger {
     instance: Arc<LedgerInstance>,
     id: LedgerId,
 }
@@ -128,7 +134,7 @@ impl Display for SimulatedLedger {
     }
 }
 
-pub(crate) static GENESIS: Lazy<LedgerInstance> = Lazy::new(|| {
+pub static GENESIS: Lazy<LedgerInstance> = Lazy::new(|| {
     LedgerInstance::genesis()
 });
 
@@ -228,10 +234,16 @@ impl Ledger for SimulatedLedger {
 
 /// Oracle maintaining unique ledgers for a simulation.
 #[derive(Debug)]
-pub(crate) struct LedgerOracle {
+pub struct LedgerOracle {
     instances: BiMap<Arc<LedgerInstance>, LedgerId>,
 }
 
+impl Default for LedgerOracle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LedgerOracle {
     pub fn new() -> Self {
         let mut instances = BiMap::new();
@@ -370,13 +382,19 @@ fn round_close_time(close_time: &SystemTime, close_resolution: Duration) -> Syst
 }
 
 #[derive(Debug)]
-pub(crate) struct LedgerHistoryHelper {
+pub struct LedgerHistoryHelper {
     oracle: LedgerOracle,
     next_tx: TxId,
     ledgers: HashMap<String, Rc<SimulatedLedger>>,
     seen: HashSet<char>,
 }
 
+impl Default for LedgerHistoryHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LedgerHistoryHelper {
     pub fn new() -> Self {
         let mut ledgers = HashMap::new();
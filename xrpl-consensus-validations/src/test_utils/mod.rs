@@ -1,14 +1,24 @@
 use std::time::{Duration, SystemTime};
 use xrpl_consensus_core::NetClock;
 
+// The types needed to build the nodes of an ArenaLedgerTrie directly
+pub use generational_arena::Index;
+pub use crate::span::Span;
+
+pub mod ledgers;
 #[cfg(test)]
-pub(crate) mod ledgers;
 pub(crate) mod validation;
 
 pub struct ManualClock {
     now: SystemTime
 }
 
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ManualClock {
     pub fn new() -> Self {
         ManualClock {
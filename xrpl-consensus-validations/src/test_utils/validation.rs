@@ -1,41 +1,19 @@
-use std::ops::{Add, AddAssign, Sub};
 use std::time::SystemTime;
 
 use derivative::Derivative;
 
-use xrpl_consensus_core::{Ledger, LedgerIndex, Validation};
+use xrpl_consensus_core::{Ledger, LedgerIndex, NodeId, Validation};
 
 use crate::test_utils::ledgers::SimulatedLedger;
 
-#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub(crate) struct PeerId(pub u32);
+/// Simulated peers are identified by the same [`NodeId`] as real validators.
+pub(crate) type PeerId = NodeId;
 
-impl Add for PeerId {
-    type Output = Self;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        PeerId(self.0 + rhs.0)
-    }
-}
-
-impl AddAssign for PeerId {
-    fn add_assign(&mut self, rhs: Self) {
-        self.0 += rhs.0
-    }
-}
-
-impl AddAssign<u32> for PeerId {
-    fn add_assign(&mut self, rhs: u32) {
-        self.0 += rhs
-    }
-}
-
-impl Sub for PeerId {
-    type Output = Self;
-
-    fn sub(self, rhs: Self) -> Self::Output {
-        PeerId(self.0 - rhs.0)
-    }
+/// Return the `NodeId` of the `n`th simulated peer.
+pub(crate) fn peer_id(n: u32) -> PeerId {
+    let mut bytes = [0; NodeId::LEN];
+    bytes[NodeId::LEN - 4..].copy_from_slice(&n.to_be_bytes());
+    NodeId::from_bytes(bytes)
 }
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
@@ -89,10 +67,6 @@ impl TestValidation {
         }
     }
 
-    pub(crate) fn node_id(&self) -> &PeerId {
-        &self.node_id
-    }
-
     pub(crate) fn key(&self) -> &PeerKey {
         &self.key
     }
@@ -112,6 +86,7 @@ impl TestValidation {
 
 impl Validation for TestValidation {
     type LedgerIdType = <SimulatedLedger as Ledger>::IdType;
+    type NodeIdType = PeerId;
 
     fn seq(&self) -> LedgerIndex {
         self.seq
@@ -121,6 +96,10 @@ impl Validation for TestValidation {
         self.ledger_id
     }
 
+    fn node_id(&self) -> PeerId {
+        self.node_id
+    }
+
     fn sign_time(&self) -> SystemTime {
         self.sign_time
     }
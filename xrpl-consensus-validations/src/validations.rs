@@ -9,8 +9,9 @@ use xrpl_consensus_core::{Ledger, LedgerIndex, NetClock, Validation, WallNetCloc
 use xrpl_consensus_core::aged_unordered_map::AgedUnorderedMap;
 
 use crate::adaptor::Adaptor;
-use crate::ledger_trie::LedgerTrie;
+use crate::ledger_trie::{LedgerTrie, TrieError};
 use crate::seq_enforcer::SeqEnforcer;
+use crate::span::SpanTip;
 use crate::validation_params::ValidationParams;
 
 struct KeepRange {
@@ -60,6 +61,10 @@ pub struct Validations<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock = W
     /// Adaptor instance
     /// Is NOT managed by the Mutex above
     adaptor: A,
+    /// Called with each validation dropped by `gc`
+    on_stale: Option<Box<dyn FnMut(A::ValidationType) + Send>>,
+    /// Number of times a node's latest validated ledger was moved in the trie
+    trie_updates: u64,
 }
 
 impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C> {
@@ -76,6 +81,8 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
             acquiring: Default::default(),
             params,
             adaptor,
+            on_stale: None,
+            trie_updates: 0,
         }
     }
 }
@@ -93,6 +100,18 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         &self.params
     }
 
+    /// Return the number of times a trusted node's latest validated ledger was moved in the
+    /// trie. A node validating the ledger it already supports in the trie is not counted.
+    pub fn trie_updates(&self) -> u64 {
+        self.trie_updates
+    }
+
+    /// Set a callback to be called with each validation as it is dropped by `gc`, for example to
+    /// record it in a stale validation log. Replaces any previously set callback.
+    pub fn set_stale_callback(&mut self, cb: impl FnMut(A::ValidationType) + Send + 'static) {
+        self.on_stale = Some(Box::new(cb));
+    }
+
     /// Return whether the local node can issue a validation for the given
     /// sequence number.
     ///
@@ -216,6 +235,62 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         todo!()
     }
 
+    /// Garbage collect the validation sets of nodes we have not heard from in a while.
+    ///
+    /// Unlike `expire`, which only ages out validation sets by ledger, this drops every trace
+    /// of a node whose last validation was received more than
+    /// `ValidationParams.validation_set_expires()` before `now`: its current validation, its
+    /// sequence enforcer, its entries in the by-ledger and by-sequence maps, any ledger it is
+    /// acquiring and its support in the trie. Each dropped validation is passed to the callback
+    /// set by `set_stale_callback`, if any.
+    ///
+    /// # Params
+    /// - **now**: The time to measure each node's last validation against.
+    pub fn gc(&mut self, now: SystemTime) {
+        let expires = self.params.validation_set_expires();
+        let expired: HashSet<A::NodeIdType> = self.seq_enforcers.iter()
+            .filter(|(_, enforcer)| now > enforcer.when() + expires)
+            .map(|(node_id, _)| *node_id)
+            .collect();
+
+        if expired.is_empty() {
+            return;
+        }
+
+        self.seq_enforcers.retain(|node_id, _| !expired.contains(node_id));
+        self.current.retain(|node_id, _| !expired.contains(node_id));
+
+        for node_id in &expired {
+            if let Some(ledger) = self.last_ledger.remove(node_id) {
+                Self::_remove_support(&mut self.trie, &ledger);
+            }
+        }
+
+        self.acquiring.retain(|_, node_ids| {
+            node_ids.retain(|node_id| !expired.contains(node_id));
+            !node_ids.is_empty()
+        });
+
+        // Every accepted validation is in by_ledger, so report stale validations from there
+        let on_stale = &mut self.on_stale;
+        self.by_ledger.retain(|_, vals| {
+            vals.retain(|node_id, val| {
+                let keep = !expired.contains(node_id);
+                if !keep {
+                    if let Some(cb) = on_stale.as_mut() {
+                        cb(*val);
+                    }
+                }
+                keep
+            });
+            !vals.is_empty()
+        });
+        self.by_sequence.retain(|_, vals| {
+            vals.retain(|node_id, _| !expired.contains(node_id));
+            !vals.is_empty()
+        });
+    }
+
     /// Update the trust status of validations.
     ///
     /// Updates the trusted status of known validations to account for nodes that have been added or
@@ -354,6 +429,27 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
             )
     }
 
+    /// Return the preferred ledger only once its branch support among trusted validators has
+    /// reached a quorum.
+    ///
+    /// # Params
+    /// - **quorum**: The minimum number of trusted validators supporting the preferred ledger or
+    ///   one of its descendants.
+    /// - **largest_issued**: The sequence number of the largest validation issued by this node.
+    ///
+    /// # Returns
+    /// The `SpanTip` of the preferred ledger, or `None` if there is no preferred ledger or its
+    /// support is less than `quorum`.
+    pub fn quorum_reached(
+        &mut self,
+        quorum: T::Support,
+        largest_issued: LedgerIndex,
+    ) -> Option<SpanTip<A::LedgerType>> {
+        self._with_trie(|trie| trie.get_preferred_with_support(largest_issued))
+            .filter(|(_, support)| *support >= quorum)
+            .map(|(tip, _)| tip)
+    }
+
     /// Count the number of current trusted validators working on a ledger after the specified
     /// ledger.
     ///
@@ -369,7 +465,7 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
             return self._with_trie(|trie| {
                 let branch_support = trie.branch_support(ledger);
                 let tip_support = trie.tip_support(ledger);
-                branch_support - tip_support
+                (branch_support - tip_support).into()
             }) as usize;
         }
 
@@ -457,23 +553,64 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         )
     }
 
-    /// Flush all current validations.
-    pub fn flush(&mut self) {
-        self.current.clear();
+    /// Remove and return all current validations, for example to persist them on shutdown.
+    ///
+    /// Each node's support is removed from the trie and any ledgers still being acquired are
+    /// forgotten, leaving the trie empty.
+    pub fn flush(&mut self) -> Vec<(A::NodeIdType, A::ValidationType)> {
+        for (_, ledger) in self.last_ledger.drain() {
+            Self::_remove_support(&mut self.trie, &ledger);
+        }
+        self.acquiring.clear();
+        self.current.drain().collect()
     }
 
-    /// Return the quantity of lagging proposers, and remove online proposers for purposes of
+    /// Return the quantity of lagging proposers, and remove caught up proposers for purposes of
     /// evaluating whether to pause.
     ///
     /// Laggards are the trusted proposers whose sequence number is lower than the sequence number
     /// from which our current pending proposal is based. Proposers from whom we have not
-    /// received a validation for awhile are considered offline.
-    pub fn laggards(&mut self, seq: LedgerIndex, trusted_keys: HashSet<&mut A::NodeKeyType>) -> usize {
-        todo!()
+    /// received a validation for awhile are considered offline, not lagging.
+    ///
+    /// # Params
+    /// - **seq**: The sequence number of our current pending proposal's ledger.
+    /// - **trusted_keys**: The identifiers of trusted proposers. Any proposer with a fresh
+    ///   validation for `seq` or later is removed, leaving the lagging and offline proposers.
+    ///
+    /// # Returns
+    /// The number of trusted proposers with a fresh validation for a sequence lower than `seq`.
+    pub fn laggards(&mut self, seq: LedgerIndex, trusted_keys: &mut HashSet<A::NodeIdType>) -> usize {
+        let now = self.adaptor.now();
+        let freshness = self.params.validation_freshness();
+        let mut laggards = 0;
+        self._current(|node_id, val| {
+            if now < val.seen_time() + freshness && trusted_keys.contains(node_id) {
+                if seq > val.seq() {
+                    laggards += 1;
+                } else {
+                    trusted_keys.remove(node_id);
+                }
+            }
+        });
+
+        laggards
     }
 
 
     ///// Private functions
+
+    /// Remove a node's support for `ledger` from the trie, if the trie still has it. The trie
+    /// only lacks it if the trie and `last_ledger` disagree.
+    fn _remove_support(trie: &mut T, ledger: &A::LedgerType) {
+        match trie.remove(ledger, None) {
+            Ok(_) => {}
+            // Only a single validation was removed, so the ledger had no tip support left, and
+            // clamping the count to the ledger's support would also leave the trie unchanged
+            Err(TrieError::InsufficientSupport { .. }) => {}
+            Err(e) => unreachable!("remove only reports insufficient support, not {:?}", e),
+        }
+    }
+
     fn _remove_trie(
         trie: &mut T,
         acquiring: &mut HashMap<(LedgerIndex, A::LedgerIdType), HashSet<A::NodeIdType>>,
@@ -491,22 +628,27 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
 
         if let Entry::Occupied(e) = last_ledger.entry(*node_id) {
             if e.get().id() == validation.ledger_id() {
-                trie.remove(e.get(), None);
+                Self::_remove_support(trie, e.get());
                 last_ledger.remove(node_id);
             }
         }
     }
 
+    /// Move a node's support in the trie to `ledger`.
+    ///
+    /// # Returns
+    /// Whether the trie changed, which it doesn't if `ledger` is already the node's last ledger.
     fn _update_trie(
         trie: &mut T,
         last_ledger: &mut HashMap<A::NodeIdType, A::LedgerType>,
         node_id: &A::NodeIdType,
         ledger: A::LedgerType,
-    ) {
+    ) -> bool {
         let ledger_copy = ledger.clone();
         match last_ledger.entry(*node_id) {
+            Entry::Occupied(e) if e.get().id() == ledger.id() => return false,
             Entry::Occupied(mut e) => {
-                trie.remove(e.get(), None);
+                Self::_remove_support(trie, e.get());
                 e.insert(ledger_copy);
             }
             Entry::Vacant(e) => {
@@ -515,6 +657,7 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         }
 
         trie.insert(&ledger, None);
+        true
     }
 
     async fn _process_validation(
@@ -537,6 +680,13 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
 
         self.check_acquired().await;
 
+        // A node re-sending the ledger it already supports leaves the trie alone, without
+        // acquiring the ledger again. `last_ledger` holds the ledger each node last moved to in
+        // the trie, so it serves as the cache of recent updates.
+        if matches!(self.last_ledger.get(node_id), Some(ledger) if ledger.id() == validation.ledger_id()) {
+            return;
+        }
+
         match self.acquiring.entry((validation.seq(), validation.ledger_id())) {
             Entry::Occupied(mut e) => {
                 e.get_mut().insert(*node_id);
@@ -548,7 +698,9 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
                             .insert(*node_id);
                     }
                     Some(ledger) => {
-                        Self::_update_trie(&mut self.trie, &mut self.last_ledger, node_id, ledger);
+                        if Self::_update_trie(&mut self.trie, &mut self.last_ledger, node_id, ledger) {
+                            self.trie_updates += 1;
+                        }
                     }
                 }
             }
@@ -599,9 +751,12 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         sign_time: &SystemTime,
         seen_time: &SystemTime,
     ) -> bool {
+        // A validation signed too far ahead of when it was seen has a suspect sign time, even
+        // if it is within the wall clock window of now
         return (sign_time > &(*now - p.validation_current_early())) &&
             (sign_time < &(*now + p.validation_current_wall())) &&
-            ((seen_time == &UNIX_EPOCH) || (seen_time < &(*now + p.validation_current_local())));
+            ((seen_time == &UNIX_EPOCH) || (seen_time < &(*now + p.validation_current_local()))) &&
+            ((seen_time == &UNIX_EPOCH) || (sign_time <= &(*seen_time + p.validation_current_early())));
     }
 
     fn _by_ledger<Pre, PreR, F>(
@@ -631,7 +786,9 @@ impl<A: Adaptor, T: LedgerTrie<A::LedgerType>, C: NetClock> Validations<A, T, C>
         for ((seq, id), node_ids) in &self.acquiring {
             if let Some(ledger) = self.adaptor.acquire(&id).await {
                 for node_id in node_ids {
-                    Self::_update_trie(trie, last_ledger, node_id, ledger.clone())
+                    if Self::_update_trie(trie, last_ledger, node_id, ledger.clone()) {
+                        self.trie_updates += 1;
+                    }
                 }
 
                 to_remove.push((*seq, *id));
@@ -660,7 +817,7 @@ pub enum ValidationError<T> {
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, HashSet};
-    use std::sync::{Arc, RwLock};
+    use std::sync::{Arc, Mutex, RwLock};
     use std::time::{Duration, SystemTime};
 
     use async_trait::async_trait;
@@ -669,9 +826,10 @@ mod tests {
 
     use crate::adaptor::Adaptor;
     use crate::arena_ledger_trie::ArenaLedgerTrie;
+    use crate::ledger_trie::LedgerTrie;
     use crate::test_utils::ledgers::{LedgerHistoryHelper, LedgerId, LedgerOracle, SimulatedLedger};
     use crate::test_utils::ManualClock;
-    use crate::test_utils::validation::{PeerId, PeerKey, TestValidation};
+    use crate::test_utils::validation::{peer_id, PeerId, PeerKey, TestValidation};
     use crate::validation_params::ValidationParams;
     use crate::validations::{ValidationError, Validations};
 
@@ -712,10 +870,10 @@ mod tests {
         harness.advance_time(Duration::from_secs(1));
 
         // Cannot re-do the same full validation sequence
-        assert_eq!(harness.try_add(&node.validate_ledger(&ab)).await, Err(ValidationError::Conflicting));
+        assert!(matches!(harness.try_add(&node.validate_ledger(&ab)).await, Err(ValidationError::ConflictingSignTime(_))));
 
         // Cannot send the same partial validation sequence
-        assert_eq!(harness.try_add(&node.partial(&ab)).await, Err(ValidationError::Conflicting));
+        assert!(matches!(harness.try_add(&node.partial(&ab)).await, Err(ValidationError::ConflictingSignTime(_))));
 
         // Now trusts the newest ledger too
         harness.advance_time(Duration::from_secs(1));
@@ -734,6 +892,99 @@ mod tests {
         assert_eq!(harness.try_add(&val_abcde).await, Err(ValidationError::Stale));
     }
 
+    #[tokio::test]
+    async fn test_add_status() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let node = harness.make_node();
+
+        // Current
+        let val = node.validate_ledger(&a);
+        assert_eq!(harness.try_add(&val).await, Ok(()));
+
+        // The same validation again does not advance the sequence
+        assert_eq!(harness.try_add(&val).await, Err(ValidationError::BadSeq));
+
+        // Multiple, the same validation signed again with a different cookie
+        let cookie = val.cookie() + 1;
+        let resigned = TestValidation::new(
+            a.id(), a.seq(), val.sign_time(), val.seen_time(), node.curr_key(), node.node_id(), true, true, None, Some(cookie),
+        );
+        assert_eq!(harness.try_add(&resigned).await, Err(ValidationError::Multiple));
+
+        // Stale, signed too far in the future of the injected clock
+        let future = harness.params().validation_current_wall() + Duration::from_secs(1);
+        let early = node.validate_full(&ab, DurationOffset::Plus(future), DurationOffset::Zero);
+        assert_eq!(harness.try_add(&early).await, Err(ValidationError::Stale));
+
+        // Once the clock catches up it is still signed too far ahead of when it was seen, but
+        // the same sign time seen now is current
+        harness.advance_time(future);
+        assert_eq!(harness.try_add(&early).await, Err(ValidationError::Stale));
+        let seen_now = node.validate_full(&ab, DurationOffset::Zero, DurationOffset::Zero);
+        assert_eq!(seen_now.sign_time(), early.sign_time());
+        assert_eq!(harness.try_add(&seen_now).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_current_trusted_excludes_early_signed() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let on_time = harness.make_node();
+        let almost_early = harness.make_node();
+        let early = harness.make_node();
+
+        // Signed ahead of when they were seen, by up to validation_current_early and past it
+        let ahead = harness.params().validation_current_early();
+        let limit = DurationOffset::Plus(ahead);
+        let past_limit = DurationOffset::Plus(ahead + Duration::from_secs(1));
+        assert_eq!(harness.try_add(&on_time.validate_ledger(&a)).await, Ok(()));
+        assert_eq!(harness.try_add(&almost_early.validate_full(&a, limit, DurationOffset::Zero)).await, Ok(()));
+        assert_eq!(
+            harness.try_add(&early.validate_full(&a, past_limit, DurationOffset::Zero)).await,
+            Err(ValidationError::Stale)
+        );
+
+        // Both sign times are well within the wall clock window, but only the one signed no
+        // more than validation_current_early after it was seen is current
+        assert!(ahead + Duration::from_secs(1) < harness.params().validation_current_wall());
+        let mut current: Vec<PeerId> = harness.validations.current_trusted().iter()
+            .map(|val| val.node_id())
+            .collect();
+        current.sort();
+        let mut expected = vec![on_time.node_id(), almost_early.node_id()];
+        expected.sort();
+        assert_eq!(current, expected);
+    }
+
+    #[tokio::test]
+    async fn test_seq_expires_with_clock() {
+        let mut h = LedgerHistoryHelper::new();
+        let abc = h.get_or_create("abc");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let node = harness.make_node();
+
+        assert_eq!(harness.try_add(&node.validate_ledger(&abc)).await, Ok(()));
+        harness.advance_time(Duration::from_secs(1));
+        assert_eq!(harness.try_add(&node.validate_ledger(&ab)).await, Err(ValidationError::BadSeq));
+
+        // Just before the largest sequence expires, a smaller one is still rejected
+        let expires = harness.params().validation_set_expires();
+        harness.advance_time(expires - Duration::from_secs(1));
+        assert_eq!(harness.try_add(&node.validate_ledger(&ab)).await, Err(ValidationError::BadSeq));
+
+        // Once it expires, a smaller sequence is accepted
+        harness.advance_time(Duration::from_secs(1));
+        assert_eq!(harness.try_add(&node.validate_ledger(&ab)).await, Ok(()));
+    }
+
     #[tokio::test]
     async fn test_add_validations_out_of_order_with_shifted_times() {
         let mut h = LedgerHistoryHelper::new();
@@ -839,12 +1090,12 @@ mod tests {
 
         // If we advance far enough for AB to expire, we can fully
         // validate or partially validate that sequence number again
-        assert_eq!(process(do_full, &node, &mut harness, &az).await, Err(ValidationError::Conflicting));
+        assert_eq!(process(do_full, &node, &mut harness, &az).await, Err(ValidationError::ConflictingLedgerId));
         harness.advance_time(harness.params().validation_set_expires() + Duration::from_millis(1));
         assert_eq!(process(do_full, &node, &mut harness, &az).await, Ok(()));
     }
 
-    async fn process(do_full: bool, node: &TestNode, harness: &mut TestHarness<'_>, ledger: &SimulatedLedger) -> Result<(), ValidationError> {
+    async fn process(do_full: bool, node: &TestNode, harness: &mut TestHarness<'_>, ledger: &SimulatedLedger) -> Result<(), ValidationError<TestValidation>> {
         if do_full {
             harness.try_add(&node.validate_ledger(ledger)).await
         } else {
@@ -939,6 +1190,82 @@ mod tests {
         assert_eq!(harness.validations.get_nodes_after(&ad, ab.id()), 2);
     }
 
+    #[tokio::test]
+    async fn test_quorum_reached() {
+        let mut h = LedgerHistoryHelper::new();
+        let ab = h.get_or_create("ab");
+        let ac = h.get_or_create("ac");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let b_node = harness.make_node();
+        let c_node = harness.make_node();
+        let mut d_node = harness.make_node();
+        d_node.untrust();
+
+        assert!(harness.validations.quorum_reached(1, 0).is_none());
+
+        assert_eq!(harness.try_add(&a_node.validate_ledger(&ab)).await, Ok(()));
+        assert_eq!(harness.try_add(&b_node.validate_ledger(&ab)).await, Ok(()));
+        assert_eq!(harness.try_add(&c_node.validate_ledger(&ac)).await, Ok(()));
+        assert_eq!(harness.try_add(&d_node.validate_ledger(&ab)).await, Ok(()));
+
+        // Two trusted validators support the preferred ledger, the untrusted one doesn't count
+        let quorum = 2;
+        assert_eq!(harness.validations.quorum_reached(quorum - 1, 0).map(|tip| tip.id()), Some(ab.id()));
+        assert_eq!(harness.validations.quorum_reached(quorum, 0).map(|tip| tip.id()), Some(ab.id()));
+        assert!(harness.validations.quorum_reached(quorum + 1, 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trie_updates() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let node = harness.make_node();
+
+        // An exact resend is rejected by the sequence enforcer before it reaches the trie
+        let validation = node.validate_ledger(&a);
+        assert_eq!(harness.try_add(&validation).await, Ok(()));
+        assert_eq!(harness.try_add(&validation).await, Err(ValidationError::BadSeq));
+        assert_eq!(harness.validations.trie_updates(), 1);
+        assert_eq!(harness.validations.adaptor().acquires, 1);
+
+        // Once the sequence enforcer forgets the node, a new validation for the same ledger is
+        // accepted, but the trie is only touched once and the ledger is not acquired again
+        for _ in 0..3 {
+            harness.advance_time(harness.params().validation_set_expires() + Duration::from_secs(1));
+            assert_eq!(harness.try_add(&node.validate_ledger(&a)).await, Ok(()));
+        }
+        assert_eq!(harness.validations.trie_updates(), 1);
+        assert_eq!(harness.validations.adaptor().acquires, 1);
+        assert_eq!(harness.validations.trie.tip_support(&a), 1);
+
+        harness.advance_time(Duration::from_secs(1));
+        assert_eq!(harness.try_add(&node.validate_ledger(&ab)).await, Ok(()));
+        assert_eq!(harness.validations.trie_updates(), 2);
+
+        // Moving a node to the ledger it already supports leaves the trie alone
+        let (trie, last_ledger) = (&mut harness.validations.trie, &mut harness.validations.last_ledger);
+        assert!(!TestValidations::_update_trie(trie, last_ledger, &node.node_id(), (*ab).clone()));
+        assert_eq!(trie.tip_support(&ab), 1);
+        assert!(TestValidations::_update_trie(trie, last_ledger, &node.node_id(), (*a).clone()));
+        assert_eq!(trie.tip_support(&ab), 0);
+        assert_eq!(trie.tip_support(&a), 1);
+
+        // If the trie lost the node's support, moving the node still leaves it supported once
+        assert_eq!(trie.remove(&a, None), Ok(true));
+        assert!(TestValidations::_update_trie(trie, last_ledger, &node.node_id(), (*ab).clone()));
+        assert_eq!(trie.tip_support(&a), 0);
+        assert_eq!(trie.tip_support(&ab), 1);
+        assert_eq!(trie.remove(&ab, None), Ok(true));
+        TestValidations::_remove_trie(trie, &mut HashMap::new(), last_ledger, &node.node_id(), &node.validate_ledger(&ab));
+        assert!(last_ledger.is_empty());
+        assert!(trie.empty());
+    }
+
     #[tokio::test]
     async fn test_current_trusted() {
         let mut h = LedgerHistoryHelper::new();
@@ -1069,7 +1396,7 @@ mod tests {
 
         // d now thinks ledger 1, but cannot re-issue a previously used seq
         // and attempting it should generate a conflict.
-        assert_eq!(harness.try_add(&d_node.partial(&a)).await, Err(ValidationError::Conflicting));
+        assert_eq!(harness.try_add(&d_node.partial(&a)).await, Err(ValidationError::ConflictingLedgerId));
 
         // e only issues partials
         assert_eq!(harness.try_add(&e_node.partial(&ac)).await, Ok(()));
@@ -1098,14 +1425,235 @@ mod tests {
         })
     }
 
+    #[tokio::test]
+    async fn test_get_trusted_for_ledger_filters_seq() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let mut b_node = harness.make_node();
+        b_node.untrust();
+
+        let val = a_node.validate_ledger(&a);
+        assert_eq!(harness.try_add(&val).await, Ok(()));
+        assert_eq!(harness.try_add(&b_node.validate_ledger(&a)).await, Ok(()));
+
+        // Only the trusted validation matching both the ID and the sequence number
+        assert_eq!(harness.validations.get_trusted_for_ledger(&a.id(), &a.seq()), vec![val]);
+        assert!(harness.validations.get_trusted_for_ledger(&a.id(), &ab.seq()).is_empty());
+        assert!(harness.validations.get_trusted_for_ledger(&ab.id(), &a.seq()).is_empty());
+        assert!(harness.validations.get_trusted_for_ledger(&ab.id(), &ab.seq()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fees_default() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let b = h.get_or_create("b");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let mut a_node = harness.make_node();
+        let b_node = harness.make_node();
+        let mut c_node = harness.make_node();
+        a_node.set_load_fee(12);
+        c_node.set_load_fee(40);
+        c_node.untrust();
+
+        for node in [&a_node, &b_node, &c_node] {
+            assert_eq!(harness.try_add(&node.validate_ledger(&a)).await, Ok(()));
+        }
+
+        // b votes the default fee, and untrusted c doesn't vote
+        let mut fees = harness.validations.fees(&a.id(), 10);
+        fees.sort();
+        assert_eq!(fees, vec![10, 12]);
+        assert!(harness.validations.fees(&b.id(), 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_validation_fields() {
+        let mut h = LedgerHistoryHelper::new();
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let mut node = harness.make_node();
+        node.set_load_fee(7);
+
+        let val = node.validate(ab.id(), ab.seq(), DurationOffset::Minus(Duration::from_secs(1)), DurationOffset::Zero, true);
+        assert_eq!(val.ledger_id(), ab.id());
+        assert_eq!(val.seq(), ab.seq());
+        assert_eq!(val.sign_time(), node.now() - Duration::from_secs(1));
+        assert_eq!(val.seen_time(), node.now());
+        assert_eq!(val.node_id(), node.node_id());
+        assert_eq!(val.load_fee(), Some(7));
+        assert!(val.full() && val.trusted());
+        assert!(!node.partial(&ab).full());
+
+        // A validation is added under the ID of the node that signed it
+        assert_eq!(harness.validations.try_add(&val.node_id(), &val).await, Ok(()));
+        assert_eq!(harness.validations.get_current_node_ids(), HashSet::from([node.node_id()]));
+        assert_eq!(harness.validations.get_trusted_for_ledger(&ab.id(), &ab.seq()), vec![val]);
+    }
+
     #[test]
     fn test_expire() {
         // TODO: Implement this if we ever implement expire()
     }
 
-    #[test]
-    fn test_flush() {
-        // TODO: Implement this if we ever implement flush()
+    #[tokio::test]
+    async fn test_gc() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let b_node = harness.make_node();
+
+        assert_eq!(harness.try_add(&a_node.validate_ledger(&a)).await, Ok(()));
+        harness.advance_time(harness.params().validation_set_expires() - harness.params().validation_current_local());
+        assert_eq!(harness.try_add(&b_node.validate_ledger(&ab)).await, Ok(()));
+
+        // b_node's validation is no longer current, but its set has not expired
+        harness.advance_time(harness.params().validation_current_local() + Duration::from_secs(1));
+        let now = harness.clock.read().unwrap().now();
+        harness.validations.gc(now);
+
+        let vals = &harness.validations;
+        assert!(!vals.current.contains_key(&a_node.node_id()));
+        assert!(!vals.seq_enforcers.contains_key(&a_node.node_id()));
+        assert!(!vals.last_ledger.contains_key(&a_node.node_id()));
+        assert!(vals.by_ledger.get(&a.id()).is_none());
+        assert!(vals.by_sequence.get(&a.seq()).is_none());
+
+        assert!(vals.current.contains_key(&b_node.node_id()));
+        assert!(vals.seq_enforcers.contains_key(&b_node.node_id()));
+        assert!(vals.by_ledger.get(&ab.id()).unwrap().contains_key(&b_node.node_id()));
+        assert!(vals.by_sequence.get(&ab.seq()).unwrap().contains_key(&b_node.node_id()));
+        assert_eq!(vals.trie.tip_support(&a), 0);
+        assert_eq!(vals.trie.tip_support(&ab), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_callback() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let b_node = harness.make_node();
+
+        let stale = Arc::new(Mutex::new(vec![]));
+        let stale_copy = stale.clone();
+        harness.validations.set_stale_callback(move |v| stale_copy.lock().unwrap().push(v));
+
+        let val_a = a_node.validate_ledger(&a);
+        assert_eq!(harness.try_add(&val_a).await, Ok(()));
+        harness.advance_time(Duration::from_secs(1));
+        let val_ab = a_node.validate_ledger(&ab);
+        assert_eq!(harness.try_add(&val_ab).await, Ok(()));
+        harness.advance_time(harness.params().validation_set_expires() - harness.params().validation_current_local());
+        assert_eq!(harness.try_add(&b_node.validate_ledger(&abc)).await, Ok(()));
+
+        // Nothing has expired yet
+        let now = harness.clock.read().unwrap().now();
+        harness.validations.gc(now);
+        assert!(stale.lock().unwrap().is_empty());
+
+        harness.advance_time(harness.params().validation_current_local() + Duration::from_secs(1));
+        let now = harness.clock.read().unwrap().now();
+        harness.validations.gc(now);
+
+        let mut stale = stale.lock().unwrap().clone();
+        stale.sort_by_key(|v| v.seq());
+        assert_eq!(stale, vec![val_a, val_ab]);
+    }
+
+    #[tokio::test]
+    async fn test_laggards() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+        let abc = h.get_or_create("abc");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let b_node = harness.make_node();
+        let c_node = harness.make_node();
+        let d_node = harness.make_node();
+        let e_node = harness.make_node();
+
+        // d_node's validation is still current, but no longer fresh
+        assert_eq!(harness.try_add(&d_node.validate_ledger(&abc)).await, Ok(()));
+        harness.advance_time(harness.params().validation_freshness() + Duration::from_secs(1));
+
+        // a_node is caught up, b_node and c_node are lagging and e_node never validated
+        assert_eq!(harness.try_add(&a_node.validate_ledger(&abc)).await, Ok(()));
+        assert_eq!(harness.try_add(&b_node.validate_ledger(&ab)).await, Ok(()));
+        assert_eq!(harness.try_add(&c_node.validate_ledger(&a)).await, Ok(()));
+
+        // Only the caught up proposer is removed, leaving the laggards and offline proposers
+        let mut trusted_keys = HashSet::from([
+            a_node.node_id(),
+            b_node.node_id(),
+            c_node.node_id(),
+            d_node.node_id(),
+            e_node.node_id(),
+        ]);
+        assert_eq!(harness.validations.laggards(abc.seq(), &mut trusted_keys), 2);
+        assert_eq!(
+            trusted_keys,
+            HashSet::from([b_node.node_id(), c_node.node_id(), d_node.node_id(), e_node.node_id()])
+        );
+
+        // With every trusted proposer online, exactly the laggards are left
+        let mut trusted_keys = HashSet::from([a_node.node_id(), b_node.node_id(), c_node.node_id()]);
+        assert_eq!(harness.validations.laggards(abc.seq(), &mut trusted_keys), 2);
+        assert_eq!(trusted_keys, HashSet::from([b_node.node_id(), c_node.node_id()]));
+
+        // Only trusted proposers are counted
+        let mut trusted_keys = HashSet::from([a_node.node_id(), b_node.node_id()]);
+        assert_eq!(harness.validations.laggards(abc.seq(), &mut trusted_keys), 1);
+        assert_eq!(trusted_keys, HashSet::from([b_node.node_id()]));
+
+        // Nobody is behind the earliest sequence
+        let mut trusted_keys = HashSet::from([a_node.node_id(), b_node.node_id(), c_node.node_id()]);
+        assert_eq!(harness.validations.laggards(a.seq(), &mut trusted_keys), 0);
+        assert!(trusted_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush() {
+        let mut h = LedgerHistoryHelper::new();
+        let a = h.get_or_create("a");
+        let ab = h.get_or_create("ab");
+
+        let mut harness = TestHarness::new(h.oracle_mut());
+        let a_node = harness.make_node();
+        let b_node = harness.make_node();
+        let mut c_node = harness.make_node();
+        c_node.untrust();
+
+        let mut expected = vec![];
+        for (node, ledger) in [(&a_node, &a), (&b_node, &ab), (&c_node, &ab)] {
+            let val = node.validate_ledger(ledger);
+            assert_eq!(harness.try_add(&val).await, Ok(()));
+            expected.push((node.node_id(), val));
+        }
+        assert!(!harness.validations.trie.empty());
+
+        let mut flushed = harness.validations.flush();
+        flushed.sort();
+        expected.sort();
+        assert_eq!(flushed, expected);
+        assert!(harness.validations.trie.empty());
+        assert!(harness.validations.current_trusted().is_empty());
+        assert!(harness.validations.get_preferred(&a).is_none());
+        assert!(harness.validations.flush().is_empty());
     }
 
     #[tokio::test]
@@ -1351,6 +1899,8 @@ mod tests {
     struct TestAdaptor<'a> {
         oracle: &'a mut LedgerOracle,
         clock: Arc<RwLock<ManualClock>>,
+        /// The number of calls to `acquire`.
+        acquires: usize,
     }
 
     impl<'a> TestAdaptor<'a> {
@@ -1358,6 +1908,7 @@ mod tests {
             TestAdaptor {
                 oracle,
                 clock,
+                acquires: 0,
             }
         }
     }
@@ -1376,6 +1927,7 @@ mod tests {
         }
 
         async fn acquire(&mut self, ledger_id: &Self::LedgerIdType) -> Option<Self::LedgerType> {
+            self.acquires += 1;
             self.oracle.lookup(ledger_id)
         }
     }
@@ -1385,7 +1937,7 @@ mod tests {
     struct TestHarness<'a> {
         params: ValidationParams,
         pub validations: TestValidations<'a>,
-        next_node_id: PeerId,
+        next_node_id: u32,
         clock: Arc<RwLock<ManualClock>>,
     }
 
@@ -1399,18 +1951,18 @@ mod tests {
                     TestAdaptor::new(oracle, clock.clone()),
                     clock.clone(),
                 ),
-                next_node_id: PeerId(0),
+                next_node_id: 0,
                 clock,
             }
         }
 
-        pub async fn try_add(&mut self, v: &TestValidation) -> Result<(), ValidationError> {
+        pub async fn try_add(&mut self, v: &TestValidation) -> Result<(), ValidationError<TestValidation>> {
             self.validations.try_add(&v.node_id(), v).await
         }
 
         pub fn make_node(&mut self) -> TestNode {
             self.next_node_id += 1;
-            TestNode::new(self.next_node_id, self.clock.clone())
+            TestNode::new(peer_id(self.next_node_id), self.clock.clone())
         }
 
         pub fn params(&self) -> &ValidationParams {